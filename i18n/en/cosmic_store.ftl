@@ -9,3 +9,235 @@ theme = Theme
 match-desktop = Match desktop
 dark = Dark
 light = Light
+
+### Sources
+preferred-sources = Preferred sources
+sources = {$backend} sources
+
+# Backends
+backends = Backends
+backend-flatpak-user = Flatpak (user)
+backend-flatpak-system = Flatpak (system)
+backend-packagekit = System packages
+backend-snap = Snap
+backend-fwupd = Firmware
+backend-appimage = AppImage
+
+# Categories
+categories = Categories
+category-all = All
+category-audio-video = Audio & Video
+category-development = Developer Tools
+category-education = Education
+category-game = Games
+category-graphics = Graphics & Photography
+category-network = Internet
+category-office = Productivity
+category-science = Science
+category-settings = Settings
+category-system = System
+category-utility = Utilities
+
+# Additional categories
+subcategory-audio = Audio
+subcategory-video = Video
+subcategory-midi = MIDI
+subcategory-mixer = Mixer
+subcategory-sequencer = Sequencer
+subcategory-tuner = Tuner
+subcategory-tv = TV
+subcategory-audio-video-editing = Audio Video Editing
+subcategory-player = Player
+subcategory-recorder = Recorder
+subcategory-disc-burning = Disc Burning
+subcategory-building = Building
+subcategory-debugger = Debugger
+subcategory-ide = IDE
+subcategory-gui-designer = GUI Designer
+subcategory-profiling = Profiling
+subcategory-revision-control = Revision Control
+subcategory-translation = Translation
+subcategory-web-development = Web Development
+subcategory-art = Art
+subcategory-artificial-intelligence = Artificial Intelligence
+subcategory-astronomy = Astronomy
+subcategory-biology = Biology
+subcategory-chemistry = Chemistry
+subcategory-computer-science = Computer Science
+subcategory-construction = Construction
+subcategory-data-visualization = Data Visualization
+subcategory-economy = Economy
+subcategory-electricity = Electricity
+subcategory-geography = Geography
+subcategory-geology = Geology
+subcategory-geoscience = Geoscience
+subcategory-history = History
+subcategory-humanities = Humanities
+subcategory-image-processing = Image Processing
+subcategory-languages = Languages
+subcategory-literature = Literature
+subcategory-maps = Maps
+subcategory-math = Math
+subcategory-medical-software = Medical Software
+subcategory-music = Music
+subcategory-numerical-analysis = Numerical Analysis
+subcategory-parallel-computing = Parallel Computing
+subcategory-physics = Physics
+subcategory-robotics = Robotics
+subcategory-spirituality = Spirituality
+subcategory-sports = Sports
+subcategory-action-game = Action Game
+subcategory-adventure-game = Adventure Game
+subcategory-arcade-game = Arcade Game
+subcategory-board-game = Board Game
+subcategory-blocks-game = Blocks Game
+subcategory-card-game = Card Game
+subcategory-kids-game = Kids Game
+subcategory-logic-game = Logic Game
+subcategory-role-playing = Role Playing
+subcategory-shooter = Shooter
+subcategory-simulation = Simulation
+subcategory-sports-game = Sports Game
+subcategory-strategy-game = Strategy Game
+subcategory-emulator = Emulator
+subcategory-2d-graphics = 2D Graphics
+subcategory-vector-graphics = Vector Graphics
+subcategory-raster-graphics = Raster Graphics
+subcategory-3d-graphics = 3D Graphics
+subcategory-scanning = Scanning
+subcategory-ocr = OCR
+subcategory-photography = Photography
+subcategory-publishing = Publishing
+subcategory-viewer = Viewer
+subcategory-chat = Chat
+subcategory-email = Email
+subcategory-feed = Feed
+subcategory-file-transfer = File Transfer
+subcategory-ham-radio = Ham Radio
+subcategory-instant-messaging = Instant Messaging
+subcategory-irc-client = IRC Client
+subcategory-news = News
+subcategory-p2p = P2P
+subcategory-remote-access = Remote Access
+subcategory-telephony = Telephony
+subcategory-video-conference = Video Conference
+subcategory-web-browser = Web Browser
+subcategory-calendar = Calendar
+subcategory-contact-management = Contact Management
+subcategory-database = Database
+subcategory-dictionary = Dictionary
+subcategory-chart = Chart
+subcategory-finance = Finance
+subcategory-flow-chart = Flow Chart
+subcategory-project-management = Project Management
+subcategory-presentation = Presentation
+subcategory-spreadsheet = Spreadsheet
+subcategory-word-processor = Word Processor
+subcategory-desktop-settings = Desktop Settings
+subcategory-hardware-settings = Hardware Settings
+subcategory-printing = Printing
+subcategory-package-manager = Package Manager
+subcategory-security = Security
+subcategory-accessibility = Accessibility
+subcategory-file-manager = File Manager
+subcategory-file-tools = File Tools
+subcategory-filesystem = Filesystem
+subcategory-monitor = Monitor
+subcategory-terminal-emulator = Terminal Emulator
+subcategory-archiving = Archiving
+subcategory-calculator = Calculator
+subcategory-clock = Clock
+subcategory-compression = Compression
+subcategory-text-editor = Text Editor
+subcategory-text-tools = Text Tools
+
+sort-name = Name
+sort-recent = Recently updated
+loading = Loading...
+
+# Operations
+install = Install
+integrate = Add to applications
+rebase = Switch to {$id}
+rebase-label = Switch to replacement
+uninstall = Uninstall
+update = Update
+installing = Installing ({$progress}%)...
+integrating = Adding to applications ({$progress}%)...
+rebasing = Switching ({$progress}%)...
+uninstalling = Uninstalling ({$progress}%)...
+updating = Updating ({$progress}%)...
+updates-available = {$count} updates available
+updates-held = {$count} held back from updates
+
+# Offline updates
+updates = Updates
+offline-updates = Install system updates when restarting
+offline-update = Download and install on restart
+downloading-updates = Downloading updates ({$progress}%)...
+restart-to-install = Restart to install updates
+restart = Restart
+restart-now = Restart now
+cancel = Cancel
+dismiss = Dismiss
+offline-update-succeeded = The last update installed {$count} packages
+offline-update-failed = The last update failed: {$error}
+
+# Branches
+branches = Branches
+branch = {$branch} ({$arch}) from {$remote}
+
+# Holds
+hold-updates = Hold updates
+hold-app = Keep the current version
+hold-runtime = Keep runtime {$runtime}
+held-at = Held at {$version}
+runtime-held = Runtime {$runtime} held
+hold-failed = Failed to change hold: {$error}
+
+# End of life
+end-of-life = No longer supported
+end-of-life-reason = No longer supported: {$reason}
+end-of-life-rebase = Replaced by {$id}
+runtime-end-of-life = Runtime no longer supported: {$reason}
+
+# Screenshots
+screenshot-position = {$position} of {$count}
+play-video = Play video
+
+# Releases
+releases = Releases
+release-version = Version {$version}
+release-version-date = Version {$version} released {$date}
+release-newer = Not installed yet
+release-urgency-high = Important update
+release-urgency-critical = Critical update
+release-development = Development release
+release-history-show = Show release history
+release-history-hide = Hide release history
+
+# Licenses
+license-free = Free software
+license-proprietary = Proprietary
+license-unknown = Unknown license
+metadata-license = Metadata license: {$license}
+browsing = Browsing
+hide-proprietary = Hide proprietary software
+
+# Project links
+url-homepage = Website
+url-help = Help
+url-faq = FAQ
+url-bugtracker = Report an issue
+url-contact = Contact
+url-vcs-browser = Source code
+url-contribute = Contribute
+url-translate = Translate
+url-donation = Donate
+
+# History
+back = Back
+history = History
+filter-history = Filter history
+history-succeeded = Succeeded
+history-failed = Failed: {$error}
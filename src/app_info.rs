@@ -1,8 +1,10 @@
 use appstream::{
-    enums::{Icon, Launchable},
+    enums::{ComponentKind, Icon, Launchable},
     Component,
 };
 
+use crate::{Description, LicenseKind, ProjectUrl, Release, Screenshot};
+
 fn get_translatable<'a>(translatable: &'a appstream::TranslatableString, locale: &str) -> &'a str {
     match translatable.get_for_locale(locale) {
         Some(some) => some.as_str(),
@@ -13,7 +15,6 @@ fn get_translatable<'a>(translatable: &'a appstream::TranslatableString, locale:
     }
 }
 
-/*TODO: handle p tags with xml:lang
 fn get_markup_translatable<'a>(
     translatable: &'a appstream::MarkupTranslatableString,
     locale: &str,
@@ -26,23 +27,47 @@ fn get_markup_translatable<'a>(
         },
     }
 }
-*/
 
 // Replaced Icon due to skip_field not supported in bitcode
 #[derive(Debug, bitcode::Decode, bitcode::Encode)]
 pub enum AppIcon {
     Cached(String, Option<u32>, Option<u32>, Option<u32>),
+    Local(String),
     Stock(String),
 }
 
-#[derive(Debug, bitcode::Decode, bitcode::Encode)]
+/// Kinds of components that are loaded from catalogs
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, bitcode::Decode, bitcode::Encode)]
+pub enum AppKind {
+    #[default]
+    DesktopApplication,
+    /// Extends another component, like a plugin. Only shown when searching for addons
+    Addon,
+}
+
+#[derive(Debug, Default, bitcode::Decode, bitcode::Encode)]
 pub struct AppInfo {
+    pub kind: AppKind,
     pub origin_opt: Option<String>,
     pub name: String,
     pub summary: String,
     pub pkgname: Option<String>,
     pub icons: Vec<AppIcon>,
     pub desktop_ids: Vec<String>,
+    pub mimetypes: Vec<String>,
+    pub description: Description,
+    pub screenshots: Vec<Screenshot>,
+    pub releases: Vec<Release>,
+    pub categories: Vec<String>,
+    /// Search keywords, untranslated and for the locale
+    pub keywords: Vec<String>,
+    pub urls: Vec<ProjectUrl>,
+    /// SPDX license expression of the app
+    pub project_license_opt: Option<String>,
+    /// Whether the app is free software, classified from its project license when parsed
+    pub license_kind: LicenseKind,
+    /// SPDX license expression of the appstream metadata
+    pub metadata_license_opt: Option<String>,
 }
 
 impl AppInfo {
@@ -52,14 +77,13 @@ impl AppInfo {
             .summary
             .as_ref()
             .map_or("", |x| get_translatable(x, locale));
-        /*TODO: MarkupTranslatableString doesn't properly filter p tag with xml:lang
-        if let Some(description) = &component.description {
-            column = column.push(widget::text(get_markup_translatable(
-                description,
-                &self.locale,
-            )));
-        }
-        */
+        // XML catalogs clear this and parse the description element, see AppstreamCache::parse_xml
+        let description = component
+            .description
+            .as_ref()
+            .map_or_else(Description::default, |x| {
+                Description::from_markup(get_markup_translatable(x, locale))
+            });
         let icons = component
             .icons
             .into_iter()
@@ -75,6 +99,7 @@ impl AppInfo {
                     height,
                     scale,
                 )),
+                Icon::Local { path, .. } => Some(AppIcon::Local(path.to_str()?.to_string())),
                 Icon::Stock(path) => Some(AppIcon::Stock(path)),
                 _ => None,
             })
@@ -88,12 +113,36 @@ impl AppInfo {
             })
             .collect();
         Self {
+            kind: match component.kind {
+                ComponentKind::Addon => AppKind::Addon,
+                _ => AppKind::DesktopApplication,
+            },
             origin_opt: origin_opt.map(|x| x.to_string()),
             name: name.to_string(),
             summary: summary.to_string(),
             pkgname: component.pkgname,
             icons,
             desktop_ids,
+            // Media types from provides are added by AppstreamCache
+            mimetypes: component.mimetypes,
+            description,
+            // These are parsed from the catalog by AppstreamCache
+            screenshots: Vec::new(),
+            releases: Vec::new(),
+            categories: Vec::new(),
+            keywords: Vec::new(),
+            urls: Vec::new(),
+            project_license_opt: None,
+            license_kind: LicenseKind::Unknown,
+            metadata_license_opt: None,
         }
     }
+
+    /// Set the project license and classify it, so searches do not parse it again
+    pub fn set_project_license(&mut self, project_license_opt: Option<String>) {
+        self.license_kind = project_license_opt
+            .as_deref()
+            .map_or(LicenseKind::Unknown, LicenseKind::from_expression);
+        self.project_license_opt = project_license_opt;
+    }
 }
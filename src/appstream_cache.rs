@@ -17,7 +17,11 @@ use std::{
     time::{Instant, SystemTime},
 };
 
-use crate::{AppIcon, AppInfo};
+use crate::{
+    category::camel_case_words,
+    description::{child_elements, element_lang, element_text, lang_score, normalize_locale},
+    project_url, release, screenshot, AppIcon, AppInfo, AppKind, Description,
+};
 
 const PREFIXES: &'static [&'static str] = &["/usr/share", "/var/lib", "/var/cache"];
 const CATALOGS: &'static [&'static str] = &["swcatalog", "app-info"];
@@ -49,7 +53,152 @@ pub struct AppstreamCache {
     pub icons_paths: Vec<PathBuf>,
     pub locale: String,
     pub infos: HashMap<String, Arc<AppInfo>>,
+    /// Addons are kept apart from apps, so they are only found by searching for them
+    pub addons: HashMap<String, Arc<AppInfo>>,
     pub pkgnames: HashMap<String, HashSet<String>>,
+    /// IDs of apps in each category
+    pub categories: HashMap<String, HashSet<String>>,
+    /// IDs of apps with each lowercase word of their names, summaries, keywords, categories,
+    /// and IDs. Uses btreemap so words can be found by prefix
+    pub tokens: BTreeMap<String, HashSet<String>>,
+}
+
+/// Lowercase words of a search phrase
+fn search_words(input: &str) -> Vec<String> {
+    input
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|x| !x.is_empty())
+        .map(|x| x.to_lowercase())
+        .collect()
+}
+
+/// Add the lowercase words of a text to the search tokens, also splitting words written in
+/// CamelCase like LibreOffice so that both libreoffice and office are found
+fn search_tokens(text: &str, tokens: &mut HashSet<String>) {
+    for word in text.split(|c: char| !c.is_alphanumeric()) {
+        if word.is_empty() {
+            continue;
+        }
+        let words = camel_case_words(word);
+        if words.len() > 1 {
+            for part in words {
+                tokens.insert(part.to_lowercase());
+            }
+        }
+        tokens.insert(word.to_lowercase());
+    }
+}
+
+/// Keywords of a component element that are untranslated or match the locale
+fn xml_keywords(component: &xmltree::Element, locale: &str) -> Vec<String> {
+    let locale = normalize_locale(locale);
+    let mut keywords = Vec::new();
+    for keywords_e in child_elements(component, "keywords") {
+        // Older catalogs translate the whole keywords element
+        if lang_score(element_lang(keywords_e), &locale).is_none() {
+            continue;
+        }
+        for keyword_e in child_elements(keywords_e, "keyword") {
+            if lang_score(element_lang(keyword_e), &locale).is_none() {
+                continue;
+            }
+            let mut keyword = String::new();
+            element_text(keyword_e, &mut keyword);
+            let keyword = keyword.trim();
+            if !keyword.is_empty() && !keywords.iter().any(|x| x == keyword) {
+                keywords.push(keyword.to_string());
+            }
+        }
+    }
+    keywords
+}
+
+/// Keywords of a YAML component that are untranslated or match the locale
+fn yaml_keywords(value: &serde_yaml::Value, locale: &str) -> Vec<String> {
+    let locale = normalize_locale(locale);
+    let mut keywords = Vec::new();
+    for (lang, lang_keywords) in value.as_mapping().into_iter().flatten() {
+        if lang_score(lang.as_str(), &locale).is_none() {
+            continue;
+        }
+        for keyword in lang_keywords.as_sequence().into_iter().flatten() {
+            if let Some(keyword) = keyword.as_str().map(|x| x.trim()) {
+                if !keyword.is_empty() && !keywords.iter().any(|x| x == keyword) {
+                    keywords.push(keyword.to_string());
+                }
+            }
+        }
+    }
+    keywords
+}
+
+/// Trimmed text of the first child element with a name, if not empty
+fn xml_child_text(component: &xmltree::Element, name: &str) -> Option<String> {
+    let e = child_elements(component, name).next()?;
+    let mut text = String::new();
+    element_text(e, &mut text);
+    let text = text.trim();
+    if text.is_empty() {
+        None
+    } else {
+        Some(text.to_string())
+    }
+}
+
+/// Categories of a component element
+fn xml_categories(component: &xmltree::Element) -> Vec<String> {
+    let mut categories = Vec::new();
+    for categories_e in child_elements(component, "categories") {
+        for category_e in child_elements(categories_e, "category") {
+            let mut category = String::new();
+            element_text(category_e, &mut category);
+            let category = category.trim();
+            if !category.is_empty() {
+                categories.push(category.to_string());
+            }
+        }
+    }
+    categories
+}
+
+/// Media types in the provides of a component element, newer data lists them there instead
+/// of in mimetypes
+fn xml_mediatypes(component: &xmltree::Element) -> Vec<String> {
+    let mut mediatypes = Vec::new();
+    for provides_e in child_elements(component, "provides") {
+        for mediatype_e in child_elements(provides_e, "mediatype") {
+            let mut mediatype = String::new();
+            element_text(mediatype_e, &mut mediatype);
+            let mediatype = mediatype.trim();
+            if !mediatype.is_empty() {
+                mediatypes.push(mediatype.to_string());
+            }
+        }
+    }
+    mediatypes
+}
+
+/// Info of a component element, with the fields the appstream crate does not parse
+fn component_info(
+    e: &xmltree::Element,
+    mut component: Component,
+    origin_opt: Option<&str>,
+    media: &screenshot::MediaLocation,
+    locale: &str,
+) -> AppInfo {
+    // The appstream crate does not filter paragraphs by xml:lang, so parse it here
+    component.description = None;
+    let mut info = AppInfo::new(origin_opt, component, locale);
+    info.description = Description::from_component(e, locale);
+    info.screenshots = screenshot::from_component(e, locale, media);
+    info.releases = release::from_component(e, locale);
+    info.categories = xml_categories(e);
+    info.keywords = xml_keywords(e, locale);
+    info.mimetypes.extend(xml_mediatypes(e));
+    info.urls = project_url::from_component(e);
+    info.set_project_license(xml_child_text(e, "project_license"));
+    info.metadata_license_opt = xml_child_text(e, "metadata_license");
+    info
 }
 
 impl AppstreamCache {
@@ -169,7 +318,7 @@ impl AppstreamCache {
 
     /// Versioned filename of cache
     fn cache_filename() -> &'static str {
-        "appstream_cache-v0-1.bitcode-v0-5"
+        "appstream_cache-v0-15.bitcode-v0-5"
     }
 
     /// Remove all files from cache not matching filename
@@ -283,9 +432,12 @@ impl AppstreamCache {
             return false;
         }
 
-        // Everything matches, copy infos and pkgnames
+        // Everything matches, copy infos and indexes
         self.infos = cache.infos;
+        self.addons = cache.addons;
         self.pkgnames = cache.pkgnames;
+        self.categories = cache.categories;
+        self.tokens = cache.tokens;
 
         let duration = start.elapsed();
         log::info!("loaded cache {:?} in {:?}", cache_name, duration);
@@ -334,7 +486,10 @@ impl AppstreamCache {
     /// Reload from original package sources
     pub fn load_original(&mut self) {
         self.infos.clear();
+        self.addons.clear();
         self.pkgnames.clear();
+        self.categories.clear();
+        self.tokens.clear();
 
         let path_results: Vec<_> = self
             .path_tags
@@ -406,12 +561,17 @@ impl AppstreamCache {
 
         for infos in path_results {
             for (id, info) in infos {
+                if info.kind == AppKind::Addon {
+                    self.addons.insert(id, info);
+                    continue;
+                }
                 if let Some(pkgname) = &info.pkgname {
                     self.pkgnames
                         .entry(pkgname.clone())
                         .or_insert_with(|| HashSet::new())
                         .insert(id.clone());
                 }
+                self.index_info(&id, &info);
                 match self.infos.insert(id.clone(), info) {
                     Some(_old) => {
                         //TODO: merge based on priority
@@ -423,6 +583,65 @@ impl AppstreamCache {
         }
     }
 
+    /// Add an app to the category and search indexes, for apps that are added after loading
+    pub fn index_info(&mut self, id: &str, info: &AppInfo) {
+        for category in info.categories.iter() {
+            self.categories
+                .entry(category.clone())
+                .or_insert_with(|| HashSet::new())
+                .insert(id.to_string());
+        }
+
+        let mut tokens = HashSet::new();
+        search_tokens(&info.name, &mut tokens);
+        search_tokens(&info.summary, &mut tokens);
+        for text in info
+            .keywords
+            .iter()
+            .chain(info.categories.iter())
+            .chain(info.desktop_ids.iter())
+            .chain(info.pkgname.iter())
+        {
+            search_tokens(text, &mut tokens);
+        }
+        search_tokens(id, &mut tokens);
+        for token in tokens {
+            self.tokens
+                .entry(token)
+                .or_insert_with(|| HashSet::new())
+                .insert(id.to_string());
+        }
+    }
+
+    /// IDs of apps with a token starting with every word of a search phrase, or None if the
+    /// phrase has no words
+    pub fn search_index(&self, input: &str) -> Option<HashSet<&str>> {
+        let mut ids_opt: Option<HashSet<&str>> = None;
+        for word in search_words(input) {
+            let mut word_ids = HashSet::new();
+            for (_token, ids) in self
+                .tokens
+                .range(word.clone()..)
+                .take_while(|(token, _ids)| token.starts_with(&word))
+            {
+                match &ids_opt {
+                    // Only keep IDs that matched previous words
+                    Some(previous) => word_ids.extend(
+                        ids.iter()
+                            .map(|id| id.as_str())
+                            .filter(|id| previous.contains(id)),
+                    ),
+                    None => word_ids.extend(ids.iter().map(|id| id.as_str())),
+                }
+            }
+            if word_ids.is_empty() {
+                return Some(word_ids);
+            }
+            ids_opt = Some(word_ids);
+        }
+        ids_opt
+    }
+
     /// Either load from cache or load from originals. Cache is cleaned before loading and saved after.
     pub fn reload(&mut self, cache_name: &str) {
         self.clean_cache(cache_name);
@@ -480,6 +699,13 @@ impl AppstreamCache {
                         cached_size = size;
                     }
                 }
+                AppIcon::Local(path) => {
+                    if cached_size != 0 {
+                        // Skip if a cached icon was found
+                        continue;
+                    }
+                    icon_opt = Some(widget::icon::from_path(PathBuf::from(path)));
+                }
                 AppIcon::Stock(stock) => {
                     if cached_size != 0 {
                         // Skip if a cached icon was found
@@ -504,12 +730,27 @@ impl AppstreamCache {
         let start = Instant::now();
         //TODO: just running this and not saving the results makes a huge memory leak!
         let e = xmltree::Element::parse(reader)?;
+        if &*e.name == "component" {
+            // Metainfo files contain a single component with no collection
+            let component = Component::try_from(&e)?;
+            let id = component.id.to_string();
+            let media = screenshot::MediaLocation::new(path, None, None);
+            let info = component_info(&e, component, None, &media, locale);
+            let duration = start.elapsed();
+            log::info!("loaded metainfo {} from {:?} in {:?}", id, path, duration);
+            return Ok(vec![(id, Arc::new(info))]);
+        }
         let _version = e
             .attributes
             .get("version")
             .ok_or_else(|| ParseError::missing_attribute("version", "collection"))?;
         let origin_opt = e.attributes.get("origin");
         let _arch_opt = e.attributes.get("architecture");
+        let media = screenshot::MediaLocation::new(
+            path,
+            origin_opt.map(|x| x.as_str()),
+            e.attributes.get("media_baseurl").map(|x| x.as_str()),
+        );
         let infos: Vec<_> = e
             .children
             .par_iter()
@@ -518,21 +759,24 @@ impl AppstreamCache {
                     if &*e.name == "component" {
                         match Component::try_from(e) {
                             Ok(component) => {
-                                if component.kind != ComponentKind::DesktopApplication {
-                                    // Skip anything that is not a desktop application
+                                if !matches!(
+                                    component.kind,
+                                    ComponentKind::DesktopApplication | ComponentKind::Addon
+                                ) {
+                                    // Skip anything that is not a desktop application or addon
                                     //TODO: should we allow more components?
                                     return None;
                                 }
 
                                 let id = component.id.to_string();
-                                return Some((
-                                    id,
-                                    Arc::new(AppInfo::new(
-                                        origin_opt.map(|x| x.as_str()),
-                                        component,
-                                        locale,
-                                    )),
-                                ));
+                                let info = component_info(
+                                    e,
+                                    component,
+                                    origin_opt.map(|x| x.as_str()),
+                                    &media,
+                                    locale,
+                                );
+                                return Some((id, Arc::new(info)));
                             }
                             Err(err) => {
                                 log::error!(
@@ -566,6 +810,7 @@ impl AppstreamCache {
     ) -> Result<Vec<(String, Arc<AppInfo>)>, Box<dyn Error>> {
         let start = Instant::now();
         let mut origin_opt = None;
+        let mut media = screenshot::MediaLocation::default();
         let mut infos = Vec::new();
         //TODO: par_iter?
         for (doc_i, doc) in serde_yaml::Deserializer::from_reader(reader).enumerate() {
@@ -578,11 +823,19 @@ impl AppstreamCache {
             };
             if doc_i == 0 {
                 origin_opt = value["Origin"].as_str().map(|x| x.to_string());
+                media = screenshot::MediaLocation::new(
+                    path,
+                    origin_opt.as_deref(),
+                    value["MediaBaseUrl"].as_str(),
+                );
             } else {
                 match Component::deserialize(&value) {
                     Ok(mut component) => {
-                        if component.kind != ComponentKind::DesktopApplication {
-                            // Skip anything that is not a desktop application
+                        if !matches!(
+                            component.kind,
+                            ComponentKind::DesktopApplication | ComponentKind::Addon
+                        ) {
+                            // Skip anything that is not a desktop application or addon
                             //TODO: should we allow more components?
                             continue;
                         }
@@ -712,10 +965,36 @@ impl AppstreamCache {
                         }
 
                         let id = component.id.to_string();
-                        infos.push((
-                            id,
-                            Arc::new(AppInfo::new(origin_opt.as_deref(), component, locale)),
-                        ));
+                        let mut info = AppInfo::new(origin_opt.as_deref(), component, locale);
+                        info.screenshots =
+                            screenshot::from_yaml(&value["Screenshots"], locale, &media);
+                        info.releases = release::from_yaml(&value["Releases"], locale);
+                        info.categories = value["Categories"]
+                            .as_sequence()
+                            .into_iter()
+                            .flatten()
+                            .filter_map(|x| Some(x.as_str()?.to_string()))
+                            .collect();
+                        info.keywords = yaml_keywords(&value["Keywords"], locale);
+                        info.mimetypes.extend(
+                            value["Provides"]["mediatypes"]
+                                .as_sequence()
+                                .into_iter()
+                                .flatten()
+                                .filter_map(|x| Some(x.as_str()?.to_string())),
+                        );
+                        info.urls = project_url::from_yaml(&value["Url"]);
+                        info.set_project_license(
+                            value["ProjectLicense"]
+                                .as_str()
+                                .map(|x| x.trim().to_string())
+                                .filter(|x| !x.is_empty()),
+                        );
+                        info.metadata_license_opt = value["MetadataLicense"]
+                            .as_str()
+                            .map(|x| x.trim().to_string())
+                            .filter(|x| !x.is_empty());
+                        infos.push((id, Arc::new(info)));
                     }
                     Err(err) => {
                         log::error!("failed to parse {:?} in {:?}: {}", value["ID"], path, err);
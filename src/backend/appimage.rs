@@ -0,0 +1,625 @@
+use cosmic::widget;
+use std::{
+    collections::HashMap,
+    error::Error,
+    fs,
+    io::Read,
+    os::unix::fs::PermissionsExt,
+    path::{Path, PathBuf},
+    process,
+    sync::Arc,
+};
+
+use super::{Backend, Capabilities, Package};
+use crate::{AppIcon, AppInfo, AppstreamCache, Operation, OperationKind};
+
+// https://github.com/AppImage/AppImageSpec/blob/master/draft.md#type-2-image-format
+const TYPE_2_MAGIC: [u8; 3] = [0x41, 0x49, 0x02];
+
+/// Prefix of desktop entries and icons installed by integration
+const INTEGRATION_PREFIX: &str = "appimagekit_cosmic-store";
+
+fn is_type_2(path: &Path) -> bool {
+    let mut header = [0; 11];
+    match fs::File::open(path).and_then(|mut file| file.read_exact(&mut header)) {
+        Ok(()) => header[8..11] == TYPE_2_MAGIC,
+        Err(_) => false,
+    }
+}
+
+/// Offset of the squashfs image after the ELF runtime, which ends with its section headers
+fn squashfs_offset(path: &Path) -> Result<u64, Box<dyn Error>> {
+    let mut header = [0; 64];
+    fs::File::open(path)?.read_exact(&mut header)?;
+    if header[..4] != [0x7F, b'E', b'L', b'F'] {
+        return Err(format!("{:?} is not an ELF file", path).into());
+    }
+    let little_endian = match header[5] {
+        1 => true,
+        2 => false,
+        data => return Err(format!("{:?} has unknown ELF data encoding {}", path, data).into()),
+    };
+    let read = |range: std::ops::Range<usize>| -> u64 {
+        let bytes = &header[range];
+        let mut value = 0;
+        for i in 0..bytes.len() {
+            let byte = if little_endian {
+                bytes[bytes.len() - 1 - i]
+            } else {
+                bytes[i]
+            };
+            value = (value << 8) | byte as u64;
+        }
+        value
+    };
+    let (shoff, shentsize, shnum) = match header[4] {
+        1 => (read(0x20..0x24), read(0x2E..0x30), read(0x30..0x32)),
+        2 => (read(0x28..0x30), read(0x3A..0x3C), read(0x3C..0x3E)),
+        class => return Err(format!("{:?} has unknown ELF class {}", path, class).into()),
+    };
+    Ok(shoff + shentsize * shnum)
+}
+
+/// Extract files matching patterns from the squashfs image of an AppImage, without running it
+fn unsquashfs(
+    path: &Path,
+    offset: u64,
+    root: &Path,
+    patterns: &[&str],
+) -> Result<(), Box<dyn Error>> {
+    let status = process::Command::new("unsquashfs")
+        .arg("-offset")
+        .arg(offset.to_string())
+        .arg("-dest")
+        .arg(root)
+        .arg("-force")
+        .arg("-no-progress")
+        .arg(path)
+        .args(patterns)
+        .stdout(process::Stdio::null())
+        .status()?;
+    if !status.success() {
+        return Err(format!(
+            "failed to extract {:?} from {:?}: {}",
+            patterns, path, status
+        )
+        .into());
+    }
+    Ok(())
+}
+
+/// Quote an argument of a desktop entry Exec key, escaping it for both the quoting and the
+/// string value rules of the desktop entry specification
+fn exec_quote(arg: &str) -> String {
+    let mut quoted = String::with_capacity(arg.len() + 2);
+    quoted.push('"');
+    for c in arg.chars() {
+        match c {
+            // Escaped by the quoting rule, then the backslash is escaped by the string rule
+            '"' | '`' | '$' => {
+                quoted.push_str("\\\\");
+                quoted.push(c);
+            }
+            '\\' => quoted.push_str("\\\\\\\\"),
+            // Percent signs start field codes unless doubled
+            '%' => quoted.push_str("%%"),
+            _ => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Arguments after the executable of a desktop entry Exec key, which may be quoted
+fn exec_args(exec: &str) -> Option<&str> {
+    let exec = exec.trim_start();
+    let end = if exec.starts_with('"') {
+        // Backslashes are escaped by the string rule before the quoting rule, so a quote is
+        // escaped by two backslashes, or one in entries that skip the string rule
+        let mut backslashes = 0;
+        let mut end_opt = None;
+        for (i, c) in exec.char_indices().skip(1) {
+            match c {
+                '\\' => backslashes += 1,
+                '"' if ((backslashes + 1) / 2) % 2 == 0 => {
+                    end_opt = Some(i + 1);
+                    break;
+                }
+                _ => backslashes = 0,
+            }
+        }
+        end_opt?
+    } else {
+        exec.find(' ').unwrap_or(exec.len())
+    };
+    let args = exec[end..].trim();
+    if args.is_empty() {
+        None
+    } else {
+        Some(args)
+    }
+}
+
+fn modified(path: &Path) -> Option<std::time::SystemTime> {
+    fs::metadata(path).and_then(|x| x.modified()).ok()
+}
+
+#[derive(Debug)]
+struct AppImage {
+    path: PathBuf,
+    /// Name of the AppImage file without extension
+    stem: String,
+    /// Extracted squashfs-root with the desktop entry, icon, and metainfo
+    root: PathBuf,
+    desktop_path: PathBuf,
+    icon_path_opt: Option<PathBuf>,
+    /// Appstream component ID, or desktop entry name if there is no metainfo
+    id: String,
+}
+
+impl AppImage {
+    /// Extract the files needed to list an AppImage, reusing a previous extraction if up to date
+    fn extract(path: PathBuf, extract_dir: &Path) -> Result<Self, Box<dyn Error>> {
+        if !is_type_2(&path) {
+            return Err(format!("{:?} is not a type 2 AppImage", path).into());
+        }
+
+        let stem = path
+            .file_stem()
+            .and_then(|x| x.to_str())
+            .ok_or_else(|| format!("{:?} has no file name", path))?
+            .to_string();
+        let dir = extract_dir.join(&stem);
+        let root = dir.join("squashfs-root");
+        let outdated = match (modified(&path), modified(&root)) {
+            (Some(appimage_time), Some(root_time)) => appimage_time > root_time,
+            _ => true,
+        };
+        let offset_opt = if outdated {
+            if dir.exists() {
+                fs::remove_dir_all(&dir)?;
+            }
+            fs::create_dir_all(&dir)?;
+
+            // The squashfs image is read directly, so the AppImage is never made executable or
+            // run before the user integrates it
+            let offset = squashfs_offset(&path)?;
+            unsquashfs(
+                &path,
+                offset,
+                &root,
+                &["*.desktop", ".DirIcon", "usr/share/metainfo/*"],
+            )?;
+            Some(offset)
+        } else {
+            None
+        };
+
+        let desktop_path = fs::read_dir(&root)?
+            .filter_map(|entry_res| entry_res.ok().map(|entry| entry.path()))
+            .find(|x| x.extension().map_or(false, |ext| ext == "desktop"))
+            .ok_or_else(|| format!("no desktop entry in {:?}", path))?;
+        let entry = freedesktop_entry_parser::parse_entry(&desktop_path)?;
+
+        // The icon named in the desktop entry lives at the root, .DirIcon may link to it
+        let mut icon_path_opt = None;
+        if let Some(icon) = entry.section("Desktop Entry").attr("Icon") {
+            for ext in ["png", "svg"] {
+                let file_name = format!("{}.{}", icon, ext);
+                let icon_path = root.join(&file_name);
+                if let Some(offset) = offset_opt.filter(|_| !icon_path.exists()) {
+                    if let Err(err) = unsquashfs(&path, offset, &root, &[&file_name]) {
+                        log::debug!("{}", err);
+                    }
+                }
+                if icon_path.is_file() {
+                    icon_path_opt = Some(icon_path);
+                    break;
+                }
+            }
+        }
+        if icon_path_opt.is_none() {
+            let dir_icon = root.join(".DirIcon");
+            if dir_icon.is_file() {
+                icon_path_opt = Some(dir_icon);
+            }
+        }
+
+        let id = desktop_path
+            .file_stem()
+            .and_then(|x| x.to_str())
+            .unwrap_or(&stem)
+            .to_string();
+
+        Ok(Self {
+            path,
+            stem,
+            root,
+            desktop_path,
+            icon_path_opt,
+            id,
+        })
+    }
+
+    fn metainfo_paths(&self) -> Vec<PathBuf> {
+        let metainfo_dir = self.root.join("usr/share/metainfo");
+        match fs::read_dir(&metainfo_dir) {
+            Ok(read_dir) => read_dir
+                .filter_map(|entry_res| entry_res.ok().map(|entry| entry.path()))
+                .filter(|x| x.extension().map_or(false, |ext| ext == "xml"))
+                .collect(),
+            Err(_) => Vec::new(),
+        }
+    }
+
+    /// Path of the desktop entry installed by integration
+    fn integrated_desktop_path(&self) -> Option<PathBuf> {
+        dirs::data_dir().map(|x| {
+            x.join("applications")
+                .join(format!("{}-{}.desktop", INTEGRATION_PREFIX, self.stem))
+        })
+    }
+
+    /// Path of the icon installed by integration
+    fn integrated_icon_path(&self) -> Option<PathBuf> {
+        let icon_path = self.icon_path_opt.as_ref()?;
+        let ext = icon_path
+            .extension()
+            .and_then(|x| x.to_str())
+            .unwrap_or("png");
+        dirs::data_dir().map(|x| {
+            x.join("icons")
+                .join(format!("{}-{}.{}", INTEGRATION_PREFIX, self.stem, ext))
+        })
+    }
+
+    fn is_integrated(&self) -> bool {
+        self.integrated_desktop_path()
+            .map_or(false, |x| x.is_file())
+    }
+
+    /// Install the desktop entry and icon into the user's XDG data directories
+    fn integrate(&self) -> Result<(), Box<dyn Error>> {
+        let desktop_path = self
+            .integrated_desktop_path()
+            .ok_or("failed to find data directory")?;
+        let icon_name = format!("{}-{}", INTEGRATION_PREFIX, self.stem);
+
+        if let (Some(src), Some(dst)) = (&self.icon_path_opt, self.integrated_icon_path()) {
+            if let Some(parent) = dst.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::copy(src, &dst)?;
+        }
+
+        // Integrated AppImages are launched directly, so they must be executable
+        let mut permissions = fs::metadata(&self.path)?.permissions();
+        if permissions.mode() & 0o111 == 0 {
+            permissions.set_mode(permissions.mode() | 0o755);
+            fs::set_permissions(&self.path, permissions)?;
+        }
+
+        // Point Exec and Icon at the AppImage and the integrated icon
+        let exec = exec_quote(&self.path.to_string_lossy());
+        let mut desktop = String::new();
+        for line in fs::read_to_string(&self.desktop_path)?.lines() {
+            if let Some(value) = line.strip_prefix("Exec=") {
+                // Keep field codes and arguments after the original executable
+                match exec_args(value) {
+                    Some(args) => desktop.push_str(&format!("Exec={} {}", exec, args)),
+                    None => desktop.push_str(&format!("Exec={}", exec)),
+                }
+            } else if line.starts_with("TryExec=") {
+                continue;
+            } else if line.starts_with("Icon=") {
+                desktop.push_str(&format!("Icon={}", icon_name));
+            } else {
+                desktop.push_str(line);
+            }
+            desktop.push('\n');
+        }
+        if let Some(parent) = desktop_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&desktop_path, desktop)?;
+        Ok(())
+    }
+
+    /// Delete the AppImage, its integration, and its extracted files
+    fn remove(&self) -> Result<(), Box<dyn Error>> {
+        for path in [self.integrated_desktop_path(), self.integrated_icon_path()]
+            .into_iter()
+            .flatten()
+        {
+            if path.is_file() {
+                fs::remove_file(&path)?;
+            }
+        }
+        if let Some(dir) = self.root.parent() {
+            fs::remove_dir_all(dir)?;
+        }
+        fs::remove_file(&self.path)?;
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub struct Appimage {
+    dir: PathBuf,
+    appimages: Vec<AppImage>,
+    appstream_cache: AppstreamCache,
+}
+
+impl Appimage {
+    pub fn new(locale: &str, dir_opt: Option<&Path>) -> Result<Self, Box<dyn Error>> {
+        let dir = match dir_opt {
+            Some(some) => some.to_path_buf(),
+            None => dirs::home_dir()
+                .ok_or("failed to find home directory")?
+                .join("Applications"),
+        };
+        let extract_dir = dirs::cache_dir()
+            .ok_or("failed to find cache directory")?
+            .join("cosmic-store")
+            .join("appimage-extract");
+
+        let mut appimages = Vec::new();
+        if dir.is_dir() {
+            for entry_res in fs::read_dir(&dir)? {
+                let path = match entry_res {
+                    Ok(entry) => entry.path(),
+                    Err(err) => {
+                        log::warn!("failed to read entry in directory {:?}: {}", dir, err);
+                        continue;
+                    }
+                };
+                if !path
+                    .extension()
+                    .map_or(false, |ext| ext.eq_ignore_ascii_case("appimage"))
+                {
+                    continue;
+                }
+                match AppImage::extract(path.clone(), &extract_dir) {
+                    Ok(appimage) => appimages.push(appimage),
+                    Err(err) => {
+                        log::warn!("failed to load AppImage {:?}: {}", path, err);
+                    }
+                }
+            }
+        } else {
+            log::info!("AppImage directory {:?} does not exist", dir);
+        }
+
+        let paths = appimages.iter().flat_map(|x| x.metainfo_paths()).collect();
+        Ok(Self {
+            dir,
+            appimages,
+            appstream_cache: AppstreamCache::new(paths, Vec::new(), locale),
+        })
+    }
+
+    fn appimage(&self, id: &str) -> Result<&AppImage, Box<dyn Error>> {
+        self.appimages
+            .iter()
+            .find(|x| x.id == id)
+            .ok_or_else(|| format!("failed to find AppImage {}", id).into())
+    }
+}
+
+impl Backend for Appimage {
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            uninstall: true,
+            integrate: true,
+            local_files: false,
+            permissions: false,
+            ..Default::default()
+        }
+    }
+
+    fn load_cache(&mut self) -> Result<(), Box<dyn Error>> {
+        self.appstream_cache.reload("appimage");
+
+        // Match AppImages to their metainfo, or build info from their desktop entry
+        for appimage in self.appimages.iter_mut() {
+            let desktop_id = format!("{}.desktop", appimage.id);
+            let id_opt = self
+                .appstream_cache
+                .infos
+                .iter()
+                .find(|(id, info)| **id == appimage.id || info.desktop_ids.contains(&desktop_id))
+                .map(|(id, _)| id.clone());
+            match id_opt {
+                Some(id) => appimage.id = id,
+                None => {
+                    let entry = freedesktop_entry_parser::parse_entry(&appimage.desktop_path)?;
+                    let section = entry.section("Desktop Entry");
+                    let info = AppInfo {
+                        origin_opt: Some("appimage".to_string()),
+                        name: section.attr("Name").unwrap_or(&appimage.stem).to_string(),
+                        summary: section.attr("Comment").unwrap_or_default().to_string(),
+                        icons: appimage
+                            .icon_path_opt
+                            .iter()
+                            .filter_map(|x| Some(AppIcon::Local(x.to_str()?.to_string())))
+                            .collect(),
+                        desktop_ids: vec![desktop_id],
+                        categories: section
+                            .attr("Categories")
+                            .unwrap_or_default()
+                            .split(';')
+                            .filter(|x| !x.is_empty())
+                            .map(|x| x.to_string())
+                            .collect(),
+                        keywords: section
+                            .attr("Keywords")
+                            .unwrap_or_default()
+                            .split(';')
+                            .filter(|x| !x.is_empty())
+                            .map(|x| x.to_string())
+                            .collect(),
+                        ..Default::default()
+                    };
+                    self.appstream_cache.index_info(&appimage.id, &info);
+                    self.appstream_cache
+                        .infos
+                        .insert(appimage.id.clone(), Arc::new(info));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn installed(&self) -> Result<Vec<Package>, Box<dyn Error>> {
+        let mut packages = Vec::new();
+        for appimage in self.appimages.iter() {
+            let info = match self.appstream_cache.infos.get(&appimage.id) {
+                Some(some) => some,
+                None => {
+                    log::warn!("failed to find info {}", appimage.id);
+                    continue;
+                }
+            };
+            let mut extra = HashMap::new();
+            extra.insert(
+                "path".to_string(),
+                appimage.path.to_string_lossy().to_string(),
+            );
+            extra.insert(
+                "integrated".to_string(),
+                appimage.is_integrated().to_string(),
+            );
+            let entry_version = freedesktop_entry_parser::parse_entry(&appimage.desktop_path)
+                .ok()
+                .and_then(|entry| {
+                    entry
+                        .section("Desktop Entry")
+                        .attr("X-AppImage-Version")
+                        .map(|x| x.to_string())
+                });
+            packages.push(Package {
+                id: appimage.id.clone(),
+                icon: match &appimage.icon_path_opt {
+                    Some(icon_path) => widget::icon::from_path(icon_path.clone()),
+                    None => self.appstream_cache.icon(info),
+                },
+                name: info.name.clone(),
+                summary: info.summary.clone(),
+                origin_opt: Some("appimage".to_string()),
+                version: entry_version.unwrap_or_default(),
+                extra,
+            });
+        }
+        Ok(packages)
+    }
+
+    fn updates(&self) -> Result<Vec<Package>, Box<dyn Error>> {
+        Ok(Vec::new())
+    }
+
+    fn sources(&self) -> Result<Vec<String>, Box<dyn Error>> {
+        Ok(Vec::new())
+    }
+
+    fn operation(
+        &self,
+        op: &Operation,
+        mut f: Box<dyn FnMut(f32) + 'static>,
+    ) -> Result<(), Box<dyn Error>> {
+        let appimage = self.appimage(&op.package_id)?;
+        match op.kind {
+            OperationKind::Integrate => appimage.integrate()?,
+            OperationKind::Uninstall => appimage.remove()?,
+            _ => {
+                return Err(format!("{:?} is not supported for AppImages", op.kind).into());
+            }
+        }
+        f(100.0);
+        Ok(())
+    }
+
+    fn info_cache(&self) -> &AppstreamCache {
+        &self.appstream_cache
+    }
+
+    fn watch_paths(&self) -> Vec<PathBuf> {
+        // Added and removed AppImages change the directory
+        if self.dir.is_dir() {
+            vec![self.dir.clone()]
+        } else {
+            Vec::new()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quote_exec() {
+        assert_eq!(
+            exec_quote("/home/user/Apps/My App.AppImage"),
+            "\"/home/user/Apps/My App.AppImage\""
+        );
+        assert_eq!(
+            exec_quote("/apps/100%.AppImage"),
+            "\"/apps/100%%.AppImage\""
+        );
+        assert_eq!(
+            exec_quote("/apps/$HOME \"quoted\".AppImage"),
+            r#""/apps/\\$HOME \\"quoted\\".AppImage""#
+        );
+        assert_eq!(exec_quote(r"/apps/back\slash"), r#""/apps/back\\\\slash""#);
+    }
+
+    #[test]
+    fn args_after_exec() {
+        assert_eq!(exec_args("app"), None);
+        assert_eq!(exec_args("app %U"), Some("%U"));
+        assert_eq!(exec_args("AppRun --no-sandbox %F"), Some("--no-sandbox %F"));
+        assert_eq!(exec_args("\"my app\" %U"), Some("%U"));
+        assert_eq!(exec_args(r#""my \\"app\\"" %f"#), Some("%f"));
+        assert_eq!(exec_args(r#""my \"app\"" %f"#), Some("%f"));
+        assert_eq!(exec_args(r#""dir\\\\" %f"#), Some("%f"));
+        assert_eq!(exec_args("\"my app\""), None);
+        assert_eq!(exec_args("\"unterminated %U"), None);
+    }
+
+    #[test]
+    fn offset_after_section_headers() {
+        let path = std::env::temp_dir().join(format!(
+            "cosmic-store-appimage-{}.AppImage",
+            std::process::id()
+        ));
+
+        // 64-bit little endian with 30 section headers of 64 bytes at 0x1000
+        let mut header = [0; 64];
+        header[..4].copy_from_slice(&[0x7F, b'E', b'L', b'F']);
+        header[4] = 2;
+        header[5] = 1;
+        header[8..11].copy_from_slice(&TYPE_2_MAGIC);
+        header[0x28..0x30].copy_from_slice(&0x1000u64.to_le_bytes());
+        header[0x3A..0x3C].copy_from_slice(&64u16.to_le_bytes());
+        header[0x3C..0x3E].copy_from_slice(&30u16.to_le_bytes());
+        fs::write(&path, header).unwrap();
+        assert!(is_type_2(&path));
+        assert_eq!(squashfs_offset(&path).unwrap(), 0x1000 + 64 * 30);
+
+        // 32-bit big endian with 20 section headers of 40 bytes at 0x800
+        let mut header = [0; 64];
+        header[..4].copy_from_slice(&[0x7F, b'E', b'L', b'F']);
+        header[4] = 1;
+        header[5] = 2;
+        header[0x20..0x24].copy_from_slice(&0x800u32.to_be_bytes());
+        header[0x2E..0x30].copy_from_slice(&40u16.to_be_bytes());
+        header[0x30..0x32].copy_from_slice(&20u16.to_be_bytes());
+        fs::write(&path, header).unwrap();
+        assert!(!is_type_2(&path));
+        assert_eq!(squashfs_offset(&path).unwrap(), 0x800 + 40 * 20);
+
+        fs::write(&path, [0; 64]).unwrap();
+        assert!(squashfs_offset(&path).is_err());
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -1,23 +1,227 @@
 use cosmic::widget;
-use libflatpak::{gio::Cancellable, prelude::*, Installation, RefKind};
-use std::{collections::HashMap, error::Error};
+use libflatpak::{gio::Cancellable, prelude::*, Installation, InstalledRef, RefKind, Transaction};
+use std::{
+    cell::{Cell, RefCell},
+    cmp,
+    collections::HashMap,
+    error::Error,
+    path::{Path, PathBuf},
+    rc::Rc,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
+};
 
-use super::{Backend, Package};
-use crate::AppstreamCache;
+use super::{Backend, Branch, Capabilities, Package};
+use crate::{AppIcon, AppInfo, AppstreamCache, Operation, OperationKind};
+
+/// Appstream data older than this is downloaded again
+const APPSTREAM_MAX_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Find the metainfo file shipped in the deploy directory of an app
+fn deploy_metainfo(deploy_dir: &Path, id: &str) -> Option<PathBuf> {
+    let share_dir = deploy_dir.join("files").join("share");
+    [
+        share_dir
+            .join("metainfo")
+            .join(format!("{}.metainfo.xml", id)),
+        share_dir
+            .join("metainfo")
+            .join(format!("{}.appdata.xml", id)),
+        share_dir
+            .join("appdata")
+            .join(format!("{}.appdata.xml", id)),
+    ]
+    .into_iter()
+    .find(|path| path.is_file())
+}
+
+/// Find the largest icon exported from the deploy directory of an app
+fn deploy_icon(deploy_dir: &Path, id: &str) -> Option<PathBuf> {
+    let hicolor_dir = deploy_dir
+        .join("export")
+        .join("share")
+        .join("icons")
+        .join("hicolor");
+    let mut paths = vec![hicolor_dir
+        .join("scalable")
+        .join("apps")
+        .join(format!("{}.svg", id))];
+    for size in [512, 256, 128, 64, 48] {
+        paths.push(
+            hicolor_dir
+                .join(format!("{}x{}", size, size))
+                .join("apps")
+                .join(format!("{}.png", id)),
+        );
+    }
+    paths.into_iter().find(|path| path.is_file())
+}
+
+/// The replacement id of an end-of-life rebase, which may be given as an id or a full ref
+fn rebase_id(eol_rebase: &str) -> &str {
+    match eol_rebase.split('/').nth(1) {
+        Some(id) => id,
+        None => eol_rebase,
+    }
+}
+
+/// The runtime used by an app, as name/arch/branch
+fn app_runtime(r: &InstalledRef) -> Option<String> {
+    let metadata = match r.load_metadata(Cancellable::NONE) {
+        Ok(ok) => ok,
+        Err(err) => {
+            log::warn!("failed to load metadata of {:?}: {}", r.name(), err);
+            return None;
+        }
+    };
+    std::str::from_utf8(&metadata)
+        .ok()?
+        .lines()
+        .skip_while(|line| line.trim() != "[Application]")
+        .find_map(|line| line.strip_prefix("runtime="))
+        .map(|x| x.trim().to_string())
+}
+
+/// The end-of-life reason of a runtime given as name/arch/branch
+fn runtime_eol(inst: &Installation, runtime: &str) -> Option<String> {
+    let mut parts = runtime.split('/');
+    let (name, arch, branch) = (parts.next()?, parts.next()?, parts.next()?);
+    let runtime_ref = inst
+        .installed_ref(
+            RefKind::Runtime,
+            name,
+            Some(arch),
+            Some(branch),
+            Cancellable::NONE,
+        )
+        .ok()?;
+    runtime_ref.eol().map(|x| x.to_string())
+}
+
+/// Installed ref of an app on a branch, or on any branch if none is given.
+///
+/// A missing branch would otherwise be treated as master, which most remotes do not use.
+fn installed_app_ref(
+    inst: &Installation,
+    id: &str,
+    branch_opt: Option<&Branch>,
+) -> Result<InstalledRef, Box<dyn Error>> {
+    if let Some(branch) = branch_opt {
+        return Ok(inst.installed_ref(
+            RefKind::App,
+            id,
+            Some(&branch.arch),
+            Some(&branch.branch),
+            Cancellable::NONE,
+        )?);
+    }
+    // Prefer the branch that is run by default when several are installed
+    if let Ok(r) = inst.current_installed_app(id, Cancellable::NONE) {
+        return Ok(r);
+    }
+    inst.list_installed_refs_by_kind(RefKind::App, Cancellable::NONE)?
+        .into_iter()
+        .find(|r| r.name().as_deref() == Some(id))
+        .ok_or_else(|| format!("{} is not installed", id).into())
+}
+
+/// Check if a glob with `*` wildcards matches all of a text
+fn glob_matches(glob: &str, text: &str) -> bool {
+    match glob.split_once('*') {
+        None => glob == text,
+        Some((prefix, rest)) => {
+            let text = match text.strip_prefix(prefix) {
+                Some(some) => some,
+                None => return false,
+            };
+            // Try every possible length for the wildcard
+            text.char_indices()
+                .map(|(i, _)| i)
+                .chain(std::iter::once(text.len()))
+                .any(|i| glob_matches(rest, &text[i..]))
+        }
+    }
+}
+
+/// Check if a mask or pin pattern like `org.gnome.*` or `runtime/org.gnome.Platform//45` matches
+/// a ref the way flatpak does: the kind is optional, and missing or empty arch and branch parts
+/// match anything
+fn pattern_matches(pattern: &str, kind: &str, name: &str, arch: &str, branch: &str) -> bool {
+    let mut parts: Vec<&str> = pattern.split('/').collect();
+    if parts.len() > 1 && (parts[0] == "app" || parts[0] == "runtime") {
+        if parts[0] != kind {
+            return false;
+        }
+        parts.remove(0);
+    }
+    if parts.len() > 3 {
+        return false;
+    }
+    [name, arch, branch]
+        .iter()
+        .zip(parts.iter())
+        .enumerate()
+        .all(|(i, (value, part))| (i > 0 && part.is_empty()) || glob_matches(part, value))
+}
+
+/// Patterns stored in an installation config key, such as masked or pinned refs
+fn config_patterns(inst: &Installation, key: &str) -> Vec<String> {
+    // Returns an error when the key is not set
+    match inst.config(key, Cancellable::NONE) {
+        Ok(value) => value
+            .split(';')
+            .filter(|x| !x.is_empty())
+            .map(|x| x.to_string())
+            .collect(),
+        Err(_) => Vec::new(),
+    }
+}
+
+/// Add or remove a pattern in an installation config key
+fn set_config_pattern(
+    inst: &Installation,
+    key: &str,
+    pattern: &str,
+    enable: bool,
+) -> Result<(), Box<dyn Error>> {
+    let mut patterns = config_patterns(inst, key);
+    patterns.retain(|x| x != pattern);
+    if enable {
+        patterns.push(pattern.to_string());
+    }
+    inst.set_config_sync(key, &patterns.join(";"), Cancellable::NONE)?;
+    Ok(())
+}
 
 #[derive(Debug)]
 pub struct Flatpak {
+    user: bool,
     appstream_cache: AppstreamCache,
+    /// Metainfo of installed apps, read from their deploy directories
+    installed_cache: AppstreamCache,
+    /// Origin remote and exported icon of installed apps
+    installed_origins: HashMap<String, String>,
+    installed_icons: HashMap<String, PathBuf>,
+    watch_paths: Vec<PathBuf>,
+    /// Branches of apps in each remote, listing remote refs may download so it is done once
+    remote_branches: Mutex<HashMap<String, HashMap<String, Vec<Branch>>>>,
 }
 
 impl Flatpak {
-    pub fn new(locale: &str) -> Result<Self, Box<dyn Error>> {
-        //TODO: should we support system installations?
-        let inst = Installation::new_user(Cancellable::NONE)?;
+    pub fn new(locale: &str, user: bool) -> Result<Self, Box<dyn Error>> {
+        let inst = Self::installation_for(user)?;
         let mut paths = Vec::new();
         let mut icons_paths = Vec::new();
+        let mut watch_paths = Vec::new();
         for remote in inst.list_remotes(Cancellable::NONE)? {
             if let Some(appstream_dir) = remote.appstream_dir(None).and_then(|x| x.path()) {
+                // Appstream updates replace the active symlink in the parent directory
+                if let Some(parent) = appstream_dir.parent() {
+                    if parent.is_dir() {
+                        watch_paths.push(parent.to_path_buf());
+                    }
+                }
+
                 let xml_gz_path = appstream_dir.join("appstream.xml.gz");
                 if xml_gz_path.is_file() {
                     paths.push(xml_gz_path);
@@ -35,49 +239,559 @@ impl Flatpak {
             }
         }
 
+        // Installing or removing apps changes the app directory
+        if let Some(app_dir) = inst.path().and_then(|x| x.path()).map(|x| x.join("app")) {
+            if app_dir.is_dir() {
+                watch_paths.push(app_dir);
+            }
+        }
+
+        let mut installed_paths = Vec::new();
+        let mut installed_origins = HashMap::new();
+        let mut installed_icons = HashMap::new();
+        for r in inst.list_installed_refs_by_kind(RefKind::App, Cancellable::NONE)? {
+            let (id, deploy_dir) = match (r.name(), r.deploy_dir()) {
+                (Some(id), Some(deploy_dir)) => (id, deploy_dir),
+                _ => continue,
+            };
+            let deploy_dir = Path::new(deploy_dir.as_str());
+            if let Some(path) = deploy_metainfo(deploy_dir, &id) {
+                installed_paths.push(path);
+            }
+            if let Some(origin) = r.origin() {
+                installed_origins.insert(id.to_string(), origin.to_string());
+            }
+            if let Some(path) = deploy_icon(deploy_dir, &id) {
+                installed_icons.insert(id.to_string(), path);
+            }
+        }
+
         // We don't store the installation because it is not Send
         Ok(Self {
-            appstream_cache: AppstreamCache::new(paths, icons_paths, locale),
+            user,
+            appstream_cache: AppstreamCache::new(paths, icons_paths.clone(), locale),
+            installed_cache: AppstreamCache::new(installed_paths, icons_paths, locale),
+            installed_origins,
+            installed_icons,
+            watch_paths,
+            remote_branches: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Branches of an app in a remote, listing the refs of the remote if not already listed
+    fn remote_branches(
+        &self,
+        inst: &Installation,
+        remote_name: &str,
+        id: &str,
+    ) -> Result<Vec<Branch>, Box<dyn Error>> {
+        let mut remote_branches = self.remote_branches.lock().unwrap();
+        if !remote_branches.contains_key(remote_name) {
+            let mut app_branches = HashMap::<String, Vec<Branch>>::new();
+            for r in inst.list_remote_refs_sync(remote_name, Cancellable::NONE)? {
+                if r.kind() != RefKind::App {
+                    continue;
+                }
+                let name = match r.name() {
+                    Some(some) => some,
+                    None => continue,
+                };
+                app_branches
+                    .entry(name.to_string())
+                    .or_default()
+                    .push(Branch {
+                        remote: remote_name.to_string(),
+                        arch: r.arch().unwrap_or_default().to_string(),
+                        branch: r.branch().unwrap_or_default().to_string(),
+                        installed: false,
+                    });
+            }
+            remote_branches.insert(remote_name.to_string(), app_branches);
+        }
+        Ok(remote_branches
+            .get(remote_name)
+            .and_then(|app_branches| app_branches.get(id))
+            .cloned()
+            .unwrap_or_default())
+    }
+
+    fn cache_name(&self) -> &'static str {
+        if self.user {
+            "flatpak-user"
+        } else {
+            "flatpak-system"
+        }
+    }
+
+    fn installation_for(user: bool) -> Result<Installation, Box<dyn Error>> {
+        Ok(if user {
+            Installation::new_user(Cancellable::NONE)?
+        } else {
+            Installation::new_system(Cancellable::NONE)?
+        })
+    }
+
+    fn installation(&self) -> Result<Installation, Box<dyn Error>> {
+        Self::installation_for(self.user)
+    }
+
+    fn package(&self, inst: &Installation, r: &InstalledRef) -> Option<Package> {
+        let id = r.name()?;
+        let mut extra = HashMap::new();
+        if let Some(arch) = r.arch() {
+            extra.insert("arch".to_string(), arch.to_string());
+        }
+        if let Some(branch) = r.branch() {
+            extra.insert("branch".to_string(), branch.to_string());
+        }
+        if let Some(eol) = r.eol() {
+            extra.insert("eol".to_string(), eol.to_string());
+        }
+        if let Some(eol_rebase) = r.eol_rebase() {
+            extra.insert("eol_rebase".to_string(), rebase_id(&eol_rebase).to_string());
+        }
+        let masked = config_patterns(inst, "masked");
+        let arch = r.arch().unwrap_or_default();
+        let branch = r.branch().unwrap_or_default();
+        if masked
+            .iter()
+            .any(|pattern| pattern_matches(pattern, "app", &id, &arch, &branch))
+        {
+            extra.insert("held".to_string(), "true".to_string());
+        }
+        if let Some(runtime) = app_runtime(r) {
+            if let Some(runtime_eol) = runtime_eol(inst, &runtime) {
+                extra.insert("runtime_eol".to_string(), runtime_eol);
+            }
+            let mut parts = runtime.split('/');
+            let (runtime_name, runtime_arch, runtime_branch) = (
+                parts.next().unwrap_or_default(),
+                parts.next().unwrap_or_default(),
+                parts.next().unwrap_or_default(),
+            );
+            if masked.iter().any(|pattern| {
+                pattern_matches(
+                    pattern,
+                    "runtime",
+                    runtime_name,
+                    runtime_arch,
+                    runtime_branch,
+                )
+            }) {
+                extra.insert("runtime_held".to_string(), "true".to_string());
+            }
+            extra.insert("runtime".to_string(), runtime);
+        }
+        let icon = match r
+            .deploy_dir()
+            .and_then(|deploy_dir| deploy_icon(Path::new(deploy_dir.as_str()), &id))
+        {
+            Some(path) => widget::icon::from_path(path),
+            None => match self.appstream_cache.infos.get(id.as_str()) {
+                Some(info) => self.appstream_cache.icon(info),
+                None => widget::icon::from_name(id.to_string()).size(128).handle(),
+            },
+        };
+        Some(Package {
+            id: id.to_string(),
+            icon,
+            name: r.appdata_name().unwrap_or(id.clone()).to_string(),
+            summary: r.appdata_summary().map_or(String::new(), |x| x.to_string()),
+            version: r.appdata_version().unwrap_or_default().to_string(),
+            origin_opt: r.origin().map(|x| x.to_string()),
+            extra,
         })
     }
 }
 
 impl Backend for Flatpak {
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            install: true,
+            uninstall: true,
+            updates: true,
+            remotes: true,
+            holds: true,
+            branches: true,
+            rebase: true,
+            local_files: false,
+            permissions: false,
+            ..Default::default()
+        }
+    }
+
     fn load_cache(&mut self) -> Result<(), Box<dyn Error>> {
-        self.appstream_cache.reload("flatpak");
+        let cache_name = self.cache_name();
+        self.appstream_cache.reload(cache_name);
+        self.installed_cache
+            .reload(&format!("{}-installed", cache_name));
+
+        // Metainfo does not know its remote and rarely has icons, so add them from the installation
+        for (id, info) in self.installed_cache.infos.iter_mut() {
+            // Older metainfo uses the desktop file name as the component id
+            let id = id.trim_end_matches(".desktop");
+            let info = match Arc::get_mut(info) {
+                Some(some) => some,
+                None => continue,
+            };
+            if info.origin_opt.is_none() {
+                info.origin_opt = self.installed_origins.get(id).cloned();
+            }
+            if let Some(path) = self.installed_icons.get(id) {
+                info.icons
+                    .push(AppIcon::Local(path.to_string_lossy().to_string()));
+            }
+        }
         Ok(())
     }
 
     fn installed(&self) -> Result<Vec<Package>, Box<dyn Error>> {
-        //TODO: should we support system installations?
-        let inst = Installation::new_user(Cancellable::NONE)?;
+        let inst = self.installation()?;
         let mut packages = Vec::new();
         //TODO: show non-desktop items?
         for r in inst.list_installed_refs_by_kind(RefKind::App, Cancellable::NONE)? {
-            if let Some(id) = r.name() {
-                let mut extra = HashMap::new();
-                if let Some(arch) = r.arch() {
-                    extra.insert("arch".to_string(), arch.to_string());
-                }
-                if let Some(branch) = r.branch() {
-                    extra.insert("branch".to_string(), branch.to_string());
+            if let Some(package) = self.package(&inst, &r) {
+                packages.push(package);
+            }
+        }
+        Ok(packages)
+    }
+
+    fn updates(&self) -> Result<Vec<Package>, Box<dyn Error>> {
+        let inst = self.installation()?;
+        let mut packages = Vec::new();
+        for r in inst.list_installed_refs_for_update(Cancellable::NONE)? {
+            //TODO: show runtime updates?
+            if r.kind() != RefKind::App {
+                continue;
+            }
+            if let Some(package) = self.package(&inst, &r) {
+                // Held apps are shown separately by the updates view
+                if package.extra.contains_key("held") {
+                    continue;
                 }
-                packages.push(Package {
-                    id: id.to_string(),
-                    //TODO: get icon from appstream data?
-                    icon: widget::icon::from_name(id.to_string()).size(128).handle(),
-                    name: r.appdata_name().unwrap_or(id).to_string(),
-                    summary: r.appdata_summary().map_or(String::new(), |x| x.to_string()),
-                    version: r.appdata_version().unwrap_or_default().to_string(),
-                    origin_opt: r.origin().map(|x| x.to_string()),
-                    extra,
-                })
+                packages.push(package);
             }
         }
         Ok(packages)
     }
 
+    fn sources(&self) -> Result<Vec<String>, Box<dyn Error>> {
+        let inst = self.installation()?;
+        let mut sources = Vec::new();
+        for remote in inst.list_remotes(Cancellable::NONE)? {
+            if let Some(name) = remote.name() {
+                sources.push(name.to_string());
+            }
+        }
+        Ok(sources)
+    }
+
+    fn refresh(&self) -> Result<bool, Box<dyn Error>> {
+        let inst = self.installation()?;
+        let mut changed = false;
+        for remote in inst.list_remotes(Cancellable::NONE)? {
+            if remote.is_disabled() || remote.is_noenumerate() {
+                continue;
+            }
+            let name = match remote.name() {
+                Some(some) => some,
+                None => continue,
+            };
+            let appstream_dir = match remote.appstream_dir(None).and_then(|x| x.path()) {
+                Some(some) => some,
+                None => continue,
+            };
+            // Files in the ostree checkout have an mtime of 0, flatpak touches .timestamp in the
+            // arch directory above the active checkout on every appstream update
+            let modified_opt = appstream_dir
+                .parent()
+                .map(|arch_dir| arch_dir.join(".timestamp"))
+                .and_then(|path| path.metadata().ok())
+                .and_then(|metadata| metadata.modified().ok());
+            let old = match modified_opt {
+                Some(modified) => SystemTime::now()
+                    .duration_since(modified)
+                    .map_or(false, |age| age > APPSTREAM_MAX_AGE),
+                None => true,
+            };
+            if !old {
+                continue;
+            }
+            log::info!(
+                "updating appstream of {} remote {}",
+                self.cache_name(),
+                name
+            );
+            match inst.update_appstream_sync(&name, None, Cancellable::NONE) {
+                Ok(remote_changed) => changed |= remote_changed,
+                Err(err) => {
+                    log::warn!(
+                        "failed to update appstream of {} remote {}: {}",
+                        self.cache_name(),
+                        name,
+                        err
+                    );
+                }
+            }
+        }
+        Ok(changed)
+    }
+
+    fn branches(&self, id: &str) -> Result<Vec<Branch>, Box<dyn Error>> {
+        let inst = self.installation()?;
+        let mut branches = Vec::new();
+        for r in inst.list_installed_refs_by_kind(RefKind::App, Cancellable::NONE)? {
+            if r.name().as_deref() != Some(id) {
+                continue;
+            }
+            branches.push(Branch {
+                remote: r.origin().unwrap_or_default().to_string(),
+                arch: r.arch().unwrap_or_default().to_string(),
+                branch: r.branch().unwrap_or_default().to_string(),
+                installed: true,
+            });
+        }
+        for remote in inst.list_remotes(Cancellable::NONE)? {
+            if remote.is_disabled() {
+                continue;
+            }
+            let remote_name = match remote.name() {
+                Some(some) => some,
+                None => continue,
+            };
+            let remote_branches = match self.remote_branches(&inst, &remote_name, id) {
+                Ok(ok) => ok,
+                Err(err) => {
+                    log::warn!("failed to list refs of remote {}: {}", remote_name, err);
+                    continue;
+                }
+            };
+            for branch in remote_branches {
+                // Installed branches were already added
+                if !branches.iter().any(|x| x.same_ref(&branch)) {
+                    branches.push(branch);
+                }
+            }
+        }
+        Ok(branches)
+    }
+
+    fn hold(&self, id: &str, runtime: bool, hold: bool) -> Result<(), Box<dyn Error>> {
+        let inst = self.installation()?;
+        let r = installed_app_ref(&inst, id, None)?;
+        // Masked refs are skipped by updates, app masks match by id
+        let (pattern, kind, name, arch, branch) = if runtime {
+            let runtime = app_runtime(&r).ok_or_else(|| format!("{} has no runtime", id))?;
+            let mut parts = runtime.split('/');
+            let (name, arch, branch) = (
+                parts.next().unwrap_or_default().to_string(),
+                parts.next().unwrap_or_default().to_string(),
+                parts.next().unwrap_or_default().to_string(),
+            );
+            (
+                format!("runtime/{}", runtime),
+                "runtime",
+                name,
+                arch,
+                branch,
+            )
+        } else {
+            (
+                id.to_string(),
+                "app",
+                id.to_string(),
+                r.arch().unwrap_or_default().to_string(),
+                r.branch().unwrap_or_default().to_string(),
+            )
+        };
+        set_config_pattern(&inst, "masked", &pattern, hold)?;
+        if runtime {
+            // Pinned runtimes are not removed when unused
+            set_config_pattern(&inst, "pinned", &pattern, hold)?;
+        }
+        if !hold {
+            // Masks added outside of the store, such as globs, cannot be removed for one ref
+            if let Some(other) = config_patterns(&inst, "masked")
+                .iter()
+                .find(|x| pattern_matches(x, kind, &name, &arch, &branch))
+            {
+                return Err(format!(
+                    "{} is still held by the mask {:?}, remove it with flatpak mask --remove",
+                    name, other
+                )
+                .into());
+            }
+        }
+        Ok(())
+    }
+
+    fn operation(
+        &self,
+        op: &Operation,
+        f: Box<dyn FnMut(f32) + 'static>,
+    ) -> Result<(), Box<dyn Error>> {
+        let inst = self.installation()?;
+        let tx = Transaction::for_installation(&inst, Cancellable::NONE)?;
+        match op.kind {
+            OperationKind::Install => match &op.branch_opt {
+                Some(branch) => {
+                    let ref_str =
+                        format!("app/{}/{}/{}", op.package_id, branch.arch, branch.branch);
+                    tx.add_install(&branch.remote, &ref_str, &[])?;
+                }
+                None => {
+                    let remote_name = op
+                        .info
+                        .origin_opt
+                        .as_deref()
+                        .ok_or_else(|| format!("{} has no flatpak remote", op.package_id))?;
+                    let remote = inst.remote_by_name(remote_name, Cancellable::NONE)?;
+                    let branch = remote.default_branch();
+                    let remote_ref = inst.fetch_remote_ref_sync(
+                        remote_name,
+                        RefKind::App,
+                        &op.package_id,
+                        None,
+                        Some(branch.as_deref().unwrap_or("stable")),
+                        Cancellable::NONE,
+                    )?;
+                    let ref_str = remote_ref
+                        .format_ref()
+                        .ok_or_else(|| format!("failed to format ref for {}", op.package_id))?;
+                    tx.add_install(remote_name, &ref_str, &[])?;
+                }
+            },
+            OperationKind::Uninstall | OperationKind::Update => {
+                let r = installed_app_ref(&inst, &op.package_id, op.branch_opt.as_ref())?;
+                let ref_str = r
+                    .format_ref()
+                    .ok_or_else(|| format!("failed to format ref for {}", op.package_id))?;
+                if op.kind == OperationKind::Uninstall {
+                    tx.add_uninstall(&ref_str)?;
+                } else {
+                    tx.add_update(&ref_str, &[], None)?;
+                }
+            }
+            OperationKind::Rebase => {
+                let r = installed_app_ref(&inst, &op.package_id, op.branch_opt.as_ref())?;
+                let remote_name = r
+                    .origin()
+                    .ok_or_else(|| format!("{} has no flatpak remote", op.package_id))?;
+                let eol_rebase = r
+                    .eol_rebase()
+                    .ok_or_else(|| format!("{} has no replacement", op.package_id))?;
+                let new_ref_str = if eol_rebase.contains('/') {
+                    eol_rebase.to_string()
+                } else {
+                    format!(
+                        "app/{}/{}/{}",
+                        eol_rebase,
+                        r.arch().unwrap_or_default(),
+                        r.branch().unwrap_or_default()
+                    )
+                };
+                let old_ref_str = r
+                    .format_ref()
+                    .ok_or_else(|| format!("failed to format ref for {}", op.package_id))?;
+                // Previous ids let the new app migrate the data of the old one
+                tx.add_rebase(&remote_name, &new_ref_str, &[], &[&op.package_id])?;
+                tx.add_uninstall(&old_ref_str)?;
+            }
+            OperationKind::Integrate | OperationKind::OfflineUpdate => {
+                return Err(format!("{:?} is not supported by flatpak", op.kind).into());
+            }
+        }
+
+        // Transaction callbacks run on this thread, so they can share the progress callback
+        let f = Rc::new(RefCell::new(f));
+        let started = Rc::new(Cell::new(0));
+        tx.connect_new_operation(move |tx, _op, progress| {
+            let total = cmp::max(tx.operations().len(), 1) as f32;
+            let index = started.get() as f32;
+            started.set(started.get() + 1);
+            let f = f.clone();
+            progress.connect_changed(move |progress| {
+                let percent = 100.0 * (index + progress.progress() as f32 / 100.0) / total;
+                (f.borrow_mut())(percent);
+            });
+        });
+        tx.run(Cancellable::NONE)?;
+        Ok(())
+    }
+
+    fn info(&self, package: &Package) -> Result<Arc<AppInfo>, Box<dyn Error>> {
+        // Prefer the metainfo of the installed app, it matches the deployed version
+        match self
+            .installed_cache
+            .infos
+            .get(&package.id)
+            .or_else(|| {
+                self.installed_cache
+                    .infos
+                    .get(&format!("{}.desktop", package.id))
+            })
+            .or_else(|| self.appstream_cache.infos.get(&package.id))
+        {
+            Some(info) => Ok(info.clone()),
+            // Apps without metainfo still have the details exported by the installation
+            None => Ok(Arc::new(AppInfo {
+                origin_opt: package.origin_opt.clone(),
+                name: package.name.clone(),
+                summary: package.summary.clone(),
+                icons: self
+                    .installed_icons
+                    .get(&package.id)
+                    .map(|path| AppIcon::Local(path.to_string_lossy().to_string()))
+                    .into_iter()
+                    .collect(),
+                desktop_ids: vec![format!("{}.desktop", package.id)],
+                ..Default::default()
+            })),
+        }
+    }
+
     fn info_cache(&self) -> &AppstreamCache {
         &self.appstream_cache
     }
+
+    fn watch_paths(&self) -> Vec<PathBuf> {
+        self.watch_paths.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn glob() {
+        assert!(glob_matches("org.gnome.*", "org.gnome.Maps"));
+        assert!(glob_matches("*", ""));
+        assert!(glob_matches("org.*.Maps", "org.gnome.Maps"));
+        assert!(!glob_matches("org.gnome.*", "org.kde.kate"));
+        assert!(!glob_matches("org.gnome.Maps", "org.gnome.Maps2"));
+    }
+
+    #[test]
+    fn mask_patterns() {
+        let app = |pattern| pattern_matches(pattern, "app", "org.gnome.Maps", "x86_64", "stable");
+        assert!(app("org.gnome.Maps"));
+        assert!(app("org.gnome.*"));
+        assert!(app("app/org.gnome.Maps"));
+        assert!(app("app/org.gnome.Maps//stable"));
+        assert!(app("org.gnome.Maps/x86_64/stable"));
+        assert!(!app("runtime/org.gnome.Maps"));
+        assert!(!app("org.gnome.Maps//beta"));
+        assert!(!app("org.gnome.Maps/aarch64"));
+        assert!(!app("org.kde.*"));
+
+        let runtime =
+            |pattern| pattern_matches(pattern, "runtime", "org.gnome.Platform", "x86_64", "45");
+        assert!(runtime("runtime/org.gnome.Platform/x86_64/45"));
+        assert!(runtime("runtime/org.gnome.*//45"));
+        assert!(runtime("org.gnome.Platform"));
+        assert!(!runtime("app/org.gnome.Platform"));
+        assert!(!runtime("runtime/org.gnome.Platform//46"));
+    }
 }
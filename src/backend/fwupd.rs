@@ -0,0 +1,417 @@
+use cosmic::widget;
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+use std::{
+    collections::HashMap,
+    error::Error,
+    fs,
+    io::Read,
+    os::fd::AsRawFd,
+    sync::{mpsc, Arc},
+    thread,
+    time::Duration,
+};
+use zbus::{
+    blocking::Connection,
+    dbus_proxy,
+    zvariant::{Array, Fd, OwnedValue, Value},
+};
+
+use super::{Backend, Capabilities, Package};
+use crate::{AppIcon, AppInfo, AppstreamCache, Operation, OperationKind};
+
+// https://github.com/fwupd/fwupd/blob/main/libfwupd/fwupd-enums.h
+const DEVICE_FLAG_UPDATABLE: u64 = 1 << 1;
+/// Size of a firmware cabinet file, larger downloads are refused
+const FIRMWARE_FILE_SIZE: u64 = 256 * 1024 * 1024;
+
+type Dict = HashMap<String, OwnedValue>;
+
+#[dbus_proxy(
+    interface = "org.freedesktop.fwupd",
+    default_service = "org.freedesktop.fwupd",
+    default_path = "/"
+)]
+trait Fwupd {
+    fn get_devices(&self) -> zbus::Result<Vec<Dict>>;
+
+    fn get_upgrades(&self, device_id: &str) -> zbus::Result<Vec<Dict>>;
+
+    fn install(&self, id: &str, handle: Fd, options: HashMap<&str, Value<'_>>) -> zbus::Result<()>;
+
+    #[dbus_proxy(property)]
+    fn percentage(&self) -> zbus::Result<u32>;
+}
+
+fn dict_str<'a>(dict: &'a Dict, key: &str) -> Option<&'a str> {
+    dict.get(key).and_then(|value| <&str>::try_from(value).ok())
+}
+
+fn dict_u64(dict: &Dict, key: &str) -> Option<u64> {
+    dict.get(key).and_then(|value| u64::try_from(value).ok())
+}
+
+fn dict_strs(dict: &Dict, key: &str) -> Vec<String> {
+    match dict
+        .get(key)
+        .and_then(|value| <&Array>::try_from(value).ok())
+    {
+        Some(array) => array
+            .get()
+            .iter()
+            .filter_map(|value| match value {
+                Value::Str(string) => Some(string.to_string()),
+                _ => None,
+            })
+            .collect(),
+        None => Vec::new(),
+    }
+}
+
+/// Download location of a release, only HTTPS is used so the download cannot be altered
+fn download_uri(release: &Dict) -> Option<String> {
+    let mut uris = dict_strs(release, "Locations");
+    if let Some(uri) = dict_str(release, "Uri") {
+        uris.push(uri.to_string());
+    }
+    uris.into_iter().find(|uri| uri.starts_with("https://"))
+}
+
+/// Lowercase checksums of a release, fwupd joins them with commas
+fn release_checksums(release: &Dict) -> Vec<String> {
+    let mut checksums = dict_strs(release, "Checksum");
+    if let Some(checksum) = dict_str(release, "Checksum") {
+        checksums.push(checksum.to_string());
+    }
+    checksums
+        .iter()
+        .flat_map(|checksum| checksum.split(','))
+        .map(|checksum| checksum.trim().to_lowercase())
+        .filter(|checksum| !checksum.is_empty())
+        .collect()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Check data against the SHA-256 checksums of a release, or SHA-1 if it has none, returning the
+/// matching checksum
+fn verify_checksum(data: &[u8], checksums: &[String]) -> Result<String, Box<dyn Error>> {
+    let (kind, checksum) = if checksums.iter().any(|x| x.len() == 64) {
+        ("SHA-256", hex(&Sha256::digest(data)))
+    } else if checksums.iter().any(|x| x.len() == 40) {
+        ("SHA-1", hex(&Sha1::digest(data)))
+    } else {
+        return Err("release has no SHA-256 or SHA-1 checksum".into());
+    };
+    if checksums.contains(&checksum) {
+        Ok(checksum)
+    } else {
+        Err(format!("{} checksum {} does not match release", kind, checksum).into())
+    }
+}
+
+//TODO: render release description markup
+fn strip_markup(markup: &str) -> String {
+    let mut text = String::with_capacity(markup.len());
+    let mut in_tag = false;
+    for c in markup.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' => {
+                in_tag = false;
+                if !text.ends_with('\n') && !text.is_empty() {
+                    text.push('\n');
+                }
+            }
+            _ if !in_tag => text.push(c),
+            _ => {}
+        }
+    }
+    text.trim().to_string()
+}
+
+#[derive(Debug)]
+pub struct Fwupd {
+    connection: Connection,
+    appstream_cache: AppstreamCache,
+}
+
+impl Fwupd {
+    pub fn new(locale: &str) -> Result<Self, Box<dyn Error>> {
+        let connection = Connection::system()?;
+        // Firmware has no appstream catalog, this cache stays empty
+        Ok(Self {
+            connection,
+            appstream_cache: AppstreamCache::new(Vec::new(), Vec::new(), locale),
+        })
+    }
+
+    fn proxy(&self) -> Result<FwupdProxyBlocking, Box<dyn Error>> {
+        Ok(FwupdProxyBlocking::new(&self.connection)?)
+    }
+
+    fn updatable_devices(&self) -> Result<Vec<Dict>, Box<dyn Error>> {
+        Ok(self
+            .proxy()?
+            .get_devices()?
+            .into_iter()
+            .filter(|device| dict_u64(device, "Flags").unwrap_or(0) & DEVICE_FLAG_UPDATABLE != 0)
+            .collect())
+    }
+
+    fn package(&self, device: &Dict, release_opt: Option<&Dict>) -> Option<Package> {
+        let id = dict_str(device, "DeviceId")?;
+        let name = dict_str(device, "Name").unwrap_or(id);
+        let icon_name = dict_strs(device, "Icon")
+            .into_iter()
+            .next()
+            .unwrap_or_else(|| "application-x-firmware".to_string());
+        let current_version = dict_str(device, "Version").unwrap_or_default();
+        let mut extra = HashMap::new();
+        extra.insert("current_version".to_string(), current_version.to_string());
+        if let Some(release) = release_opt {
+            if let Some(description) = dict_str(release, "Description") {
+                extra.insert("release_notes".to_string(), strip_markup(description));
+            }
+        }
+        Some(Package {
+            id: id.to_string(),
+            icon: widget::icon::from_name(icon_name).size(128).handle(),
+            name: match dict_str(device, "Vendor") {
+                Some(vendor) => format!("{} {}", vendor, name),
+                None => name.to_string(),
+            },
+            summary: dict_str(device, "Summary").unwrap_or_default().to_string(),
+            origin_opt: Some("fwupd".to_string()),
+            version: release_opt
+                .and_then(|release| dict_str(release, "Version"))
+                .unwrap_or(current_version)
+                .to_string(),
+            extra,
+        })
+    }
+
+    /// Download the cabinet file of a release into the cache directory and verify it
+    fn download(&self, device_id: &str, release: &Dict) -> Result<fs::File, Box<dyn Error>> {
+        let uri = download_uri(release)
+            .ok_or_else(|| format!("no HTTPS download location for {} release", device_id))?;
+
+        let cache_dir = dirs::cache_dir()
+            .ok_or("failed to find cache directory")?
+            .join("cosmic-store")
+            .join("fwupd");
+        fs::create_dir_all(&cache_dir)?;
+
+        log::info!("downloading {} to {:?}", uri, cache_dir);
+        let mut data = Vec::new();
+        ureq::get(&uri)
+            .call()?
+            .into_reader()
+            .take(FIRMWARE_FILE_SIZE + 1)
+            .read_to_end(&mut data)?;
+        if data.len() as u64 > FIRMWARE_FILE_SIZE {
+            return Err(format!(
+                "{} release from {} is larger than {} bytes",
+                device_id, uri, FIRMWARE_FILE_SIZE
+            )
+            .into());
+        }
+        let checksum = verify_checksum(&data, &release_checksums(release))
+            .map_err(|err| format!("{} release from {}: {}", device_id, uri, err))?;
+        // The checksum is a safe file name, unlike the end of the URI
+        let path = cache_dir.join(format!("{}.cab", checksum));
+        fs::write(&path, &data)?;
+        Ok(fs::File::open(&path)?)
+    }
+}
+
+impl Backend for Fwupd {
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            updates: true,
+            local_files: false,
+            permissions: false,
+            ..Default::default()
+        }
+    }
+
+    fn load_cache(&mut self) -> Result<(), Box<dyn Error>> {
+        Ok(())
+    }
+
+    fn installed(&self) -> Result<Vec<Package>, Box<dyn Error>> {
+        // Devices are not apps, they are only listed when they have updates
+        Ok(Vec::new())
+    }
+
+    fn updates(&self) -> Result<Vec<Package>, Box<dyn Error>> {
+        let proxy = self.proxy()?;
+        let mut packages = Vec::new();
+        for device in self.updatable_devices()? {
+            let device_id = match dict_str(&device, "DeviceId") {
+                Some(some) => some,
+                None => continue,
+            };
+            // Returns an error when there are no upgrades
+            let releases = match proxy.get_upgrades(device_id) {
+                Ok(ok) => ok,
+                Err(err) => {
+                    log::debug!("no upgrades for {}: {}", device_id, err);
+                    continue;
+                }
+            };
+            // Upgrades are sorted newest first
+            if let Some(package) = self.package(&device, releases.first()) {
+                packages.push(package);
+            }
+        }
+        Ok(packages)
+    }
+
+    fn sources(&self) -> Result<Vec<String>, Box<dyn Error>> {
+        Ok(Vec::new())
+    }
+
+    fn operation(
+        &self,
+        op: &Operation,
+        mut f: Box<dyn FnMut(f32) + 'static>,
+    ) -> Result<(), Box<dyn Error>> {
+        if op.kind != OperationKind::Update {
+            return Err(format!("{:?} is not supported for firmware", op.kind).into());
+        }
+
+        let proxy = self.proxy()?;
+        let release = proxy
+            .get_upgrades(&op.package_id)?
+            .into_iter()
+            .next()
+            .ok_or_else(|| format!("no upgrades for {}", op.package_id))?;
+        let file = self.download(&op.package_id, &release)?;
+
+        // Install blocks until done, so run it on another thread and poll progress here
+        let (tx, rx) = mpsc::channel();
+        {
+            let proxy = proxy.clone();
+            let device_id = op.package_id.clone();
+            thread::spawn(move || {
+                let result = proxy
+                    .install(&device_id, Fd::from(file.as_raw_fd()), HashMap::new())
+                    .map_err(|err| err.to_string());
+                // The file must stay open until the install completes
+                drop(file);
+                let _ = tx.send(result);
+            });
+        }
+        loop {
+            match rx.recv_timeout(Duration::from_millis(250)) {
+                Ok(result) => return Ok(result?),
+                Err(mpsc::RecvTimeoutError::Timeout) => {
+                    if let Ok(percentage) = proxy.percentage() {
+                        f(percentage as f32);
+                    }
+                }
+                Err(mpsc::RecvTimeoutError::Disconnected) => {
+                    return Err("fwupd install thread exited".into());
+                }
+            }
+        }
+    }
+
+    fn info(&self, package: &Package) -> Result<Arc<AppInfo>, Box<dyn Error>> {
+        Ok(Arc::new(AppInfo {
+            origin_opt: package.origin_opt.clone(),
+            name: package.name.clone(),
+            summary: package.summary.clone(),
+            icons: vec![AppIcon::Stock("application-x-firmware".to_string())],
+            ..Default::default()
+        }))
+    }
+
+    fn info_cache(&self) -> &AppstreamCache {
+        &self.appstream_cache
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HELLO_SHA1: &str = "aaf4c61ddcc5e8a2dabede0f3b482cd9aea9434d";
+    const HELLO_SHA256: &str = "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824";
+
+    fn release(entries: Vec<(&str, Value)>) -> Dict {
+        entries
+            .into_iter()
+            .map(|(key, value)| (key.to_string(), OwnedValue::from(value)))
+            .collect()
+    }
+
+    #[test]
+    fn https_only() {
+        let http = release(vec![(
+            "Locations",
+            Value::from(vec!["http://fwupd.org/downloads/firmware.cab"]),
+        )]);
+        assert_eq!(download_uri(&http), None);
+
+        let mixed = release(vec![
+            (
+                "Locations",
+                Value::from(vec!["http://fwupd.org/downloads/firmware.cab"]),
+            ),
+            (
+                "Uri",
+                Value::from("https://fwupd.org/downloads/firmware.cab"),
+            ),
+        ]);
+        assert_eq!(
+            download_uri(&mixed).as_deref(),
+            Some("https://fwupd.org/downloads/firmware.cab")
+        );
+    }
+
+    #[test]
+    fn checksums() {
+        let joined = release(vec![(
+            "Checksum",
+            Value::from(format!("{},{}", HELLO_SHA1, HELLO_SHA256.to_uppercase()).as_str()),
+        )]);
+        assert_eq!(
+            release_checksums(&joined),
+            vec![HELLO_SHA1.to_string(), HELLO_SHA256.to_string()]
+        );
+        assert_eq!(
+            release_checksums(&release(Vec::new())),
+            Vec::<String>::new()
+        );
+    }
+
+    #[test]
+    fn verify() {
+        let checksums = |x: &[&str]| x.iter().map(|x| x.to_string()).collect::<Vec<_>>();
+        assert_eq!(
+            verify_checksum(b"hello", &checksums(&[HELLO_SHA1, HELLO_SHA256])).ok(),
+            Some(HELLO_SHA256.to_string())
+        );
+        assert_eq!(
+            verify_checksum(b"hello", &checksums(&[HELLO_SHA1])).ok(),
+            Some(HELLO_SHA1.to_string())
+        );
+        assert!(verify_checksum(b"hello!", &checksums(&[HELLO_SHA1, HELLO_SHA256])).is_err());
+        // A matching SHA-1 is not enough when the release has a SHA-256 checksum
+        let other_sha256 = "0".repeat(64);
+        assert!(verify_checksum(b"hello", &checksums(&[HELLO_SHA1, &other_sha256])).is_err());
+        assert!(verify_checksum(b"hello", &[]).is_err());
+    }
+
+    #[test]
+    fn markup() {
+        assert_eq!(
+            strip_markup("<p>Fixes:</p><ul><li>Battery drain</li></ul>"),
+            "Fixes:\nBattery drain"
+        );
+    }
+}
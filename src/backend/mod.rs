@@ -1,15 +1,31 @@
 use cosmic::widget;
 use rayon::prelude::*;
-use std::{collections::HashMap, error::Error, fmt, sync::Arc, time::Instant};
+use std::{
+    collections::HashMap,
+    error::Error,
+    fmt,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Instant,
+};
 
-use crate::{AppInfo, AppstreamCache};
+use crate::{AppInfo, AppstreamCache, Config, Operation};
+
+#[cfg(feature = "appimage")]
+mod appimage;
 
 #[cfg(feature = "flatpak")]
 mod flatpak;
 
+#[cfg(feature = "fwupd")]
+mod fwupd;
+
 #[cfg(feature = "packagekit")]
 mod packagekit;
 
+#[cfg(feature = "snap")]
+mod snap;
+
 #[derive(Clone, Debug)]
 pub struct Package {
     pub id: String,
@@ -21,9 +37,125 @@ pub struct Package {
     pub extra: HashMap<String, String>,
 }
 
+impl Package {
+    /// Installed branch, for backends that install branches side by side
+    pub fn branch_opt(&self) -> Option<Branch> {
+        Some(Branch {
+            remote: self.origin_opt.clone()?,
+            arch: self.extra.get("arch")?.clone(),
+            branch: self.extra.get("branch")?.clone(),
+            installed: true,
+        })
+    }
+}
+
+/// A branch and arch of a package in a remote, for backends that install them side by side
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Branch {
+    pub remote: String,
+    pub arch: String,
+    pub branch: String,
+    pub installed: bool,
+}
+
+impl Branch {
+    /// Check if both are the same branch of a remote, whether installed or not
+    pub fn same_ref(&self, other: &Self) -> bool {
+        self.remote == other.remote && self.arch == other.arch && self.branch == other.branch
+    }
+}
+
+/// Result of the last offline update
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct OfflineResult {
+    pub success: bool,
+    pub packages: Vec<String>,
+    pub error_opt: Option<String>,
+}
+
+/// State of offline updates, for backends that apply updates on the next boot
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct OfflineState {
+    /// Updates are downloaded and ready to install
+    pub prepared: bool,
+    /// Updates will be installed on the next boot
+    pub triggered: bool,
+    pub last_result_opt: Option<OfflineResult>,
+}
+
+/// Features supported by a backend, used to hide actions it cannot perform
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct Capabilities {
+    /// Can install packages from its catalog
+    pub install: bool,
+    /// Can uninstall installed packages
+    pub uninstall: bool,
+    /// Can add installed packages to the application menu
+    pub integrate: bool,
+    /// Can list and apply updates
+    pub updates: bool,
+    /// Can apply updates offline on the next boot
+    pub offline_updates: bool,
+    /// Has remotes that can be listed, such as Flatpak remotes
+    pub remotes: bool,
+    /// Has repositories that can be listed, such as distribution repositories
+    pub repos: bool,
+    /// Can install local package files
+    pub local_files: bool,
+    /// Can show and change app permissions
+    pub permissions: bool,
+    /// Can hold packages at their installed version
+    pub holds: bool,
+    /// Can list and install branches of a package side by side
+    pub branches: bool,
+    /// Can replace an end-of-life package with its successor
+    pub rebase: bool,
+}
+
 pub trait Backend: fmt::Debug + Send + Sync {
+    fn capabilities(&self) -> Capabilities;
     fn load_cache(&mut self) -> Result<(), Box<dyn Error>>;
     fn installed(&self) -> Result<Vec<Package>, Box<dyn Error>>;
+    fn updates(&self) -> Result<Vec<Package>, Box<dyn Error>>;
+    /// Names of remotes or repositories, if supported by capabilities
+    fn sources(&self) -> Result<Vec<String>, Box<dyn Error>>;
+    /// Download remote metadata that is missing or old, returns true if it changed
+    fn refresh(&self) -> Result<bool, Box<dyn Error>> {
+        Ok(false)
+    }
+    /// Search a remote catalog, for backends without a local appstream catalog
+    fn search(&self, _input: &str) -> Result<Vec<Package>, Box<dyn Error>> {
+        Ok(Vec::new())
+    }
+    /// Packages that can open a MIME type, for backends that know more than their appstream data
+    fn what_provides(&self, _mimetype: &str) -> Result<Vec<Package>, Box<dyn Error>> {
+        Ok(Vec::new())
+    }
+    /// All branches of a package across remotes, if supported by capabilities
+    fn branches(&self, _id: &str) -> Result<Vec<Branch>, Box<dyn Error>> {
+        Ok(Vec::new())
+    }
+    /// State of offline updates, if supported by capabilities
+    fn offline_state(&self) -> Result<OfflineState, Box<dyn Error>> {
+        Ok(OfflineState::default())
+    }
+    /// Forget the result of the last offline update, if supported by capabilities
+    fn offline_clear_result(&self) -> Result<(), Box<dyn Error>> {
+        Ok(())
+    }
+    /// Restart to install triggered offline updates, if supported by capabilities
+    fn offline_reboot(&self) -> Result<(), Box<dyn Error>> {
+        Err("offline updates are not supported".into())
+    }
+    /// Hold a package, or its runtime, at the installed version, if supported by capabilities
+    fn hold(&self, _id: &str, _runtime: bool, _hold: bool) -> Result<(), Box<dyn Error>> {
+        Err("holding packages is not supported".into())
+    }
+    fn operation(
+        &self,
+        op: &Operation,
+        f: Box<dyn FnMut(f32) + 'static>,
+    ) -> Result<(), Box<dyn Error>>;
     //TODO: remove
     fn info(&self, package: &Package) -> Result<Arc<AppInfo>, Box<dyn Error>> {
         let info_cache = self.info_cache();
@@ -33,65 +165,365 @@ pub trait Backend: fmt::Debug + Send + Sync {
         }
     }
     fn info_cache(&self) -> &AppstreamCache;
+    /// Directories to watch for changes that require reloading the backend
+    fn watch_paths(&self) -> Vec<PathBuf> {
+        let mut paths = Vec::new();
+        for path in self.info_cache().path_tags.keys() {
+            if let Some(parent) = path.parent() {
+                if !paths.iter().any(|x| x == parent) {
+                    paths.push(parent.to_path_buf());
+                }
+            }
+        }
+        paths
+    }
 }
 
 pub type Backends = HashMap<&'static str, Arc<dyn Backend>>;
 
-pub fn backends(locale: &str) -> Backends {
+/// Names of the backends compiled into this build
+pub fn backend_names() -> Vec<&'static str> {
+    let mut names = Vec::new();
+    #[cfg(feature = "appimage")]
+    names.push("appimage");
+    #[cfg(feature = "flatpak")]
+    names.extend(["flatpak-user", "flatpak-system"]);
+    #[cfg(feature = "packagekit")]
+    names.push("packagekit");
+    #[cfg(feature = "fwupd")]
+    names.push("fwupd");
+    #[cfg(feature = "snap")]
+    names.push("snap");
+    names
+}
+
+/// Create a backend by name, without loading its cache
+fn new_backend(
+    backend_name: &str,
+    locale: &str,
+    config: &Config,
+) -> Result<Arc<dyn Backend>, Box<dyn Error>> {
+    match backend_name {
+        #[cfg(feature = "appimage")]
+        "appimage" => Ok(Arc::new(appimage::Appimage::new(
+            locale,
+            config.appimage_dir.as_deref(),
+        )?)),
+        #[cfg(feature = "flatpak")]
+        "flatpak-user" => Ok(Arc::new(flatpak::Flatpak::new(locale, true)?)),
+        #[cfg(feature = "flatpak")]
+        "flatpak-system" => Ok(Arc::new(flatpak::Flatpak::new(locale, false)?)),
+        #[cfg(feature = "packagekit")]
+        "packagekit" => Ok(Arc::new(packagekit::Packagekit::new(locale)?)),
+        #[cfg(feature = "fwupd")]
+        "fwupd" => Ok(Arc::new(fwupd::Fwupd::new(locale)?)),
+        #[cfg(feature = "snap")]
+        "snap" => Ok(Arc::new(snap::Snap::new(
+            locale,
+            Path::new(snap::SNAPD_SOCKET),
+        )?)),
+        _ => Err(format!("unknown backend {:?}", backend_name).into()),
+    }
+}
+
+fn load_cache(backend_name: &str, backend: &mut Arc<dyn Backend>) {
+    let start = Instant::now();
+    match Arc::get_mut(backend).unwrap().load_cache() {
+        Ok(()) => {
+            let duration = start.elapsed();
+            log::info!("loaded {} backend cache in {:?}", backend_name, duration);
+        }
+        Err(err) => {
+            log::error!("failed to load {} backend cache: {}", backend_name, err);
+        }
+    }
+}
+
+fn malloc_trim() {
+    //TODO: Workaround for xml-rs memory leak when loading appstream data
+    let start = Instant::now();
+    unsafe {
+        libc::malloc_trim(0);
+    }
+    let duration = start.elapsed();
+    log::info!("trimmed allocations in {:?}", duration);
+}
+
+pub fn backends(locale: &str, config: &Config) -> Backends {
     let mut backends = Backends::new();
 
-    #[cfg(feature = "flatpak")]
-    {
+    for backend_name in backend_names() {
+        if !config.enabled_backends.iter().any(|x| x == backend_name) {
+            continue;
+        }
         let start = Instant::now();
-        match flatpak::Flatpak::new(locale) {
+        match new_backend(backend_name, locale, config) {
             Ok(backend) => {
-                backends.insert("flatpak", Arc::new(backend));
+                backends.insert(backend_name, backend);
                 let duration = start.elapsed();
-                log::info!("initialized flatpak backend in {:?}", duration);
+                log::info!("initialized {} backend in {:?}", backend_name, duration);
             }
             Err(err) => {
-                log::error!("failed to load flatpak backend: {}", err);
+                log::error!("failed to load {} backend: {}", backend_name, err);
             }
         }
     }
 
-    #[cfg(feature = "packagekit")]
-    {
-        let start = Instant::now();
-        match packagekit::Packagekit::new(locale) {
-            Ok(backend) => {
-                backends.insert("packagekit", Arc::new(backend));
-                let duration = start.elapsed();
-                log::info!("initialized packagekit backend in {:?}", duration);
-            }
+    backends
+        .par_iter_mut()
+        .for_each(|(backend_name, backend)| load_cache(backend_name, backend));
+
+    malloc_trim();
+
+    backends
+}
+
+/// Create a backend again and load its cache, used when its appstream data changes
+pub fn reload_backend(
+    backend_name: &'static str,
+    locale: &str,
+    config: &Config,
+) -> Result<Arc<dyn Backend>, Box<dyn Error>> {
+    let mut backend = new_backend(backend_name, locale, config)?;
+    load_cache(backend_name, &mut backend);
+    malloc_trim();
+    Ok(backend)
+}
+
+/// Offline update state of the backends that support offline updates
+pub fn offline_states(backends: &Backends) -> Vec<(&'static str, OfflineState)> {
+    let mut offline_states = Vec::new();
+    for (backend_name, backend) in backends.iter() {
+        if !backend.capabilities().offline_updates {
+            continue;
+        }
+        match backend.offline_state() {
+            Ok(offline_state) => offline_states.push((*backend_name, offline_state)),
             Err(err) => {
-                log::error!("failed to load packagekit backend: {}", err);
+                log::error!(
+                    "failed to get offline update state from {}: {}",
+                    backend_name,
+                    err
+                );
             }
         }
     }
+    offline_states
+}
 
-    backends.par_iter_mut().for_each(|(backend_name, backend)| {
-        let start = Instant::now();
-        match Arc::get_mut(backend).unwrap().load_cache() {
-            Ok(()) => {
-                let duration = start.elapsed();
-                log::info!("loaded {} backend cache in {:?}", backend_name, duration);
+/// Run an offline update request on a backend, then get the offline update state of all
+/// backends, which includes the changes made by the request
+pub fn offline_request(
+    backends: &Backends,
+    backend_name: &'static str,
+    f: fn(&dyn Backend) -> Result<(), Box<dyn Error>>,
+) -> Vec<(&'static str, OfflineState)> {
+    match backends.get(backend_name) {
+        Some(backend) => {
+            if let Err(err) = f(backend.as_ref()) {
+                log::error!("offline update request to {} failed: {}", backend_name, err);
             }
-            Err(err) => {
-                log::error!("failed to load {} backend cache: {}", backend_name, err);
+        }
+        None => {
+            log::error!("failed to find backend {:?}", backend_name);
+        }
+    }
+    offline_states(backends)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+    use crate::OperationKind;
+
+    /// Stand-in for a backend that installs updates on the next boot, like PackageKit
+    #[derive(Debug, Default)]
+    struct OfflineBackend {
+        info_cache: AppstreamCache,
+        state: Mutex<OfflineState>,
+    }
+
+    impl Backend for OfflineBackend {
+        fn capabilities(&self) -> Capabilities {
+            Capabilities {
+                updates: true,
+                offline_updates: true,
+                local_files: false,
+                permissions: false,
+                ..Default::default()
             }
         }
-    });
 
-    //TODO: Workaround for xml-rs memory leak when loading appstream data
-    {
-        let start = Instant::now();
-        unsafe {
-            libc::malloc_trim(0);
+        fn load_cache(&mut self) -> Result<(), Box<dyn Error>> {
+            Ok(())
+        }
+
+        fn installed(&self) -> Result<Vec<Package>, Box<dyn Error>> {
+            Ok(Vec::new())
+        }
+
+        fn updates(&self) -> Result<Vec<Package>, Box<dyn Error>> {
+            Ok(Vec::new())
+        }
+
+        fn sources(&self) -> Result<Vec<String>, Box<dyn Error>> {
+            Ok(Vec::new())
+        }
+
+        fn offline_state(&self) -> Result<OfflineState, Box<dyn Error>> {
+            Ok(self.state.lock().unwrap().clone())
+        }
+
+        fn offline_clear_result(&self) -> Result<(), Box<dyn Error>> {
+            self.state.lock().unwrap().last_result_opt = None;
+            Ok(())
+        }
+
+        fn offline_reboot(&self) -> Result<(), Box<dyn Error>> {
+            let mut state = self.state.lock().unwrap();
+            if !state.triggered {
+                return Err("no offline update was triggered".into());
+            }
+            // Pretend the system restarted and installed the updates
+            *state = OfflineState {
+                prepared: false,
+                triggered: false,
+                last_result_opt: Some(OfflineResult {
+                    success: true,
+                    packages: vec!["app".to_string()],
+                    error_opt: None,
+                }),
+            };
+            Ok(())
+        }
+
+        fn operation(
+            &self,
+            op: &Operation,
+            _f: Box<dyn FnMut(f32) + 'static>,
+        ) -> Result<(), Box<dyn Error>> {
+            match op.kind {
+                OperationKind::OfflineUpdate => {
+                    let mut state = self.state.lock().unwrap();
+                    state.prepared = true;
+                    state.triggered = true;
+                    Ok(())
+                }
+                _ => Err(format!("{:?} is not supported", op.kind).into()),
+            }
+        }
+
+        fn info_cache(&self) -> &AppstreamCache {
+            &self.info_cache
         }
-        let duration = start.elapsed();
-        log::info!("trimmed allocations in {:?}", duration);
     }
 
-    backends
+    /// Stand-in for a backend without offline updates
+    #[derive(Debug, Default)]
+    struct OnlineBackend {
+        info_cache: AppstreamCache,
+    }
+
+    impl Backend for OnlineBackend {
+        fn capabilities(&self) -> Capabilities {
+            Capabilities {
+                updates: true,
+                ..Default::default()
+            }
+        }
+
+        fn load_cache(&mut self) -> Result<(), Box<dyn Error>> {
+            Ok(())
+        }
+
+        fn installed(&self) -> Result<Vec<Package>, Box<dyn Error>> {
+            Ok(Vec::new())
+        }
+
+        fn updates(&self) -> Result<Vec<Package>, Box<dyn Error>> {
+            Ok(Vec::new())
+        }
+
+        fn sources(&self) -> Result<Vec<String>, Box<dyn Error>> {
+            Ok(Vec::new())
+        }
+
+        fn operation(
+            &self,
+            _op: &Operation,
+            _f: Box<dyn FnMut(f32) + 'static>,
+        ) -> Result<(), Box<dyn Error>> {
+            Ok(())
+        }
+
+        fn info_cache(&self) -> &AppstreamCache {
+            &self.info_cache
+        }
+    }
+
+    fn test_backends() -> Backends {
+        let mut backends = Backends::new();
+        backends.insert("offline", Arc::new(OfflineBackend::default()));
+        backends.insert("online", Arc::new(OnlineBackend::default()));
+        backends
+    }
+
+    fn offline_update(backends: &Backends) {
+        let op = Operation {
+            kind: OperationKind::OfflineUpdate,
+            backend_name: "offline",
+            package_id: String::new(),
+            info: Arc::new(AppInfo::default()),
+            branch_opt: None,
+        };
+        backends["offline"]
+            .operation(&op, Box::new(|_progress| {}))
+            .unwrap();
+    }
+
+    #[test]
+    fn offline_states_only_lists_offline_backends() {
+        let backends = test_backends();
+        assert_eq!(
+            offline_states(&backends),
+            vec![("offline", OfflineState::default())]
+        );
+    }
+
+    #[test]
+    fn offline_update_cycle() {
+        let backends = test_backends();
+
+        offline_update(&backends);
+        let states = offline_states(&backends);
+        assert!(states[0].1.prepared && states[0].1.triggered);
+
+        // The state returned by a request is read after the request finished
+        let states = offline_request(&backends, "offline", |backend| backend.offline_reboot());
+        assert!(!states[0].1.triggered);
+        assert_eq!(
+            states[0].1.last_result_opt.as_ref().map(|x| x.success),
+            Some(true)
+        );
+
+        let states = offline_request(&backends, "offline", |backend| {
+            backend.offline_clear_result()
+        });
+        assert_eq!(states, vec![("offline", OfflineState::default())]);
+    }
+
+    #[test]
+    fn failed_offline_request_keeps_state() {
+        let backends = test_backends();
+
+        // Restarting without a triggered update fails and changes nothing
+        let states = offline_request(&backends, "offline", |backend| backend.offline_reboot());
+        assert_eq!(states, vec![("offline", OfflineState::default())]);
+
+        // Unknown backends are skipped, but states are still returned
+        let states = offline_request(&backends, "missing", |backend| backend.offline_reboot());
+        assert_eq!(states, vec![("offline", OfflineState::default())]);
+    }
 }
@@ -1,16 +1,93 @@
 use packagekit_zbus::{
-    zbus::blocking::Connection, PackageKit::PackageKitProxyBlocking,
+    zbus::{self, blocking::Connection, Message},
+    PackageKit::PackageKitProxyBlocking,
     Transaction::TransactionProxyBlocking,
 };
-use std::{collections::HashMap, error::Error};
+use std::{collections::HashMap, error::Error, fs, sync::Arc};
+use zbus::dbus_proxy;
 
-use super::{Backend, Package};
-use crate::AppstreamCache;
+use super::{Backend, Capabilities, OfflineResult, OfflineState, Package};
+use crate::{AppstreamCache, Operation, OperationKind};
+
+// Written by PackageKit after an offline update, the misspelling is intentional
+const OFFLINE_RESULTS_PATH: &str = "/var/lib/PackageKit/offline-update-competed";
+
+// https://lazka.github.io/pgi-docs/PackageKitGlib-1.0/flags.html#PackageKitGlib.TransactionFlagEnum
+const TRANSACTION_FLAG_ONLY_DOWNLOAD: u64 = 1 << 3;
+
+#[dbus_proxy(
+    interface = "org.freedesktop.PackageKit.Offline",
+    default_service = "org.freedesktop.PackageKit",
+    default_path = "/org/freedesktop/PackageKit"
+)]
+trait Offline {
+    fn trigger(&self, action: &str) -> zbus::Result<()>;
+
+    fn clear_results(&self) -> zbus::Result<()>;
+
+    #[dbus_proxy(property)]
+    fn update_prepared(&self) -> zbus::Result<bool>;
+
+    #[dbus_proxy(property)]
+    fn update_triggered(&self) -> zbus::Result<bool>;
+}
+
+#[dbus_proxy(
+    interface = "org.freedesktop.login1.Manager",
+    default_service = "org.freedesktop.login1",
+    default_path = "/org/freedesktop/login1"
+)]
+trait Login1Manager {
+    fn reboot(&self, interactive: bool) -> zbus::Result<()>;
+}
+
+/// Read the result of the last offline update
+fn offline_result() -> Option<OfflineResult> {
+    match fs::read_to_string(OFFLINE_RESULTS_PATH) {
+        Ok(data) => Some(parse_offline_result(&data)),
+        // There is no result until an offline update has run
+        Err(_) => None,
+    }
+}
+
+/// Parse the key file with the result of an offline update
+fn parse_offline_result(data: &str) -> OfflineResult {
+    let mut result = OfflineResult {
+        success: false,
+        packages: Vec::new(),
+        error_opt: None,
+    };
+    for line in data.lines() {
+        match line.split_once('=') {
+            Some(("Success", value)) => result.success = value.trim() == "true",
+            Some(("Packages", value)) => {
+                // Package IDs contain semicolons, so they are separated by commas
+                result.packages = value
+                    .split(',')
+                    .map(|x| x.trim())
+                    .filter(|x| !x.is_empty())
+                    .map(|x| package_name(x).to_string())
+                    .collect();
+            }
+            Some(("ErrorDetails", value)) => result.error_opt = Some(value.trim().to_string()),
+            _ => {}
+        }
+    }
+    result
+}
 
 // https://lazka.github.io/pgi-docs/PackageKitGlib-1.0/enums.html#PackageKitGlib.FilterEnum
 #[repr(u64)]
 enum FilterKind {
+    None = 1 << 1,
     Installed = 1 << 2,
+    NotInstalled = 1 << 3,
+    Newest = 1 << 16,
+    Arch = 1 << 18,
+}
+
+fn package_name(package_id: &str) -> &str {
+    package_id.split(';').next().unwrap_or(package_id)
 }
 
 #[derive(Debug)]
@@ -41,30 +118,69 @@ impl Packagekit {
         Ok(tx)
     }
 
-    fn packages(&self, filter: FilterKind) -> Result<Vec<Package>, Box<dyn Error>> {
-        let mut package_ids = Vec::new();
-        {
-            let tx = self.transaction()?;
-            tx.get_packages(filter as u64)?;
-            for signal in tx.receive_all_signals()? {
-                match signal.member() {
-                    Some(member) => {
-                        if member == "Package" {
-                            // https://www.freedesktop.org/software/PackageKit/gtk-doc/Transaction.html#Transaction::Package
-                            let (_info, package_id, _summary) =
-                                signal.body::<(u32, String, String)>()?;
-                            package_ids.push(package_id);
-                        } else if member == "Finished" {
-                            break;
-                        } else {
-                            log::warn!("unknown signal {}", member);
+    /// Run a transaction method until it finishes, reporting progress and returning data signals
+    fn run_transaction<M: FnOnce(&TransactionProxyBlocking) -> zbus::Result<()>>(
+        &self,
+        method: M,
+        f: &mut dyn FnMut(f32),
+    ) -> Result<Vec<Arc<Message>>, Box<dyn Error>> {
+        let tx = self.transaction()?;
+        // Subscribe before calling the method so no signals are missed
+        let signals = tx.receive_all_signals()?;
+        method(&tx)?;
+        let mut data = Vec::new();
+        for signal in signals {
+            match signal.member() {
+                Some(member) => {
+                    if member == "ItemProgress" {
+                        // https://www.freedesktop.org/software/PackageKit/gtk-doc/Transaction.html#Transaction::ItemProgress
+                        let (_package_id, _status, percentage) =
+                            signal.body::<(String, u32, u32)>()?;
+                        // A percentage of 101 means unknown
+                        if percentage <= 100 {
+                            f(percentage as f32);
                         }
+                    } else if member == "ErrorCode" {
+                        let (code, details) = signal.body::<(u32, String)>()?;
+                        return Err(format!("packagekit error {}: {}", code, details).into());
+                    } else if member == "Finished" {
+                        break;
+                    } else {
+                        data.push(signal);
                     }
-                    None => {}
                 }
+                None => {}
             }
         }
+        Ok(data)
+    }
 
+    /// Run a transaction method and return the package IDs it emitted
+    fn transaction_package_ids<M: FnOnce(&TransactionProxyBlocking) -> zbus::Result<()>>(
+        &self,
+        method: M,
+        f: &mut dyn FnMut(f32),
+    ) -> Result<Vec<String>, Box<dyn Error>> {
+        let mut package_ids = Vec::new();
+        for signal in self.run_transaction(method, f)? {
+            match signal.member() {
+                Some(member) => {
+                    if member == "Package" {
+                        // https://www.freedesktop.org/software/PackageKit/gtk-doc/Transaction.html#Transaction::Package
+                        let (_info, package_id, _summary) =
+                            signal.body::<(u32, String, String)>()?;
+                        package_ids.push(package_id);
+                    } else {
+                        log::warn!("unknown signal {}", member);
+                    }
+                }
+                None => {}
+            }
+        }
+        Ok(package_ids)
+    }
+
+    fn packages_for_ids(&self, package_ids: Vec<String>) -> Vec<Package> {
         let mut packages = Vec::new();
         for package_id in package_ids {
             let mut parts = package_id.split(';');
@@ -104,11 +220,45 @@ impl Packagekit {
                 }
             }
         }
-        Ok(packages)
+        packages
+    }
+
+    fn packages(&self, filter: FilterKind) -> Result<Vec<Package>, Box<dyn Error>> {
+        let package_ids =
+            self.transaction_package_ids(|tx| tx.get_packages(filter as u64), &mut |_| {})?;
+        Ok(self.packages_for_ids(package_ids))
+    }
+
+    /// Resolve the package name of an operation to package IDs
+    fn resolve(&self, op: &Operation, filter: u64) -> Result<Vec<String>, Box<dyn Error>> {
+        let pkgname = op
+            .info
+            .pkgname
+            .as_deref()
+            .ok_or_else(|| format!("{} has no package name", op.package_id))?;
+        let package_ids =
+            self.transaction_package_ids(|tx| tx.resolve(filter, &[pkgname]), &mut |_| {})?;
+        if package_ids.is_empty() {
+            return Err(format!("failed to resolve package {}", pkgname).into());
+        }
+        Ok(package_ids)
     }
 }
 
 impl Backend for Packagekit {
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            install: true,
+            uninstall: true,
+            updates: true,
+            offline_updates: true,
+            repos: true,
+            local_files: false,
+            permissions: false,
+            ..Default::default()
+        }
+    }
+
     fn load_cache(&mut self) -> Result<(), Box<dyn Error>> {
         self.appstream_cache.reload("packagekit");
         Ok(())
@@ -118,7 +268,262 @@ impl Backend for Packagekit {
         self.packages(FilterKind::Installed)
     }
 
+    fn updates(&self) -> Result<Vec<Package>, Box<dyn Error>> {
+        let package_ids = self
+            .transaction_package_ids(|tx| tx.get_updates(FilterKind::None as u64), &mut |_| {})?;
+        Ok(self.packages_for_ids(package_ids))
+    }
+
+    fn sources(&self) -> Result<Vec<String>, Box<dyn Error>> {
+        let mut sources = Vec::new();
+        for signal in
+            self.run_transaction(|tx| tx.get_repo_list(FilterKind::None as u64), &mut |_| {})?
+        {
+            if signal
+                .member()
+                .map_or(false, |member| member == "RepoDetail")
+            {
+                // https://www.freedesktop.org/software/PackageKit/gtk-doc/Transaction.html#Transaction::RepoDetail
+                let (repo_id, description, enabled) = signal.body::<(String, String, bool)>()?;
+                if enabled {
+                    sources.push(if description.is_empty() {
+                        repo_id
+                    } else {
+                        description
+                    });
+                }
+            }
+        }
+        Ok(sources)
+    }
+
+    fn operation(
+        &self,
+        op: &Operation,
+        mut f: Box<dyn FnMut(f32) + 'static>,
+    ) -> Result<(), Box<dyn Error>> {
+        match op.kind {
+            OperationKind::Install => {
+                let package_ids = self.resolve(
+                    op,
+                    FilterKind::NotInstalled as u64
+                        | FilterKind::Newest as u64
+                        | FilterKind::Arch as u64,
+                )?;
+                let package_ids: Vec<&str> = package_ids.iter().map(|x| x.as_str()).collect();
+                self.run_transaction(|tx| tx.install_packages(0, &package_ids), &mut f)?;
+            }
+            OperationKind::Uninstall => {
+                let package_ids = self.resolve(op, FilterKind::Installed as u64)?;
+                let package_ids: Vec<&str> = package_ids.iter().map(|x| x.as_str()).collect();
+                self.run_transaction(
+                    |tx| tx.remove_packages(0, &package_ids, false, true),
+                    &mut f,
+                )?;
+            }
+            OperationKind::Update => {
+                let pkgname = op.info.pkgname.as_deref().unwrap_or_default();
+                let package_ids: Vec<String> = self
+                    .transaction_package_ids(
+                        |tx| tx.get_updates(FilterKind::None as u64),
+                        &mut |_| {},
+                    )?
+                    .into_iter()
+                    .filter(|package_id| package_name(package_id) == pkgname)
+                    .collect();
+                if package_ids.is_empty() {
+                    return Err(format!("no updates for package {}", pkgname).into());
+                }
+                let package_ids: Vec<&str> = package_ids.iter().map(|x| x.as_str()).collect();
+                self.run_transaction(|tx| tx.update_packages(0, &package_ids), &mut f)?;
+            }
+            OperationKind::OfflineUpdate => {
+                let package_ids = self.transaction_package_ids(
+                    |tx| tx.get_updates(FilterKind::None as u64),
+                    &mut |_| {},
+                )?;
+                if package_ids.is_empty() {
+                    return Err("no updates to download".into());
+                }
+                let package_ids: Vec<&str> = package_ids.iter().map(|x| x.as_str()).collect();
+                self.run_transaction(
+                    |tx| tx.update_packages(TRANSACTION_FLAG_ONLY_DOWNLOAD, &package_ids),
+                    &mut f,
+                )?;
+                OfflineProxyBlocking::new(&self.connection)?.trigger("reboot")?;
+            }
+            OperationKind::Integrate | OperationKind::Rebase => {
+                return Err(format!("{:?} is not supported by packagekit", op.kind).into());
+            }
+        }
+        Ok(())
+    }
+
+    fn what_provides(&self, mimetype: &str) -> Result<Vec<Package>, Box<dyn Error>> {
+        // Backends match either the plain MIME type or the RPM style mimehandler provide
+        let mimehandler = format!("mimehandler({})", mimetype);
+        let package_ids = self.transaction_package_ids(
+            |tx| {
+                tx.what_provides(
+                    FilterKind::Newest as u64 | FilterKind::Arch as u64,
+                    &[mimetype, &mimehandler],
+                )
+            },
+            &mut |_| {},
+        )?;
+        Ok(self.packages_for_ids(package_ids))
+    }
+
+    fn offline_state(&self) -> Result<OfflineState, Box<dyn Error>> {
+        let offline = OfflineProxyBlocking::new(&self.connection)?;
+        Ok(OfflineState {
+            prepared: offline.update_prepared()?,
+            triggered: offline.update_triggered()?,
+            last_result_opt: offline_result(),
+        })
+    }
+
+    fn offline_clear_result(&self) -> Result<(), Box<dyn Error>> {
+        OfflineProxyBlocking::new(&self.connection)?.clear_results()?;
+        Ok(())
+    }
+
+    fn offline_reboot(&self) -> Result<(), Box<dyn Error>> {
+        // Interactive so polkit can ask for authentication when needed
+        Login1ManagerProxyBlocking::new(&self.connection)?.reboot(true)?;
+        Ok(())
+    }
+
     fn info_cache(&self) -> &AppstreamCache {
         &self.appstream_cache
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Mutex, thread};
+
+    use super::*;
+    use packagekit_zbus::zbus::{blocking::ConnectionBuilder, dbus_interface, Guid};
+
+    #[test]
+    fn offline_result_success() {
+        let result = parse_offline_result(
+            "[PackageKit Offline Update Results]\n\
+             Success=true\n\
+             Packages=bash;5.2.15-3;amd64;debian,vim;2:9.0.1378-2;amd64;debian,\n",
+        );
+        assert_eq!(
+            result,
+            OfflineResult {
+                success: true,
+                packages: vec!["bash".to_string(), "vim".to_string()],
+                error_opt: None,
+            }
+        );
+    }
+
+    #[test]
+    fn offline_result_failure() {
+        let result = parse_offline_result(
+            "[PackageKit Offline Update Results]\n\
+             Success=false\n\
+             ErrorCode=package-conflicts\n\
+             ErrorDetails=bash conflicts with sh\n",
+        );
+        assert!(!result.success);
+        assert!(result.packages.is_empty());
+        assert_eq!(result.error_opt.as_deref(), Some("bash conflicts with sh"));
+    }
+
+    #[test]
+    fn offline_result_empty() {
+        let result = parse_offline_result("");
+        assert!(!result.success);
+        assert!(result.packages.is_empty());
+        assert_eq!(result.error_opt, None);
+    }
+
+    /// Stand-in for the PackageKit offline interface, recording the methods called
+    struct OfflineStandIn {
+        prepared: bool,
+        calls: Arc<Mutex<Vec<String>>>,
+    }
+
+    #[dbus_interface(name = "org.freedesktop.PackageKit.Offline")]
+    impl OfflineStandIn {
+        fn trigger(&self, action: String) {
+            self.calls
+                .lock()
+                .unwrap()
+                .push(format!("trigger {}", action));
+        }
+
+        fn clear_results(&self) {
+            self.calls.lock().unwrap().push("clear".to_string());
+        }
+
+        #[dbus_interface(property)]
+        fn update_prepared(&self) -> bool {
+            self.prepared
+        }
+
+        #[dbus_interface(property)]
+        fn update_triggered(&self) -> bool {
+            self.calls
+                .lock()
+                .unwrap()
+                .iter()
+                .any(|call| call == "trigger reboot")
+        }
+    }
+
+    #[test]
+    fn offline_proxy() {
+        // zbus uses tokio streams, so connections are made inside a runtime
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let _guard = runtime.enter();
+        let (server_stream, client_stream) = tokio::net::UnixStream::pair().unwrap();
+
+        // Serve the stand-in over a peer to peer connection instead of the system bus
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let stand_in = OfflineStandIn {
+            prepared: true,
+            calls: calls.clone(),
+        };
+        let handle = runtime.handle().clone();
+        let server = thread::spawn(move || {
+            let _guard = handle.enter();
+            let guid = Guid::generate();
+            ConnectionBuilder::unix_stream(server_stream)
+                .server(&guid)
+                .p2p()
+                .serve_at("/org/freedesktop/PackageKit", stand_in)
+                .unwrap()
+                .build()
+                .unwrap()
+        });
+        let connection = ConnectionBuilder::unix_stream(client_stream)
+            .p2p()
+            .build()
+            .unwrap();
+        let _server_connection = server.join().unwrap();
+
+        let backend = Packagekit {
+            connection,
+            appstream_cache: AppstreamCache::default(),
+        };
+        let state = backend.offline_state().unwrap();
+        assert!(state.prepared);
+        assert!(!state.triggered);
+
+        OfflineProxyBlocking::new(&backend.connection)
+            .unwrap()
+            .trigger("reboot")
+            .unwrap();
+        assert!(backend.offline_state().unwrap().triggered);
+
+        backend.offline_clear_result().unwrap();
+        assert_eq!(*calls.lock().unwrap(), ["trigger reboot", "clear"]);
+    }
+}
@@ -0,0 +1,509 @@
+use cosmic::widget;
+use serde_json::Value;
+use std::{
+    collections::HashMap,
+    error::Error,
+    io::{Read, Write},
+    os::unix::net::UnixStream,
+    path::{Path, PathBuf},
+    sync::Arc,
+    thread,
+    time::Duration,
+};
+
+use super::{Backend, Capabilities, Package};
+use crate::{AppIcon, AppInfo, AppstreamCache, Description, Operation, OperationKind};
+
+/// Default path of the snapd REST API socket
+pub const SNAPD_SOCKET: &str = "/run/snapd.socket";
+
+/// Percent encode a query parameter
+fn encode(value: &str) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                encoded.push(byte as char)
+            }
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+fn json_str<'a>(value: &'a Value, key: &str) -> &'a str {
+    value[key].as_str().unwrap_or_default()
+}
+
+#[derive(Debug)]
+pub struct Snap {
+    socket_path: PathBuf,
+    appstream_cache: AppstreamCache,
+}
+
+impl Snap {
+    pub fn new(locale: &str, socket_path: &Path) -> Result<Self, Box<dyn Error>> {
+        let socket_path = socket_path.to_path_buf();
+        if !socket_path.exists() {
+            return Err(format!("snapd socket {:?} not found", socket_path).into());
+        }
+        // Snaps have no appstream catalog, this cache stays empty
+        Ok(Self {
+            socket_path,
+            appstream_cache: AppstreamCache::new(Vec::new(), Vec::new(), locale),
+        })
+    }
+
+    /// Send a request to the snapd REST API and return the result of the response
+    fn request(
+        &self,
+        method: &str,
+        path: &str,
+        body_opt: Option<Value>,
+    ) -> Result<Value, Box<dyn Error>> {
+        let mut stream = UnixStream::connect(&self.socket_path)?;
+        // HTTP/1.0 makes snapd close the connection instead of using chunked encoding
+        let mut request = format!("{} {} HTTP/1.0\r\nHost: localhost\r\n", method, path);
+        let body = body_opt.map(|x| x.to_string()).unwrap_or_default();
+        if !body.is_empty() {
+            request.push_str("Content-Type: application/json\r\n");
+            // Allow polkit to ask for authentication
+            request.push_str("X-Allow-Interaction: true\r\n");
+            request.push_str(&format!("Content-Length: {}\r\n", body.len()));
+        }
+        request.push_str("\r\n");
+        request.push_str(&body);
+        stream.write_all(request.as_bytes())?;
+
+        let mut response = String::new();
+        stream.read_to_string(&mut response)?;
+        let (_headers, response_body) = response
+            .split_once("\r\n\r\n")
+            .ok_or_else(|| format!("invalid snapd response to {} {}", method, path))?;
+        let json: Value = serde_json::from_str(response_body)?;
+        if json["type"] == "error" {
+            return Err(format!(
+                "snapd error for {} {}: {}",
+                method,
+                path,
+                json_str(&json["result"], "message")
+            )
+            .into());
+        }
+        Ok(json)
+    }
+
+    fn icon(&self, snap: &Value) -> widget::icon::Handle {
+        let name = json_str(snap, "name");
+        for file_name in ["icon.svg", "icon.png"] {
+            let path = Path::new("/snap")
+                .join(name)
+                .join("current/meta/gui")
+                .join(file_name);
+            if path.is_file() {
+                return widget::icon::from_path(path);
+            }
+        }
+        widget::icon::from_name("package-x-generic")
+            .size(128)
+            .handle()
+    }
+
+    fn package(&self, snap: &Value) -> Package {
+        let name = json_str(snap, "name");
+        let title = json_str(snap, "title");
+        let mut extra = HashMap::new();
+        if let Some(channel) = snap["channel"].as_str() {
+            extra.insert("channel".to_string(), channel.to_string());
+        }
+        if let Some(description) = snap["description"].as_str() {
+            extra.insert("description".to_string(), description.to_string());
+        }
+        let desktop_ids: Vec<String> = snap["apps"]
+            .as_array()
+            .map(|apps| {
+                apps.iter()
+                    .filter_map(|app| {
+                        let desktop_file = app["desktop-file"].as_str()?;
+                        Some(Path::new(desktop_file).file_name()?.to_str()?.to_string())
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        extra.insert("desktop_ids".to_string(), desktop_ids.join(";"));
+        Package {
+            id: name.to_string(),
+            icon: self.icon(snap),
+            name: if title.is_empty() { name } else { title }.to_string(),
+            summary: json_str(snap, "summary").to_string(),
+            origin_opt: Some("snapcraft".to_string()),
+            version: json_str(snap, "version").to_string(),
+            extra,
+        }
+    }
+
+    /// Poll a change until it is ready, reporting task progress
+    fn wait_change(&self, change_id: &str, f: &mut dyn FnMut(f32)) -> Result<(), Box<dyn Error>> {
+        let path = format!("/v2/changes/{}", change_id);
+        loop {
+            let change = &self.request("GET", &path, None)?["result"];
+            let mut done = 0;
+            let mut total = 0;
+            for task in change["tasks"].as_array().into_iter().flatten() {
+                done += task["progress"]["done"].as_u64().unwrap_or(0);
+                total += task["progress"]["total"].as_u64().unwrap_or(0);
+            }
+            if total > 0 {
+                f(100.0 * done as f32 / total as f32);
+            }
+            if change["ready"].as_bool().unwrap_or(false) {
+                return match json_str(change, "status") {
+                    "Done" => Ok(()),
+                    status => Err(format!(
+                        "snapd change {} {}: {}",
+                        change_id,
+                        status,
+                        json_str(change, "err")
+                    )
+                    .into()),
+                };
+            }
+            thread::sleep(Duration::from_millis(250));
+        }
+    }
+}
+
+impl Backend for Snap {
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            install: true,
+            uninstall: true,
+            updates: true,
+            local_files: false,
+            permissions: false,
+            ..Default::default()
+        }
+    }
+
+    fn load_cache(&mut self) -> Result<(), Box<dyn Error>> {
+        Ok(())
+    }
+
+    fn installed(&self) -> Result<Vec<Package>, Box<dyn Error>> {
+        let json = self.request("GET", "/v2/snaps", None)?;
+        Ok(json["result"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            // Only list snaps with desktop apps
+            .filter(|snap| {
+                snap["apps"].as_array().map_or(false, |apps| {
+                    apps.iter().any(|app| app["desktop-file"].is_string())
+                })
+            })
+            .map(|snap| self.package(snap))
+            .collect())
+    }
+
+    fn updates(&self) -> Result<Vec<Package>, Box<dyn Error>> {
+        let json = self.request("GET", "/v2/find?select=refresh", None)?;
+        Ok(json["result"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .map(|snap| self.package(snap))
+            .collect())
+    }
+
+    fn sources(&self) -> Result<Vec<String>, Box<dyn Error>> {
+        Ok(Vec::new())
+    }
+
+    fn search(&self, input: &str) -> Result<Vec<Package>, Box<dyn Error>> {
+        let json = self.request("GET", &format!("/v2/find?q={}", encode(input)), None)?;
+        Ok(json["result"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .map(|snap| self.package(snap))
+            .collect())
+    }
+
+    fn operation(
+        &self,
+        op: &Operation,
+        mut f: Box<dyn FnMut(f32) + 'static>,
+    ) -> Result<(), Box<dyn Error>> {
+        let action = match op.kind {
+            OperationKind::Install => "install",
+            OperationKind::Uninstall => "remove",
+            OperationKind::Update => "refresh",
+            OperationKind::Integrate | OperationKind::OfflineUpdate | OperationKind::Rebase => {
+                return Err(format!("{:?} is not supported by snap", op.kind).into());
+            }
+        };
+        let json = self.request(
+            "POST",
+            &format!("/v2/snaps/{}", encode(&op.package_id)),
+            Some(serde_json::json!({ "action": action })),
+        )?;
+        let change_id = json["change"]
+            .as_str()
+            .ok_or_else(|| format!("snapd did not return a change for {}", op.package_id))?;
+        self.wait_change(change_id, &mut f)
+    }
+
+    fn info(&self, package: &Package) -> Result<Arc<AppInfo>, Box<dyn Error>> {
+        Ok(Arc::new(AppInfo {
+            origin_opt: package.origin_opt.clone(),
+            name: package.name.clone(),
+            summary: package.summary.clone(),
+            icons: vec![AppIcon::Stock("package-x-generic".to_string())],
+            desktop_ids: package
+                .extra
+                .get("desktop_ids")
+                .map(|x| {
+                    x.split(';')
+                        .filter(|x| !x.is_empty())
+                        .map(|x| x.to_string())
+                        .collect()
+                })
+                .unwrap_or_default(),
+            description: package
+                .extra
+                .get("description")
+                .map(|x| Description::from_plain_text(x))
+                .unwrap_or_default(),
+            ..Default::default()
+        }))
+    }
+
+    fn info_cache(&self) -> &AppstreamCache {
+        &self.appstream_cache
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, os::unix::net::UnixListener, process, rc::Rc, thread::JoinHandle};
+
+    use super::*;
+
+    /// Read a request with its body from a client
+    fn read_request(stream: &mut UnixStream) -> String {
+        let mut headers = Vec::new();
+        let mut byte = [0];
+        while !headers.ends_with(b"\r\n\r\n") {
+            stream.read_exact(&mut byte).unwrap();
+            headers.push(byte[0]);
+        }
+        let headers = String::from_utf8(headers).unwrap();
+        let length = headers
+            .lines()
+            .find_map(|line| line.strip_prefix("Content-Length: "))
+            .map_or(0, |x| x.parse().unwrap());
+        let mut body = vec![0; length];
+        stream.read_exact(&mut body).unwrap();
+        headers + &String::from_utf8(body).unwrap()
+    }
+
+    /// Stand-in for snapd answering each connection with the next response, returns the
+    /// requests it received when all responses are sent
+    fn snapd(name: &str, responses: Vec<Value>) -> (PathBuf, JoinHandle<Vec<String>>) {
+        let socket_path = std::env::temp_dir().join(format!(
+            "cosmic-store-snapd-{}-{}.socket",
+            process::id(),
+            name
+        ));
+        let _ = std::fs::remove_file(&socket_path);
+        let listener = UnixListener::bind(&socket_path).unwrap();
+        let handle = thread::spawn(move || {
+            let mut requests = Vec::new();
+            for response in responses {
+                let (mut stream, _) = listener.accept().unwrap();
+                requests.push(read_request(&mut stream));
+                write!(
+                    stream,
+                    "HTTP/1.0 200 OK\r\nContent-Type: application/json\r\n\r\n{}",
+                    response
+                )
+                .unwrap();
+            }
+            requests
+        });
+        (socket_path, handle)
+    }
+
+    fn op(kind: OperationKind, package_id: &str) -> Operation {
+        Operation {
+            kind,
+            backend_name: "snap",
+            package_id: package_id.to_string(),
+            info: Arc::new(AppInfo::default()),
+            branch_opt: None,
+        }
+    }
+
+    #[test]
+    fn encode_query() {
+        assert_eq!(encode("gnome maps"), "gnome%20maps");
+        assert_eq!(encode("a+b&c=d"), "a%2Bb%26c%3Dd");
+        assert_eq!(encode("vlc-3.0_x~y"), "vlc-3.0_x~y");
+    }
+
+    #[test]
+    fn missing_socket() {
+        let socket_path = std::env::temp_dir().join("cosmic-store-snapd-missing.socket");
+        assert!(Snap::new("en", &socket_path).is_err());
+    }
+
+    #[test]
+    fn installed_snaps() {
+        let (socket_path, handle) = snapd(
+            "installed",
+            vec![serde_json::json!({
+                "type": "sync",
+                "result": [
+                    {
+                        "name": "firefox",
+                        "title": "Firefox",
+                        "summary": "Mozilla Firefox web browser",
+                        "version": "120.0",
+                        "channel": "latest/stable",
+                        "apps": [{
+                            "name": "firefox",
+                            "desktop-file": "/var/lib/snapd/desktop/applications/firefox_firefox.desktop"
+                        }]
+                    },
+                    {
+                        "name": "core22",
+                        "summary": "Runtime environment based on Ubuntu 22.04",
+                        "version": "20231123"
+                    }
+                ]
+            })],
+        );
+        let snap = Snap::new("en", &socket_path).unwrap();
+        let packages = snap.installed().unwrap();
+        let requests = handle.join().unwrap();
+        assert!(requests[0].starts_with("GET /v2/snaps HTTP/1.0\r\n"));
+        // Snaps without desktop apps are skipped
+        assert_eq!(packages.len(), 1);
+        let package = &packages[0];
+        assert_eq!(package.id, "firefox");
+        assert_eq!(package.name, "Firefox");
+        assert_eq!(package.version, "120.0");
+        assert_eq!(
+            package.extra.get("desktop_ids").map(|x| x.as_str()),
+            Some("firefox_firefox.desktop")
+        );
+        assert_eq!(
+            package.extra.get("channel").map(|x| x.as_str()),
+            Some("latest/stable")
+        );
+    }
+
+    #[test]
+    fn search_snaps() {
+        let (socket_path, handle) = snapd(
+            "search",
+            vec![serde_json::json!({
+                "type": "sync",
+                "result": [{
+                    "name": "gnome-maps",
+                    "summary": "Maps for GNOME",
+                    "version": "45.0"
+                }]
+            })],
+        );
+        let snap = Snap::new("en", &socket_path).unwrap();
+        let packages = snap.search("gnome maps").unwrap();
+        let requests = handle.join().unwrap();
+        assert!(requests[0].starts_with("GET /v2/find?q=gnome%20maps HTTP/1.0\r\n"));
+        // The name is used when there is no title
+        assert_eq!(packages[0].name, "gnome-maps");
+    }
+
+    #[test]
+    fn error_response() {
+        let (socket_path, handle) = snapd(
+            "error",
+            vec![serde_json::json!({
+                "type": "error",
+                "status-code": 404,
+                "result": { "message": "snap not installed" }
+            })],
+        );
+        let snap = Snap::new("en", &socket_path).unwrap();
+        let err = snap.installed().unwrap_err();
+        handle.join().unwrap();
+        assert!(err.to_string().contains("snap not installed"));
+    }
+
+    #[test]
+    fn install_waits_for_change() {
+        let (socket_path, handle) = snapd(
+            "install",
+            vec![
+                serde_json::json!({ "type": "async", "change": "7" }),
+                serde_json::json!({
+                    "type": "sync",
+                    "result": {
+                        "ready": false,
+                        "status": "Doing",
+                        "tasks": [{ "progress": { "done": 1, "total": 4 } }]
+                    }
+                }),
+                serde_json::json!({
+                    "type": "sync",
+                    "result": {
+                        "ready": true,
+                        "status": "Done",
+                        "tasks": [{ "progress": { "done": 4, "total": 4 } }]
+                    }
+                }),
+            ],
+        );
+        let snap = Snap::new("en", &socket_path).unwrap();
+        let progress = Rc::new(RefCell::new(Vec::new()));
+        let progress_f = progress.clone();
+        snap.operation(
+            &op(OperationKind::Install, "gnome-maps"),
+            Box::new(move |x| progress_f.borrow_mut().push(x)),
+        )
+        .unwrap();
+        let requests = handle.join().unwrap();
+        assert!(requests[0].starts_with("POST /v2/snaps/gnome-maps HTTP/1.0\r\n"));
+        assert!(requests[0].ends_with(r#"{"action":"install"}"#));
+        assert!(requests[1].starts_with("GET /v2/changes/7 HTTP/1.0\r\n"));
+        assert_eq!(*progress.borrow(), vec![25.0, 100.0]);
+    }
+
+    #[test]
+    fn failed_change() {
+        let (socket_path, handle) = snapd(
+            "failed",
+            vec![
+                serde_json::json!({ "type": "async", "change": "8" }),
+                serde_json::json!({
+                    "type": "sync",
+                    "result": {
+                        "ready": true,
+                        "status": "Error",
+                        "err": "cannot remove snap"
+                    }
+                }),
+            ],
+        );
+        let snap = Snap::new("en", &socket_path).unwrap();
+        let err = snap
+            .operation(
+                &op(OperationKind::Uninstall, "gnome-maps"),
+                Box::new(|_progress| {}),
+            )
+            .unwrap_err();
+        let requests = handle.join().unwrap();
+        assert!(requests[0].ends_with(r#"{"action":"remove"}"#));
+        assert!(err.to_string().contains("cannot remove snap"));
+    }
+}
@@ -0,0 +1,414 @@
+use crate::fl;
+
+/// Main categories of the freedesktop menu specification
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Category {
+    AudioVideo,
+    Development,
+    Education,
+    Game,
+    Graphics,
+    Network,
+    Office,
+    Science,
+    Settings,
+    System,
+    Utility,
+}
+
+impl Category {
+    pub fn all() -> &'static [Self] {
+        &[
+            Self::AudioVideo,
+            Self::Development,
+            Self::Education,
+            Self::Game,
+            Self::Graphics,
+            Self::Network,
+            Self::Office,
+            Self::Science,
+            Self::Settings,
+            Self::System,
+            Self::Utility,
+        ]
+    }
+
+    /// Category as written in desktop entries and appstream data
+    pub fn id(&self) -> &'static str {
+        match self {
+            Self::AudioVideo => "AudioVideo",
+            Self::Development => "Development",
+            Self::Education => "Education",
+            Self::Game => "Game",
+            Self::Graphics => "Graphics",
+            Self::Network => "Network",
+            Self::Office => "Office",
+            Self::Science => "Science",
+            Self::Settings => "Settings",
+            Self::System => "System",
+            Self::Utility => "Utility",
+        }
+    }
+
+    pub fn name(&self) -> String {
+        match self {
+            Self::AudioVideo => fl!("category-audio-video"),
+            Self::Development => fl!("category-development"),
+            Self::Education => fl!("category-education"),
+            Self::Game => fl!("category-game"),
+            Self::Graphics => fl!("category-graphics"),
+            Self::Network => fl!("category-network"),
+            Self::Office => fl!("category-office"),
+            Self::Science => fl!("category-science"),
+            Self::Settings => fl!("category-settings"),
+            Self::System => fl!("category-system"),
+            Self::Utility => fl!("category-utility"),
+        }
+    }
+
+    pub fn icon(&self) -> &'static str {
+        match self {
+            Self::AudioVideo => "applications-multimedia-symbolic",
+            Self::Development => "applications-engineering-symbolic",
+            Self::Education => "applications-education-symbolic",
+            Self::Game => "applications-games-symbolic",
+            Self::Graphics => "applications-graphics-symbolic",
+            Self::Network => "applications-internet-symbolic",
+            Self::Office => "applications-office-symbolic",
+            Self::Science => "applications-science-symbolic",
+            Self::Settings => "preferences-system-symbolic",
+            Self::System => "applications-system-symbolic",
+            Self::Utility => "applications-utilities-symbolic",
+        }
+    }
+
+    /// Additional categories that are used together with this main category
+    pub fn subcategories(&self) -> &'static [&'static str] {
+        match self {
+            Self::AudioVideo => &[
+                "Audio",
+                "Video",
+                "Midi",
+                "Mixer",
+                "Sequencer",
+                "Tuner",
+                "TV",
+                "AudioVideoEditing",
+                "Player",
+                "Recorder",
+                "DiscBurning",
+            ],
+            Self::Development => &[
+                "Building",
+                "Debugger",
+                "IDE",
+                "GUIDesigner",
+                "Profiling",
+                "RevisionControl",
+                "Translation",
+                "WebDevelopment",
+            ],
+            Self::Education | Self::Science => &[
+                "Art",
+                "ArtificialIntelligence",
+                "Astronomy",
+                "Biology",
+                "Chemistry",
+                "ComputerScience",
+                "Construction",
+                "DataVisualization",
+                "Economy",
+                "Electricity",
+                "Geography",
+                "Geology",
+                "Geoscience",
+                "History",
+                "Humanities",
+                "ImageProcessing",
+                "Languages",
+                "Literature",
+                "Maps",
+                "Math",
+                "MedicalSoftware",
+                "Music",
+                "NumericalAnalysis",
+                "ParallelComputing",
+                "Physics",
+                "Robotics",
+                "Spirituality",
+                "Sports",
+            ],
+            Self::Game => &[
+                "ActionGame",
+                "AdventureGame",
+                "ArcadeGame",
+                "BoardGame",
+                "BlocksGame",
+                "CardGame",
+                "KidsGame",
+                "LogicGame",
+                "RolePlaying",
+                "Shooter",
+                "Simulation",
+                "SportsGame",
+                "StrategyGame",
+                "Emulator",
+            ],
+            Self::Graphics => &[
+                "2DGraphics",
+                "VectorGraphics",
+                "RasterGraphics",
+                "3DGraphics",
+                "Scanning",
+                "OCR",
+                "Photography",
+                "Publishing",
+                "Viewer",
+            ],
+            Self::Network => &[
+                "Chat",
+                "Email",
+                "Feed",
+                "FileTransfer",
+                "HamRadio",
+                "InstantMessaging",
+                "IRCClient",
+                "News",
+                "P2P",
+                "RemoteAccess",
+                "Telephony",
+                "VideoConference",
+                "WebBrowser",
+            ],
+            Self::Office => &[
+                "Calendar",
+                "ContactManagement",
+                "Database",
+                "Dictionary",
+                "Chart",
+                "Email",
+                "Finance",
+                "FlowChart",
+                "ProjectManagement",
+                "Presentation",
+                "Spreadsheet",
+                "WordProcessor",
+                "Publishing",
+                "Viewer",
+            ],
+            Self::Settings => &[
+                "DesktopSettings",
+                "HardwareSettings",
+                "Printing",
+                "PackageManager",
+                "Security",
+                "Accessibility",
+            ],
+            Self::System => &[
+                "Emulator",
+                "FileManager",
+                "FileTools",
+                "Filesystem",
+                "Monitor",
+                "PackageManager",
+                "Security",
+                "TerminalEmulator",
+            ],
+            Self::Utility => &[
+                "Accessibility",
+                "Archiving",
+                "Calculator",
+                "Clock",
+                "Compression",
+                "FileTools",
+                "TextEditor",
+                "TextTools",
+            ],
+        }
+    }
+}
+
+/// Translated name of an additional category
+pub fn subcategory_name(id: &str) -> String {
+    match id {
+        "Audio" => fl!("subcategory-audio"),
+        "Video" => fl!("subcategory-video"),
+        "Midi" => fl!("subcategory-midi"),
+        "Mixer" => fl!("subcategory-mixer"),
+        "Sequencer" => fl!("subcategory-sequencer"),
+        "Tuner" => fl!("subcategory-tuner"),
+        "TV" => fl!("subcategory-tv"),
+        "AudioVideoEditing" => fl!("subcategory-audio-video-editing"),
+        "Player" => fl!("subcategory-player"),
+        "Recorder" => fl!("subcategory-recorder"),
+        "DiscBurning" => fl!("subcategory-disc-burning"),
+        "Building" => fl!("subcategory-building"),
+        "Debugger" => fl!("subcategory-debugger"),
+        "IDE" => fl!("subcategory-ide"),
+        "GUIDesigner" => fl!("subcategory-gui-designer"),
+        "Profiling" => fl!("subcategory-profiling"),
+        "RevisionControl" => fl!("subcategory-revision-control"),
+        "Translation" => fl!("subcategory-translation"),
+        "WebDevelopment" => fl!("subcategory-web-development"),
+        "Art" => fl!("subcategory-art"),
+        "ArtificialIntelligence" => fl!("subcategory-artificial-intelligence"),
+        "Astronomy" => fl!("subcategory-astronomy"),
+        "Biology" => fl!("subcategory-biology"),
+        "Chemistry" => fl!("subcategory-chemistry"),
+        "ComputerScience" => fl!("subcategory-computer-science"),
+        "Construction" => fl!("subcategory-construction"),
+        "DataVisualization" => fl!("subcategory-data-visualization"),
+        "Economy" => fl!("subcategory-economy"),
+        "Electricity" => fl!("subcategory-electricity"),
+        "Geography" => fl!("subcategory-geography"),
+        "Geology" => fl!("subcategory-geology"),
+        "Geoscience" => fl!("subcategory-geoscience"),
+        "History" => fl!("subcategory-history"),
+        "Humanities" => fl!("subcategory-humanities"),
+        "ImageProcessing" => fl!("subcategory-image-processing"),
+        "Languages" => fl!("subcategory-languages"),
+        "Literature" => fl!("subcategory-literature"),
+        "Maps" => fl!("subcategory-maps"),
+        "Math" => fl!("subcategory-math"),
+        "MedicalSoftware" => fl!("subcategory-medical-software"),
+        "Music" => fl!("subcategory-music"),
+        "NumericalAnalysis" => fl!("subcategory-numerical-analysis"),
+        "ParallelComputing" => fl!("subcategory-parallel-computing"),
+        "Physics" => fl!("subcategory-physics"),
+        "Robotics" => fl!("subcategory-robotics"),
+        "Spirituality" => fl!("subcategory-spirituality"),
+        "Sports" => fl!("subcategory-sports"),
+        "ActionGame" => fl!("subcategory-action-game"),
+        "AdventureGame" => fl!("subcategory-adventure-game"),
+        "ArcadeGame" => fl!("subcategory-arcade-game"),
+        "BoardGame" => fl!("subcategory-board-game"),
+        "BlocksGame" => fl!("subcategory-blocks-game"),
+        "CardGame" => fl!("subcategory-card-game"),
+        "KidsGame" => fl!("subcategory-kids-game"),
+        "LogicGame" => fl!("subcategory-logic-game"),
+        "RolePlaying" => fl!("subcategory-role-playing"),
+        "Shooter" => fl!("subcategory-shooter"),
+        "Simulation" => fl!("subcategory-simulation"),
+        "SportsGame" => fl!("subcategory-sports-game"),
+        "StrategyGame" => fl!("subcategory-strategy-game"),
+        "Emulator" => fl!("subcategory-emulator"),
+        "2DGraphics" => fl!("subcategory-2d-graphics"),
+        "VectorGraphics" => fl!("subcategory-vector-graphics"),
+        "RasterGraphics" => fl!("subcategory-raster-graphics"),
+        "3DGraphics" => fl!("subcategory-3d-graphics"),
+        "Scanning" => fl!("subcategory-scanning"),
+        "OCR" => fl!("subcategory-ocr"),
+        "Photography" => fl!("subcategory-photography"),
+        "Publishing" => fl!("subcategory-publishing"),
+        "Viewer" => fl!("subcategory-viewer"),
+        "Chat" => fl!("subcategory-chat"),
+        "Email" => fl!("subcategory-email"),
+        "Feed" => fl!("subcategory-feed"),
+        "FileTransfer" => fl!("subcategory-file-transfer"),
+        "HamRadio" => fl!("subcategory-ham-radio"),
+        "InstantMessaging" => fl!("subcategory-instant-messaging"),
+        "IRCClient" => fl!("subcategory-irc-client"),
+        "News" => fl!("subcategory-news"),
+        "P2P" => fl!("subcategory-p2p"),
+        "RemoteAccess" => fl!("subcategory-remote-access"),
+        "Telephony" => fl!("subcategory-telephony"),
+        "VideoConference" => fl!("subcategory-video-conference"),
+        "WebBrowser" => fl!("subcategory-web-browser"),
+        "Calendar" => fl!("subcategory-calendar"),
+        "ContactManagement" => fl!("subcategory-contact-management"),
+        "Database" => fl!("subcategory-database"),
+        "Dictionary" => fl!("subcategory-dictionary"),
+        "Chart" => fl!("subcategory-chart"),
+        "Finance" => fl!("subcategory-finance"),
+        "FlowChart" => fl!("subcategory-flow-chart"),
+        "ProjectManagement" => fl!("subcategory-project-management"),
+        "Presentation" => fl!("subcategory-presentation"),
+        "Spreadsheet" => fl!("subcategory-spreadsheet"),
+        "WordProcessor" => fl!("subcategory-word-processor"),
+        "DesktopSettings" => fl!("subcategory-desktop-settings"),
+        "HardwareSettings" => fl!("subcategory-hardware-settings"),
+        "Printing" => fl!("subcategory-printing"),
+        "PackageManager" => fl!("subcategory-package-manager"),
+        "Security" => fl!("subcategory-security"),
+        "Accessibility" => fl!("subcategory-accessibility"),
+        "FileManager" => fl!("subcategory-file-manager"),
+        "FileTools" => fl!("subcategory-file-tools"),
+        "Filesystem" => fl!("subcategory-filesystem"),
+        "Monitor" => fl!("subcategory-monitor"),
+        "TerminalEmulator" => fl!("subcategory-terminal-emulator"),
+        "Archiving" => fl!("subcategory-archiving"),
+        "Calculator" => fl!("subcategory-calculator"),
+        "Clock" => fl!("subcategory-clock"),
+        "Compression" => fl!("subcategory-compression"),
+        "TextEditor" => fl!("subcategory-text-editor"),
+        "TextTools" => fl!("subcategory-text-tools"),
+        _ => camel_case_words(id).join(" "),
+    }
+}
+
+/// Words of a name written in CamelCase, like IRCClient to IRC and Client
+pub fn camel_case_words(name: &str) -> Vec<&str> {
+    let chars: Vec<(usize, char)> = name.char_indices().collect();
+    let mut words = Vec::new();
+    let mut start = 0;
+    for (i, (index, c)) in chars.iter().enumerate() {
+        if i > 0 && c.is_uppercase() {
+            let previous = chars[i - 1].1;
+            let next_lowercase = chars.get(i + 1).map_or(false, |x| x.1.is_lowercase());
+            if previous.is_lowercase() || (previous.is_uppercase() && next_lowercase) {
+                words.push(&name[start..*index]);
+                start = *index;
+            }
+        }
+    }
+    words.push(&name[start..]);
+    words
+}
+
+/// Order of apps on a category page
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum CategorySort {
+    Name,
+    Recent,
+}
+
+impl CategorySort {
+    pub fn all() -> &'static [Self] {
+        &[Self::Name, Self::Recent]
+    }
+
+    pub fn label(&self) -> String {
+        match self {
+            Self::Name => fl!("sort-name"),
+            Self::Recent => fl!("sort-recent"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_ids() {
+        assert_eq!(camel_case_words("IRCClient"), ["IRC", "Client"]);
+        assert_eq!(
+            camel_case_words("AudioVideoEditing"),
+            ["Audio", "Video", "Editing"]
+        );
+        assert_eq!(camel_case_words("2DGraphics"), ["2D", "Graphics"]);
+        assert_eq!(camel_case_words("OCR"), ["OCR"]);
+        assert_eq!(camel_case_words("ÉditeurVidéo"), ["Éditeur", "Vidéo"]);
+        assert_eq!(subcategory_name("FooBar"), "Foo Bar");
+    }
+
+    #[test]
+    fn subcategories_are_unique() {
+        for category in Category::all() {
+            let subcategories = category.subcategories();
+            for (i, subcategory) in subcategories.iter().enumerate() {
+                assert!(!subcategories[..i].contains(subcategory), "{}", subcategory);
+            }
+        }
+    }
+}
@@ -5,6 +5,7 @@ use cosmic::{
     theme,
 };
 use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
 
 pub const CONFIG_VERSION: u64 = 1;
 
@@ -28,12 +29,73 @@ impl AppTheme {
 #[derive(Clone, CosmicConfigEntry, Debug, Deserialize, Eq, PartialEq, Serialize)]
 pub struct Config {
     pub app_theme: AppTheme,
+    /// Backend names that are initialized, others are skipped
+    pub enabled_backends: Vec<String>,
+    /// Backend names in the order their copy of an app is preferred
+    pub preferred_sources: Vec<String>,
+    /// Directory scanned for AppImages, ~/Applications if not set
+    pub appimage_dir: Option<PathBuf>,
+    /// Apply updates on the next boot for backends that support it
+    pub offline_updates: bool,
+    /// Hide apps with proprietary licenses when browsing and searching
+    pub hide_proprietary: bool,
 }
 
 impl Default for Config {
     fn default() -> Self {
         Self {
             app_theme: AppTheme::System,
+            enabled_backends: vec![
+                "appimage".to_string(),
+                "flatpak-user".to_string(),
+                "flatpak-system".to_string(),
+                "fwupd".to_string(),
+                "packagekit".to_string(),
+                "snap".to_string(),
+            ],
+            preferred_sources: vec![
+                "flatpak-user".to_string(),
+                "flatpak-system".to_string(),
+                "packagekit".to_string(),
+                "snap".to_string(),
+            ],
+            appimage_dir: None,
+            offline_updates: false,
+            hide_proprietary: false,
         }
     }
 }
+
+impl Config {
+    /// Add sources that were added to the defaults after the config was saved, keeping the order
+    /// chosen by the user. Returns true if any were added
+    pub fn migrate_preferred_sources(&mut self) -> bool {
+        let mut changed = false;
+        for source in Self::default().preferred_sources {
+            if !self.preferred_sources.contains(&source) {
+                self.preferred_sources.push(source);
+                changed = true;
+            }
+        }
+        changed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn migrate_preferred_sources() {
+        let mut config = Config {
+            preferred_sources: vec!["packagekit".to_string(), "flatpak-user".to_string()],
+            ..Config::default()
+        };
+        assert!(config.migrate_preferred_sources());
+        assert_eq!(
+            config.preferred_sources,
+            ["packagekit", "flatpak-user", "flatpak-system", "snap"]
+        );
+        assert!(!config.migrate_preferred_sources());
+    }
+}
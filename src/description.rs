@@ -0,0 +1,382 @@
+use appstream::xmltree;
+
+/// Inline style of a span of description text
+#[derive(Clone, Copy, Debug, Eq, PartialEq, bitcode::Decode, bitcode::Encode)]
+pub enum SpanStyle {
+    Normal,
+    Emphasis,
+    Code,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, bitcode::Decode, bitcode::Encode)]
+pub struct DescriptionSpan {
+    pub style: SpanStyle,
+    pub text: String,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, bitcode::Decode, bitcode::Encode)]
+pub enum DescriptionBlock {
+    Paragraph(Vec<DescriptionSpan>),
+    OrderedList(Vec<Vec<DescriptionSpan>>),
+    UnorderedList(Vec<Vec<DescriptionSpan>>),
+}
+
+/// Description of a component in the subset of markup allowed by appstream
+#[derive(Clone, Debug, Default, Eq, PartialEq, bitcode::Decode, bitcode::Encode)]
+pub struct Description {
+    pub blocks: Vec<DescriptionBlock>,
+}
+
+/// Normalize a locale like en-US.UTF-8 or en_US@euro to en_US
+pub fn normalize_locale(locale: &str) -> String {
+    locale
+        .split(['.', '@'])
+        .next()
+        .unwrap_or_default()
+        .replace('-', "_")
+}
+
+/// How well an xml:lang value matches the normalized locale, higher is better and None does not match
+pub fn lang_score(lang_opt: Option<&str>, locale: &str) -> Option<u8> {
+    let lang = match lang_opt {
+        Some("C") | None => return Some(0),
+        Some(some) => normalize_locale(some),
+    };
+    if lang == locale {
+        return Some(2);
+    }
+    // A translation without region matches all regions of its language
+    match locale.split_once('_') {
+        Some((language, _region)) if lang == language => Some(1),
+        _ => None,
+    }
+}
+
+pub fn element_lang(e: &xmltree::Element) -> Option<&str> {
+    // xmltree keys attributes by local name, dropping the xml prefix
+    e.attributes
+        .get("lang")
+        .or_else(|| e.attributes.get("xml:lang"))
+        .map(|x| x.as_str())
+}
+
+pub fn child_elements<'a>(
+    e: &'a xmltree::Element,
+    name: &'a str,
+) -> impl Iterator<Item = &'a xmltree::Element> + 'a {
+    e.children.iter().filter_map(move |node| match node {
+        xmltree::XMLNode::Element(child) if child.name == name => Some(child),
+        _ => None,
+    })
+}
+
+pub fn element_text(e: &xmltree::Element, text: &mut String) {
+    for node in e.children.iter() {
+        match node {
+            xmltree::XMLNode::Text(some) | xmltree::XMLNode::CData(some) => text.push_str(some),
+            xmltree::XMLNode::Element(child) => element_text(child, text),
+            _ => {}
+        }
+    }
+}
+
+/// The child element with a name that best matches the normalized locale
+pub fn translated_child<'a>(
+    e: &'a xmltree::Element,
+    name: &'a str,
+    locale: &str,
+) -> Option<&'a xmltree::Element> {
+    let mut best_opt: Option<(u8, &xmltree::Element)> = None;
+    for child in child_elements(e, name) {
+        if let Some(score) = lang_score(element_lang(child), locale) {
+            if best_opt.map_or(true, |(best_score, _)| score > best_score) {
+                best_opt = Some((score, child));
+            }
+        }
+    }
+    best_opt.map(|(_, child)| child)
+}
+
+fn push_span(spans: &mut Vec<DescriptionSpan>, style: SpanStyle, text: &str) {
+    // Collapse whitespace like HTML, keeping a single space between spans
+    let mut collapsed = String::with_capacity(text.len());
+    for c in text.chars() {
+        if c.is_whitespace() {
+            let previous_space = match collapsed.chars().last() {
+                Some(last) => last == ' ',
+                None => spans.last().map_or(true, |span| span.text.ends_with(' ')),
+            };
+            if !previous_space {
+                collapsed.push(' ');
+            }
+        } else {
+            collapsed.push(c);
+        }
+    }
+    if collapsed.is_empty() {
+        return;
+    }
+    match spans.last_mut() {
+        Some(span) if span.style == style => span.text.push_str(&collapsed),
+        _ => spans.push(DescriptionSpan {
+            style,
+            text: collapsed,
+        }),
+    }
+}
+
+/// Parse the inline markup of a paragraph or list item
+fn spans(e: &xmltree::Element) -> Vec<DescriptionSpan> {
+    let mut spans = Vec::new();
+    for node in e.children.iter() {
+        match node {
+            xmltree::XMLNode::Text(text) | xmltree::XMLNode::CData(text) => {
+                push_span(&mut spans, SpanStyle::Normal, text)
+            }
+            xmltree::XMLNode::Element(child) => {
+                let style = match child.name.as_str() {
+                    "em" => SpanStyle::Emphasis,
+                    "code" => SpanStyle::Code,
+                    _ => SpanStyle::Normal,
+                };
+                let mut text = String::new();
+                element_text(child, &mut text);
+                push_span(&mut spans, style, &text);
+            }
+            _ => {}
+        }
+    }
+    if let Some(last) = spans.last_mut() {
+        let len = last.text.trim_end().len();
+        last.text.truncate(len);
+        if last.text.is_empty() {
+            spans.pop();
+        }
+    }
+    spans
+}
+
+/// Parse blocks of a description element, keeping only the best translation for the locale
+fn blocks(description: &xmltree::Element, locale: &str) -> Vec<DescriptionBlock> {
+    // Translations are given as siblings with xml:lang, pick the best one that exists
+    let best_score = description
+        .children
+        .iter()
+        .filter_map(|node| match node {
+            xmltree::XMLNode::Element(e) if e.name == "p" => lang_score(element_lang(e), locale),
+            _ => None,
+        })
+        .max()
+        .unwrap_or(0);
+
+    let mut blocks = Vec::new();
+    for node in description.children.iter() {
+        let e = match node {
+            xmltree::XMLNode::Element(e) => e,
+            _ => continue,
+        };
+        match e.name.as_str() {
+            "p" => {
+                if lang_score(element_lang(e), locale) == Some(best_score) {
+                    let spans = spans(e);
+                    if !spans.is_empty() {
+                        blocks.push(DescriptionBlock::Paragraph(spans));
+                    }
+                }
+            }
+            "ol" | "ul" => {
+                // List items are translated separately, so pick the best translation per list
+                let list_score = child_elements(e, "li")
+                    .filter_map(|li| lang_score(element_lang(li), locale))
+                    .max()
+                    .unwrap_or(0);
+                let items: Vec<_> = child_elements(e, "li")
+                    .filter(|li| lang_score(element_lang(li), locale) == Some(list_score))
+                    .map(spans)
+                    .filter(|spans| !spans.is_empty())
+                    .collect();
+                if !items.is_empty() {
+                    blocks.push(if e.name == "ol" {
+                        DescriptionBlock::OrderedList(items)
+                    } else {
+                        DescriptionBlock::UnorderedList(items)
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+    blocks
+}
+
+impl Description {
+    /// Parse the description of a component element, selecting the translation for the locale
+    pub fn from_component(component: &xmltree::Element, locale: &str) -> Self {
+        let locale = normalize_locale(locale);
+        // Catalogs may also have a separate description element per language
+        match translated_child(component, "description", &locale) {
+            Some(description) => Self {
+                blocks: blocks(description, &locale),
+            },
+            None => Self::default(),
+        }
+    }
+
+    /// Parse description markup for a single language, as found in YAML catalogs
+    pub fn from_markup(markup: &str) -> Self {
+        let xml = format!("<description>{}</description>", markup);
+        match xmltree::Element::parse(xml.as_bytes()) {
+            Ok(description) => Self {
+                blocks: blocks(&description, ""),
+            },
+            Err(err) => {
+                log::warn!("failed to parse description markup: {}", err);
+                Self::from_plain_text(markup)
+            }
+        }
+    }
+
+    /// Split plain text into paragraphs at blank lines
+    pub fn from_plain_text(text: &str) -> Self {
+        let mut blocks = Vec::new();
+        for paragraph in text.split("\n\n") {
+            let mut spans = Vec::new();
+            push_span(&mut spans, SpanStyle::Normal, paragraph.trim());
+            if !spans.is_empty() {
+                blocks.push(DescriptionBlock::Paragraph(spans));
+            }
+        }
+        Self { blocks }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.blocks.is_empty()
+    }
+
+    /// Text of each span, without allocating
+    pub fn texts(&self) -> impl Iterator<Item = &str> {
+        self.blocks
+            .iter()
+            .flat_map(|block| match block {
+                DescriptionBlock::Paragraph(spans) => std::slice::from_ref(spans),
+                DescriptionBlock::OrderedList(items) | DescriptionBlock::UnorderedList(items) => {
+                    items.as_slice()
+                }
+            })
+            .flatten()
+            .map(|span| span.text.as_str())
+    }
+
+    /// Text of all blocks without markup, one block or list item per line
+    pub fn plain_text(&self) -> String {
+        let mut lines = Vec::new();
+        for block in self.blocks.iter() {
+            match block {
+                DescriptionBlock::Paragraph(spans) => lines.push(spans_text(spans)),
+                DescriptionBlock::OrderedList(items) | DescriptionBlock::UnorderedList(items) => {
+                    lines.extend(items.iter().map(|spans| spans_text(spans)))
+                }
+            }
+        }
+        lines.join("\n")
+    }
+}
+
+/// Text of spans without styling
+pub fn spans_text(spans: &[DescriptionSpan]) -> String {
+    spans.iter().map(|span| span.text.as_str()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn span(style: SpanStyle, text: &str) -> DescriptionSpan {
+        DescriptionSpan {
+            style,
+            text: text.to_string(),
+        }
+    }
+
+    #[test]
+    fn inline_markup() {
+        let description = Description::from_markup(
+            "<p>Edit  <em>any</em>\n  file with <code>vim -u NONE</code></p>",
+        );
+        assert_eq!(
+            description.blocks,
+            vec![DescriptionBlock::Paragraph(vec![
+                span(SpanStyle::Normal, "Edit "),
+                span(SpanStyle::Emphasis, "any"),
+                span(SpanStyle::Normal, " file with "),
+                span(SpanStyle::Code, "vim -u NONE"),
+            ])]
+        );
+        assert_eq!(description.plain_text(), "Edit any file with vim -u NONE");
+    }
+
+    #[test]
+    fn lists() {
+        let description = Description::from_markup(
+            "<p>Features:</p><ul><li>Fast</li><li> Small </li></ul><ol><li>First</li><li></li></ol>",
+        );
+        assert_eq!(
+            description.blocks,
+            vec![
+                DescriptionBlock::Paragraph(vec![span(SpanStyle::Normal, "Features:")]),
+                DescriptionBlock::UnorderedList(vec![
+                    vec![span(SpanStyle::Normal, "Fast")],
+                    vec![span(SpanStyle::Normal, "Small")],
+                ]),
+                DescriptionBlock::OrderedList(vec![vec![span(SpanStyle::Normal, "First")]]),
+            ]
+        );
+        assert_eq!(description.plain_text(), "Features:\nFast\nSmall\nFirst");
+    }
+
+    #[test]
+    fn translations() {
+        let component = xmltree::Element::parse(
+            r#"<component>
+                <description>
+                    <p>Hello</p>
+                    <p xml:lang="de">Hallo</p>
+                    <p xml:lang="de_AT">Servus</p>
+                    <ul>
+                        <li>One</li>
+                        <li xml:lang="de">Eins</li>
+                    </ul>
+                </description>
+            </component>"#
+                .as_bytes(),
+        )
+        .unwrap();
+        let texts = |locale: &str| -> Vec<String> {
+            Description::from_component(&component, locale)
+                .texts()
+                .map(|x| x.to_string())
+                .collect()
+        };
+        assert_eq!(texts("en_US.UTF-8"), ["Hello", "One"]);
+        assert_eq!(texts("de_DE.UTF-8"), ["Hallo", "Eins"]);
+        assert_eq!(texts("de-AT"), ["Servus", "Eins"]);
+    }
+
+    #[test]
+    fn invalid_markup() {
+        let description = Description::from_markup("Not <p>closed");
+        assert_eq!(
+            description.blocks,
+            vec![DescriptionBlock::Paragraph(vec![span(
+                SpanStyle::Normal,
+                "Not <p>closed"
+            )])]
+        );
+    }
+
+    #[test]
+    fn plain_text() {
+        let description = Description::from_plain_text("First\nline\n\n\nSecond");
+        assert_eq!(description.plain_text(), "First line\nSecond");
+    }
+}
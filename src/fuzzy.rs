@@ -0,0 +1,154 @@
+/// Longest query word that is compared, longer words are truncated
+const MAX_LEN: usize = 32;
+
+/// Typos allowed in a query word of a length, short words must match exactly
+fn max_typos(len: usize) -> usize {
+    match len {
+        0..=3 => 0,
+        4..=7 => 1,
+        _ => 2,
+    }
+}
+
+fn lowercase_alphanumeric(text: &str) -> impl Iterator<Item = char> + '_ {
+    text.chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(char::to_lowercase)
+}
+
+fn words(text: &str) -> impl Iterator<Item = &str> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|x| !x.is_empty())
+}
+
+/// Distance between a query and the closest prefix of a word, allowing insertions, deletions,
+/// substitutions, and transpositions, or None if it is larger than `max`
+fn prefix_distance(query: &[char], word: impl Iterator<Item = char>, max: usize) -> Option<usize> {
+    let len = query.len();
+    // Rows are word prefixes and columns are query prefixes
+    let mut before = [0; MAX_LEN + 1];
+    let mut previous = [0; MAX_LEN + 1];
+    let mut current = [0; MAX_LEN + 1];
+    for j in 0..=len {
+        previous[j] = j;
+    }
+    let mut best = previous[len];
+    let mut previous_c = None;
+    for c in word {
+        current[0] = previous[0] + 1;
+        let mut row_min = current[0];
+        for j in 1..=len {
+            let cost = if query[j - 1] == c { 0 } else { 1 };
+            let mut value = min3(previous[j] + 1, current[j - 1] + 1, previous[j - 1] + cost);
+            if j > 1 && Some(query[j - 1]) == previous_c && query[j - 2] == c {
+                value = value.min(before[j - 2] + 1);
+            }
+            current[j] = value;
+            row_min = row_min.min(value);
+        }
+        best = best.min(current[len]);
+        // Distances never decrease in later rows
+        if row_min > max {
+            break;
+        }
+        before = previous;
+        previous = current;
+        previous_c = Some(c);
+    }
+    if best <= max {
+        Some(best)
+    } else {
+        None
+    }
+}
+
+fn min3(a: usize, b: usize, c: usize) -> usize {
+    a.min(b).min(c)
+}
+
+/// A search phrase prepared for typo tolerant matching
+#[derive(Clone, Debug)]
+pub struct FuzzyQuery {
+    words: Vec<Vec<char>>,
+    /// All words without separators, for names written as one word like LibreOffice
+    joined: Vec<char>,
+}
+
+impl FuzzyQuery {
+    /// Returns None if the phrase is too short to allow typos
+    pub fn new(input: &str) -> Option<Self> {
+        let words: Vec<Vec<char>> = words(input)
+            .map(|word| lowercase_alphanumeric(word).take(MAX_LEN).collect())
+            .collect();
+        let joined: Vec<char> = lowercase_alphanumeric(input).take(MAX_LEN).collect();
+        if max_typos(joined.len()) == 0 {
+            return None;
+        }
+        Some(Self { words, joined })
+    }
+
+    /// Number of typos needed to match a text, or None if there are too many
+    pub fn typos(&self, text: &str) -> Option<usize> {
+        let joined_opt = prefix_distance(
+            &self.joined,
+            lowercase_alphanumeric(text),
+            max_typos(self.joined.len()),
+        );
+        if joined_opt == Some(0) || self.words.len() < 2 {
+            return joined_opt;
+        }
+        // Every query word must match the start of a word in the text
+        let mut total = 0;
+        for query_word in self.words.iter() {
+            let max = max_typos(query_word.len());
+            let typos = words(text)
+                .filter_map(|word| prefix_distance(query_word, lowercase_alphanumeric(word), max))
+                .min();
+            match typos {
+                Some(typos) => total += typos,
+                None => return joined_opt,
+            }
+        }
+        Some(joined_opt.map_or(total, |joined| joined.min(total)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn short_queries_are_exact() {
+        assert!(FuzzyQuery::new("gim").is_none());
+        assert!(FuzzyQuery::new("g i m").is_none());
+        assert!(FuzzyQuery::new("gimp").is_some());
+    }
+
+    #[test]
+    fn typos() {
+        let query = FuzzyQuery::new("firefx").unwrap();
+        assert_eq!(query.typos("Firefox"), Some(1));
+        assert_eq!(query.typos("Firefox Web Browser"), Some(1));
+        assert_eq!(query.typos("Thunderbird"), None);
+
+        let query = FuzzyQuery::new("libre ofice").unwrap();
+        assert_eq!(query.typos("LibreOffice"), Some(1));
+        assert_eq!(query.typos("LibreOffice Writer"), Some(1));
+        assert_eq!(query.typos("Libre Office"), Some(1));
+        assert_eq!(query.typos("Office Libre"), Some(1));
+        assert_eq!(query.typos("OnlyOffice"), None);
+
+        let query = FuzzyQuery::new("gimpp").unwrap();
+        assert_eq!(query.typos("GIMP"), Some(1));
+        assert_eq!(query.typos("GNU Image Manipulation Program"), None);
+    }
+
+    #[test]
+    fn exact_and_prefix_matches() {
+        let query = FuzzyQuery::new("firefox").unwrap();
+        assert_eq!(query.typos("Firefox"), Some(0));
+        assert_eq!(query.typos("firefox-esr"), Some(0));
+        let query = FuzzyQuery::new("fierfox").unwrap();
+        assert_eq!(query.typos("Firefox"), Some(1));
+    }
+}
@@ -0,0 +1,270 @@
+use std::{
+    error::Error,
+    fs,
+    io::Write,
+    path::PathBuf,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{backend::Backend, date::civil_from_days, OperationKind};
+
+/// An operation performed by the store, stored one per line in the history file
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct HistoryEntry {
+    /// When the operation finished in seconds from the unix epoch
+    pub timestamp: u64,
+    pub backend_name: String,
+    pub package_id: String,
+    pub kind: OperationKind,
+    pub version_before: String,
+    pub version_after: String,
+    pub error_opt: Option<String>,
+}
+
+impl HistoryEntry {
+    pub fn new(
+        backend_name: &str,
+        package_id: &str,
+        kind: OperationKind,
+        version_before: String,
+        version_after: String,
+        error_opt: Option<String>,
+    ) -> Self {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |x| x.as_secs());
+        Self {
+            timestamp,
+            backend_name: backend_name.to_string(),
+            package_id: package_id.to_string(),
+            kind,
+            version_before,
+            version_after,
+            error_opt,
+        }
+    }
+
+    /// Timestamp formatted as local date and time
+    pub fn date_time(&self) -> String {
+        let local = self.timestamp as i64 + utc_offset(self.timestamp);
+        let secs = local.rem_euclid(86400);
+        let (year, month, day) = civil_from_days(local.div_euclid(86400));
+        format!(
+            "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+            year,
+            month,
+            day,
+            secs / 3600,
+            (secs / 60) % 60,
+            secs % 60
+        )
+    }
+
+    /// Case insensitive match of a filter against the text fields
+    pub fn matches(&self, filter: &str) -> bool {
+        let filter = filter.to_lowercase();
+        [
+            self.backend_name.as_str(),
+            self.package_id.as_str(),
+            kind_name(self.kind),
+            self.version_before.as_str(),
+            self.version_after.as_str(),
+            self.error_opt.as_deref().unwrap_or(""),
+        ]
+        .iter()
+        .any(|field| field.to_lowercase().contains(&filter))
+    }
+
+    fn to_line(&self) -> String {
+        // Fields are tab separated, so remove tabs and newlines from them
+        let clean = |x: &str| x.replace(['\t', '\n', '\r'], " ");
+        format!(
+            "{}\t{}\t{}\t{}\t{}\t{}\t{}\n",
+            self.timestamp,
+            clean(&self.backend_name),
+            clean(&self.package_id),
+            kind_name(self.kind),
+            clean(&self.version_before),
+            clean(&self.version_after),
+            match &self.error_opt {
+                Some(err) => format!("error: {}", clean(err)),
+                None => "ok".to_string(),
+            }
+        )
+    }
+
+    fn from_line(line: &str) -> Option<Self> {
+        let mut parts = line.split('\t');
+        let timestamp = parts.next()?.parse().ok()?;
+        let backend_name = parts.next()?.to_string();
+        let package_id = parts.next()?.to_string();
+        let kind = kind_from_name(parts.next()?)?;
+        let version_before = parts.next()?.to_string();
+        let version_after = parts.next()?.to_string();
+        let error_opt = match parts.next()? {
+            "ok" => None,
+            result => Some(result.strip_prefix("error: ").unwrap_or(result).to_string()),
+        };
+        Some(Self {
+            timestamp,
+            backend_name,
+            package_id,
+            kind,
+            version_before,
+            version_after,
+            error_opt,
+        })
+    }
+}
+
+/// Offset of local time from UTC in seconds at a timestamp, which changes with daylight saving
+fn utc_offset(timestamp: u64) -> i64 {
+    let time = timestamp as libc::time_t;
+    let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+    // localtime_r is thread safe and uses the time zone of the system
+    if unsafe { libc::localtime_r(&time, &mut tm) }.is_null() {
+        return 0;
+    }
+    tm.tm_gmtoff as i64
+}
+
+fn kind_name(kind: OperationKind) -> &'static str {
+    match kind {
+        OperationKind::Install => "install",
+        OperationKind::Integrate => "integrate",
+        OperationKind::OfflineUpdate => "offline-update",
+        OperationKind::Rebase => "rebase",
+        OperationKind::Uninstall => "uninstall",
+        OperationKind::Update => "update",
+    }
+}
+
+fn kind_from_name(name: &str) -> Option<OperationKind> {
+    match name {
+        "install" => Some(OperationKind::Install),
+        "integrate" => Some(OperationKind::Integrate),
+        "offline-update" => Some(OperationKind::OfflineUpdate),
+        "rebase" => Some(OperationKind::Rebase),
+        "uninstall" => Some(OperationKind::Uninstall),
+        "update" => Some(OperationKind::Update),
+        _ => None,
+    }
+}
+
+fn history_path() -> Result<PathBuf, Box<dyn Error>> {
+    Ok(dirs::data_dir()
+        .ok_or("failed to find data directory")?
+        .join("cosmic-store")
+        .join("history.tsv"))
+}
+
+/// Append an entry to the history file
+pub fn append(entry: &HistoryEntry) -> Result<(), Box<dyn Error>> {
+    let path = history_path()?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)?;
+    file.write_all(entry.to_line().as_bytes())?;
+    Ok(())
+}
+
+/// Load all entries from the history file, oldest first
+pub fn load() -> Result<Vec<HistoryEntry>, Box<dyn Error>> {
+    let path = history_path()?;
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let mut entries = Vec::new();
+    for line in fs::read_to_string(&path)?.lines() {
+        match HistoryEntry::from_line(line) {
+            Some(entry) => entries.push(entry),
+            None => log::warn!("invalid history line in {:?}: {:?}", path, line),
+        }
+    }
+    Ok(entries)
+}
+
+/// Version of an installed package, empty if it is not installed. Lists installed packages of
+/// the backend, so only use it when the version is not already known.
+pub fn installed_version(backend: &dyn Backend, package_id: &str) -> String {
+    match backend.installed() {
+        Ok(packages) => packages
+            .into_iter()
+            .find(|package| package.id == package_id)
+            .map(|package| package.version)
+            .unwrap_or_default(),
+        Err(err) => {
+            log::warn!("failed to list installed for history: {}", err);
+            String::new()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(error_opt: Option<&str>) -> HistoryEntry {
+        HistoryEntry {
+            timestamp: 1700000000,
+            backend_name: "flatpak-user".to_string(),
+            package_id: "org.gimp.GIMP".to_string(),
+            kind: OperationKind::Update,
+            version_before: "2.10.34".to_string(),
+            version_after: "2.10.36".to_string(),
+            error_opt: error_opt.map(|x| x.to_string()),
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        for entry in [entry(None), entry(Some("failed to download"))] {
+            let line = entry.to_line();
+            assert_eq!(HistoryEntry::from_line(line.trim_end()), Some(entry));
+        }
+    }
+
+    #[test]
+    fn round_trip_error_prefix() {
+        // Only one prefix is removed from errors that start with the same text
+        let entry = entry(Some("error: no space left on device"));
+        let line = entry.to_line();
+        assert!(line.ends_with("\terror: error: no space left on device\n"));
+        assert_eq!(HistoryEntry::from_line(line.trim_end()), Some(entry));
+    }
+
+    #[test]
+    fn round_trip_tabs_and_newlines() {
+        let mut entry = entry(Some("line one\nline two\twith tab"));
+        entry.version_before = "1.0\t(old)".to_string();
+        entry.version_after = "1.1\r\n".to_string();
+        let line = entry.to_line();
+        assert_eq!(line.matches('\t').count(), 6);
+        assert_eq!(line.matches('\n').count(), 1);
+
+        let parsed = HistoryEntry::from_line(line.trim_end()).unwrap();
+        assert_eq!(parsed.version_before, "1.0 (old)");
+        assert_eq!(parsed.version_after, "1.1  ");
+        assert_eq!(
+            parsed.error_opt.as_deref(),
+            Some("line one line two with tab")
+        );
+        assert_eq!(parsed.package_id, entry.package_id);
+        assert_eq!(parsed.kind, entry.kind);
+    }
+
+    #[test]
+    fn invalid_lines() {
+        assert_eq!(HistoryEntry::from_line(""), None);
+        assert_eq!(
+            HistoryEntry::from_line("not a timestamp\ta\tb\tupdate\t\t\tok"),
+            None
+        );
+        assert_eq!(HistoryEntry::from_line("1\ta\tb\tdowngrade\t\t\tok"), None);
+        assert_eq!(HistoryEntry::from_line("1\ta\tb\tupdate\t\t"), None);
+    }
+}
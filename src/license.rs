@@ -0,0 +1,329 @@
+use crate::fl;
+
+/// SPDX license IDs that are free software or free content licenses, compared without case
+const FREE_LICENSES: &[&str] = &[
+    "0bsd",
+    "afl-2.1",
+    "afl-3.0",
+    "apache-1.0",
+    "apache-1.1",
+    "apache-2.0",
+    "apsl-2.0",
+    "artistic-2.0",
+    "bsd-1-clause",
+    "bsd-2-clause",
+    "bsd-2-clause-patent",
+    "bsd-3-clause",
+    "bsd-3-clause-clear",
+    "bsd-4-clause",
+    "bsl-1.0",
+    "cc-by-3.0",
+    "cc-by-4.0",
+    "cc-by-sa-3.0",
+    "cc-by-sa-4.0",
+    "cc0-1.0",
+    "cddl-1.0",
+    "cecill-2.1",
+    "cecill-b",
+    "cecill-c",
+    "ecl-2.0",
+    "epl-1.0",
+    "epl-2.0",
+    "eupl-1.1",
+    "eupl-1.2",
+    "fsfap",
+    "ftl",
+    "hpnd",
+    "isc",
+    "lppl-1.3c",
+    "mit",
+    "mit-0",
+    "mpl-1.1",
+    "mpl-2.0",
+    "ms-pl",
+    "ms-rl",
+    "ncsa",
+    "ofl-1.1",
+    "openssl",
+    "php-3.01",
+    "psf-2.0",
+    "python-2.0",
+    "ruby",
+    "unicode-dfs-2016",
+    "unlicense",
+    "upl-1.0",
+    "vim",
+    "w3c",
+    "wtfpl",
+    "x11",
+    "zlib",
+    "zpl-2.1",
+];
+
+/// Prefixes of SPDX license ID families that are all free, like GPL-3.0-or-later
+const FREE_LICENSE_PREFIXES: &[&str] = &["agpl-", "gfdl-", "gpl-", "lgpl-"];
+
+/// Whether an app is free software, ordered from least to most free
+#[derive(
+    Clone, Copy, Debug, Default, Eq, Ord, PartialEq, PartialOrd, bitcode::Decode, bitcode::Encode,
+)]
+pub enum LicenseKind {
+    Proprietary,
+    #[default]
+    Unknown,
+    Free,
+}
+
+impl LicenseKind {
+    /// Classify a SPDX license expression like `GPL-3.0-or-later AND MIT`
+    pub fn from_expression(expression: &str) -> Self {
+        let tokens = tokens(expression);
+        let mut parser = Parser { tokens, i: 0 };
+        match parser.or() {
+            Some(kind) if parser.i == parser.tokens.len() => kind,
+            _ => Self::Unknown,
+        }
+    }
+
+    /// Parse the value of a `license:` search filter
+    pub fn parse(kind: &str) -> Option<Self> {
+        match kind {
+            "free" => Some(Self::Free),
+            "proprietary" | "nonfree" => Some(Self::Proprietary),
+            "unknown" => Some(Self::Unknown),
+            _ => None,
+        }
+    }
+
+    pub fn id(&self) -> &'static str {
+        match self {
+            Self::Proprietary => "proprietary",
+            Self::Unknown => "unknown",
+            Self::Free => "free",
+        }
+    }
+
+    pub fn name(&self) -> String {
+        match self {
+            Self::Proprietary => fl!("license-proprietary"),
+            Self::Unknown => fl!("license-unknown"),
+            Self::Free => fl!("license-free"),
+        }
+    }
+
+    pub fn icon(&self) -> &'static str {
+        match self {
+            Self::Proprietary => "dialog-warning-symbolic",
+            Self::Unknown => "dialog-question-symbolic",
+            Self::Free => "emblem-ok-symbolic",
+        }
+    }
+}
+
+/// Classify a single license ID, ignoring the + suffix for later versions
+fn license_kind(id: &str) -> LicenseKind {
+    let id = id.trim_end_matches('+').to_lowercase();
+    // Appstream uses LicenseRef-proprietary, optionally followed by =URL of the license
+    if id.starts_with("licenseref-proprietary") {
+        return LicenseKind::Proprietary;
+    }
+    // Appstream uses LicenseRef-free=URL for free licenses without a SPDX ID
+    if id.starts_with("licenseref-free") {
+        return LicenseKind::Free;
+    }
+    // Creative Commons licenses that forbid commercial use or changes are not free
+    if id.starts_with("cc-by-nc") || id.starts_with("cc-by-nd") {
+        return LicenseKind::Proprietary;
+    }
+    if FREE_LICENSES.contains(&id.as_str())
+        || FREE_LICENSE_PREFIXES
+            .iter()
+            .any(|prefix| id.starts_with(prefix))
+    {
+        return LicenseKind::Free;
+    }
+    LicenseKind::Unknown
+}
+
+#[derive(Debug, Eq, PartialEq)]
+enum Token {
+    And,
+    Close,
+    Id(String),
+    Open,
+    Or,
+    With,
+}
+
+fn tokens(expression: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut word = String::new();
+    let push_word = |word: &mut String, tokens: &mut Vec<Token>| {
+        if word.is_empty() {
+            return;
+        }
+        tokens.push(match word.to_lowercase().as_str() {
+            "and" => Token::And,
+            "or" => Token::Or,
+            "with" => Token::With,
+            _ => Token::Id(word.clone()),
+        });
+        word.clear();
+    };
+    for c in expression.chars() {
+        match c {
+            '(' | ')' => {
+                push_word(&mut word, &mut tokens);
+                tokens.push(if c == '(' { Token::Open } else { Token::Close });
+            }
+            _ if c.is_whitespace() => push_word(&mut word, &mut tokens),
+            _ => word.push(c),
+        }
+    }
+    push_word(&mut word, &mut tokens);
+    tokens
+}
+
+/// Evaluates an expression where AND uses the least free license and OR the most free one
+struct Parser {
+    tokens: Vec<Token>,
+    i: usize,
+}
+
+impl Parser {
+    fn or(&mut self) -> Option<LicenseKind> {
+        let mut kind = self.and()?;
+        while self.tokens.get(self.i) == Some(&Token::Or) {
+            self.i += 1;
+            kind = kind.max(self.and()?);
+        }
+        Some(kind)
+    }
+
+    fn and(&mut self) -> Option<LicenseKind> {
+        let mut kind = self.license()?;
+        while self.tokens.get(self.i) == Some(&Token::And) {
+            self.i += 1;
+            kind = kind.min(self.license()?);
+        }
+        Some(kind)
+    }
+
+    fn license(&mut self) -> Option<LicenseKind> {
+        let kind = match self.tokens.get(self.i)? {
+            Token::Open => {
+                self.i += 1;
+                let kind = self.or()?;
+                if self.tokens.get(self.i) != Some(&Token::Close) {
+                    return None;
+                }
+                kind
+            }
+            Token::Id(id) => license_kind(id),
+            _ => return None,
+        };
+        self.i += 1;
+        // Exceptions like Classpath-exception-2.0 only grant more permissions
+        if self.tokens.get(self.i) == Some(&Token::With) {
+            match self.tokens.get(self.i + 1) {
+                Some(Token::Id(_)) => self.i += 2,
+                _ => return None,
+            }
+        }
+        Some(kind)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn single_licenses() {
+        assert_eq!(LicenseKind::from_expression("MIT"), LicenseKind::Free);
+        assert_eq!(
+            LicenseKind::from_expression("GPL-3.0-or-later"),
+            LicenseKind::Free
+        );
+        assert_eq!(LicenseKind::from_expression("GPL-2.0+"), LicenseKind::Free);
+        assert_eq!(
+            LicenseKind::from_expression("LicenseRef-proprietary"),
+            LicenseKind::Proprietary
+        );
+        assert_eq!(
+            LicenseKind::from_expression("LicenseRef-proprietary=https://example.com/eula"),
+            LicenseKind::Proprietary
+        );
+        assert_eq!(
+            LicenseKind::from_expression("LicenseRef-free=https://example.com/license"),
+            LicenseKind::Free
+        );
+        assert_eq!(
+            LicenseKind::from_expression("CC-BY-NC-4.0"),
+            LicenseKind::Proprietary
+        );
+        assert_eq!(
+            LicenseKind::from_expression("Some-Other-License"),
+            LicenseKind::Unknown
+        );
+    }
+
+    #[test]
+    fn and_uses_least_free() {
+        assert_eq!(
+            LicenseKind::from_expression("GPL-3.0-or-later AND MIT"),
+            LicenseKind::Free
+        );
+        assert_eq!(
+            LicenseKind::from_expression("MIT AND LicenseRef-proprietary"),
+            LicenseKind::Proprietary
+        );
+        assert_eq!(
+            LicenseKind::from_expression("MIT and Some-Other-License"),
+            LicenseKind::Unknown
+        );
+    }
+
+    #[test]
+    fn or_uses_most_free() {
+        assert_eq!(
+            LicenseKind::from_expression("LicenseRef-proprietary OR MIT"),
+            LicenseKind::Free
+        );
+        assert_eq!(
+            LicenseKind::from_expression("LicenseRef-proprietary OR Some-Other-License"),
+            LicenseKind::Unknown
+        );
+    }
+
+    #[test]
+    fn parentheses() {
+        assert_eq!(
+            LicenseKind::from_expression("LicenseRef-proprietary AND (MIT OR Apache-2.0)"),
+            LicenseKind::Proprietary
+        );
+        assert_eq!(
+            LicenseKind::from_expression("(LicenseRef-proprietary AND MIT) OR Apache-2.0"),
+            LicenseKind::Free
+        );
+        assert_eq!(
+            LicenseKind::from_expression("GPL-2.0-only WITH Classpath-exception-2.0"),
+            LicenseKind::Free
+        );
+    }
+
+    #[test]
+    fn invalid_expressions() {
+        assert_eq!(LicenseKind::from_expression(""), LicenseKind::Unknown);
+        assert_eq!(LicenseKind::from_expression("(MIT"), LicenseKind::Unknown);
+        assert_eq!(LicenseKind::from_expression("MIT)"), LicenseKind::Unknown);
+        assert_eq!(
+            LicenseKind::from_expression("MIT AND"),
+            LicenseKind::Unknown
+        );
+        assert_eq!(
+            LicenseKind::from_expression("MIT WITH"),
+            LicenseKind::Unknown
+        );
+    }
+}
@@ -7,34 +7,82 @@ use cosmic::{
     cosmic_theme, executor,
     iced::{
         event::{self, Event},
+        futures::{self, SinkExt},
         keyboard::{Event as KeyEvent, KeyCode, Modifiers},
-        subscription::Subscription,
+        subscription::{self, Subscription},
         window, Alignment, Length,
     },
     widget, Application, ApplicationExt, Element,
 };
 use rayon::prelude::*;
-use std::{any::TypeId, cmp, collections::HashMap, env, process, sync::Arc, time::Instant};
+use std::{
+    any::TypeId,
+    cmp,
+    collections::{BTreeMap, HashMap, HashSet},
+    env, mem,
+    path::{Path, PathBuf},
+    process,
+    sync::{mpsc, Arc},
+    time::{Duration, Instant},
+};
 
-use app_info::{AppIcon, AppInfo};
+use app_info::{AppIcon, AppInfo, AppKind};
 mod app_info;
 
 use appstream_cache::AppstreamCache;
 mod appstream_cache;
 
-use backend::{Backends, Package};
+use backend::{Backend, Backends, Branch, Capabilities, OfflineState, Package};
 mod backend;
 
+use category::{subcategory_name, Category, CategorySort};
+mod category;
+
 use config::{AppTheme, Config, CONFIG_VERSION};
 mod config;
 
+mod date;
+
+use description::{spans_text, Description, DescriptionBlock, DescriptionSpan};
+mod description;
+
+use fuzzy::FuzzyQuery;
+mod fuzzy;
+
+use history::HistoryEntry;
+mod history;
+
 use key_bind::{key_binds, KeyBind};
 mod key_bind;
 
+use license::LicenseKind;
+mod license;
+
 mod localize;
 
+mod media_cache;
+
+use operation::{Operation, OperationKind};
+mod operation;
+
+use project_url::ProjectUrl;
+mod project_url;
+
+use release::{compare_versions, Release, ReleaseKind, ReleaseUrgency};
+mod release;
+
+use screenshot::{Screenshot, ScreenshotImage};
+mod screenshot;
+
+use search_query::{SearchFilter, SearchQuery};
+mod search_query;
+
 const ICON_SIZE_LIST: u16 = 48;
 const ICON_SIZE_DETAILS: u16 = 128;
+const SCREENSHOT_WIDTH: u32 = 624;
+const SCREENSHOT_HEIGHT: u16 = 352;
+/// Ignore watcher events for this long after refreshing a backend, as it writes to watched paths
+const REFRESH_WATCH_IGNORE: Duration = Duration::from_secs(5);
 
 /// Runs application with these settings
 #[rustfmt::skip]
@@ -45,13 +93,22 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     let (config_handler, config) = match cosmic_config::Config::new(App::APP_ID, CONFIG_VERSION) {
         Ok(config_handler) => {
-            let config = match Config::get_entry(&config_handler) {
+            let mut config = match Config::get_entry(&config_handler) {
                 Ok(ok) => ok,
                 Err((errs, config)) => {
                     log::info!("errors loading config: {:?}", errs);
                     config
                 }
             };
+            // Saved configs do not list backends that were added later
+            let mut migrated = config.clone();
+            if migrated.migrate_preferred_sources() {
+                if let Err(err) =
+                    config.set_preferred_sources(&config_handler, migrated.preferred_sources)
+                {
+                    log::warn!("failed to save config \"preferred_sources\": {}", err);
+                }
+            }
             (Some(config_handler), config)
         }
         Err(err) => {
@@ -72,9 +129,17 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     //TODO: allow size limits on iced_winit
     //settings = settings.size_limits(Limits::NONE.min_width(400.0).min_height(200.0));
 
+    // A MIME type or file to find an app for, such as from a file manager
+    let search_opt = match env::args().nth(1) {
+        Some(arg) if arg == "--mimetype" => env::args().nth(2),
+        Some(arg) if !arg.starts_with('-') => Some(arg),
+        _ => None,
+    };
+
     let flags = Flags {
         config_handler,
         config,
+        search_opt,
     };
     cosmic::app::run::<App>(settings, flags)?;
 
@@ -98,28 +163,78 @@ impl Action {
 pub struct Flags {
     config_handler: Option<cosmic_config::Config>,
     config: Config,
+    search_opt: Option<String>,
 }
 
 /// Messages that are used specifically by our [`App`].
 #[derive(Clone, Debug)]
 pub enum Message {
     AppTheme(AppTheme),
+    BackendRefreshed(&'static str, bool),
+    BackendReloaded(&'static str, Arc<dyn Backend>),
     Backends(Backends),
+    Branches(&'static str, String, Vec<Branch>),
+    Category(Option<Category>),
+    CategoryResults(
+        Category,
+        Option<&'static str>,
+        Vec<&'static str>,
+        Vec<SearchResult>,
+    ),
+    CategorySort(usize),
+    CategorySubcategory(Option<&'static str>),
     Config(Config),
+    EnableBackend(&'static str, bool),
+    HideProprietary(bool),
+    History,
+    HistoryClose,
+    HistoryFilter(String),
+    HistoryLoaded(Vec<HistoryEntry>),
+    Hold(&'static str, String, bool, bool),
+    HoldError(String, String),
     Installed(Vec<(&'static str, Package)>),
     Key(Modifiers, KeyCode),
+    OfflineClearResult(&'static str),
+    OfflineReboot(&'static str),
+    OfflineRebootConfirm(Option<&'static str>),
+    OfflineStates(Vec<(&'static str, OfflineState)>),
+    OfflineUpdate(&'static str),
+    OfflineUpdates(bool),
     OpenDesktopId(String),
+    OpenUrl(String),
+    Operation(
+        OperationKind,
+        &'static str,
+        String,
+        Arc<AppInfo>,
+        Option<Branch>,
+    ),
+    PendingComplete(u64),
+    PendingError(u64, String),
+    PendingProgress(u64, f32),
+    PreferSource(usize),
+    RefreshInstalled,
+    ReleasesExpand(bool),
+    Screenshot(usize),
+    ScreenshotFull(bool),
+    ScreenshotLoaded(String, Option<widget::image::Handle>),
     SearchActivate,
     SearchClear,
+    SearchFilterRemove(usize),
     SearchInput(String),
     SearchResults(String, Vec<SearchResult>),
     SearchSubmit,
+    SelectCategoryResult(usize),
     SelectInstalled(usize),
     SelectNone,
     SelectSearchResult(usize),
-    Selected(Selected),
+    SelectSource(usize),
+    Selected(AppSource, Option<Branch>),
+    Sources(Vec<(&'static str, Vec<String>)>),
     SystemThemeModeChange(cosmic_theme::ThemeMode),
     ToggleContextPage(ContextPage),
+    Updates(Vec<(&'static str, Package)>),
+    WatchChanged(Vec<&'static str>),
     WindowClose,
     WindowNew,
 }
@@ -137,21 +252,752 @@ impl ContextPage {
     }
 }
 
+fn backend_label(backend_name: &str) -> String {
+    match backend_name {
+        "flatpak-user" => fl!("backend-flatpak-user"),
+        "flatpak-system" => fl!("backend-flatpak-system"),
+        "packagekit" => fl!("backend-packagekit"),
+        "snap" => fl!("backend-snap"),
+        "fwupd" => fl!("backend-fwupd"),
+        "appimage" => fl!("backend-appimage"),
+        _ => backend_name.to_string(),
+    }
+}
+
+/// Key used to find the same app in different backends
+fn app_key(id: &str) -> String {
+    id.trim_end_matches(".desktop").to_lowercase()
+}
+
+/// Scan all apps of a backend for matches that are not in the search index, like matches inside
+/// words, in descriptions, or with typos, when the index finds fewer apps than this
+const SEARCH_SCAN_BELOW: usize = 16;
+
+/// Weight for results from remote searches that do not match local fields
+const SEARCH_WEIGHT_REMOTE: usize = 18;
+
+/// Weight of a search phrase in a field, adding 0 if equal, 1 if starting with, and 2 if containing
+fn field_weight(regex: &regex::Regex, base: usize, field: &str) -> Option<usize> {
+    let mat = regex.find(field)?;
+    Some(if mat.range().start == 0 {
+        if mat.range().end == field.len() {
+            base
+        } else {
+            base + 1
+        }
+    } else {
+        base + 2
+    })
+}
+
+/// Weight of a search match, lower is better.
+///
+/// Each field has a base weight, and the match adds 0 when the field equals the search
+/// phrase, 1 when it starts with it, and 2 when it contains it:
+///
+/// | Field                                   | Base |
+/// |-----------------------------------------|------|
+/// | Name                                    | 0    |
+/// | Keyword                                 | 3    |
+/// | Summary                                 | 6    |
+/// | Component ID, desktop ID, package name  | 9    |
+/// | Category                                | 12   |
+/// | Description                             | 15   |
+///
+/// IDs are compared by their dot separated parts, so `firefox` equals a part of
+/// `org.mozilla.firefox`. Descriptions are only searched when no other field matches, and
+/// always count as containing the phrase. The best weight of all fields is used, and remote
+/// results that match no field get [`SEARCH_WEIGHT_REMOTE`]. Local results that match no
+/// field may still match with typos, see [`fuzzy_weight`].
+fn search_weight(regex: &regex::Regex, id: &str, info: &AppInfo) -> Option<usize> {
+    let mut weights = Vec::with_capacity(5);
+    weights.extend(field_weight(regex, 0, &info.name));
+    if weights.first() == Some(&0) {
+        // Nothing is better than an exact name match
+        return Some(0);
+    }
+    weights.extend(
+        info.keywords
+            .iter()
+            .filter_map(|keyword| field_weight(regex, 3, keyword))
+            .min(),
+    );
+    weights.extend(field_weight(regex, 6, &info.summary));
+    let ids = std::iter::once(id)
+        .chain(
+            info.desktop_ids
+                .iter()
+                .map(|x| x.trim_end_matches(".desktop")),
+        )
+        .chain(info.pkgname.as_deref());
+    weights.extend(
+        ids.flat_map(|id| id.split('.'))
+            .filter_map(|part| field_weight(regex, 9, part))
+            .min(),
+    );
+    weights.extend(
+        info.categories
+            .iter()
+            .filter_map(|category| field_weight(regex, 12, category))
+            .min(),
+    );
+    if weights.is_empty() {
+        // Descriptions are long, so only search them when nothing else matched
+        if info.description.texts().any(|text| regex.is_match(text)) {
+            return Some(15 + 2);
+        }
+    }
+    weights.into_iter().min()
+}
+
+/// Base weight of names matching with typos, after all exact matches
+const SEARCH_WEIGHT_FUZZY_NAME: usize = 19;
+/// Base weight of keywords matching with typos
+const SEARCH_WEIGHT_FUZZY_KEYWORD: usize = 23;
+
+/// Weight of a search match with typos, like `firefx` for Firefox, lower is better.
+///
+/// Only names and keywords are compared, adding the number of typos (at most 3) to
+/// [`SEARCH_WEIGHT_FUZZY_NAME`] or [`SEARCH_WEIGHT_FUZZY_KEYWORD`]. These weights are higher
+/// than any weight from [`search_weight`], so this is only needed when that finds nothing.
+fn fuzzy_weight(query: &FuzzyQuery, info: &AppInfo) -> Option<usize> {
+    if let Some(typos) = query.typos(&info.name) {
+        return Some(SEARCH_WEIGHT_FUZZY_NAME + typos.min(3));
+    }
+    info.keywords
+        .iter()
+        .filter_map(|keyword| query.typos(keyword))
+        .min()
+        .map(|typos| SEARCH_WEIGHT_FUZZY_KEYWORD + typos.min(3))
+}
+
+/// Weight of a search match for all search words and phrases, each of which must match. The
+/// worst weight is used. If the words do not all match, they may still match together with
+/// typos, but phrases must always match exactly.
+fn query_weight(
+    words: &[regex::Regex],
+    phrases: &[regex::Regex],
+    fuzzy_opt: Option<&FuzzyQuery>,
+    id: &str,
+    info: &AppInfo,
+) -> Option<usize> {
+    let mut weight = 0;
+    for regex in phrases.iter() {
+        weight = weight.max(search_weight(regex, id, info)?);
+    }
+    let words_weight_opt = words.iter().try_fold(0, |weight, regex| {
+        Some(weight.max(search_weight(regex, id, info)?))
+    });
+    match words_weight_opt {
+        Some(words_weight) => Some(weight.max(words_weight)),
+        None => Some(weight.max(fuzzy_weight(fuzzy_opt?, info)?)),
+    }
+}
+
+/// Case insensitive regex finding a search word or phrase
+fn search_regex(text: &str) -> Result<regex::Regex, regex::Error> {
+    regex::RegexBuilder::new(&regex::escape(text))
+        .case_insensitive(true)
+        .build()
+}
+
+/// Check if a package is installed on a branch, any branch matches if none is given
+fn package_on_branch(package: &Package, branch_opt: Option<&Branch>) -> bool {
+    match (branch_opt, package.branch_opt()) {
+        (Some(branch), Some(package_branch)) => branch.same_ref(&package_branch),
+        _ => true,
+    }
+}
+
+/// Warning for packages that are no longer supported
+fn eol_warning(package: &Package) -> Option<String> {
+    if let Some(eol_rebase) = package.extra.get("eol_rebase") {
+        Some(fl!("end-of-life-rebase", id = eol_rebase.as_str()))
+    } else if let Some(eol) = package.extra.get("eol") {
+        if eol.is_empty() {
+            Some(fl!("end-of-life"))
+        } else {
+            Some(fl!("end-of-life-reason", reason = eol.as_str()))
+        }
+    } else {
+        package
+            .extra
+            .get("runtime_eol")
+            .map(|runtime_eol| fl!("runtime-end-of-life", reason = runtime_eol.as_str()))
+    }
+}
+
+fn eol_badge<'a>(warning: String) -> Element<'a, Message> {
+    widget::row::with_children(vec![
+        widget::icon::icon(
+            widget::icon::from_name("dialog-warning-symbolic")
+                .size(16)
+                .handle(),
+        )
+        .size(16)
+        .into(),
+        widget::text(warning).into(),
+    ])
+    .align_items(Alignment::Center)
+    .spacing(4)
+    .into()
+}
+
+/// Whether an app is free software, with its license expression and the license of its metadata
+fn license_badge<'a>(info: &'a AppInfo, spacing: u16) -> Element<'a, Message> {
+    let kind = info.license_kind;
+    let mut row = widget::row::with_children(vec![
+        widget::icon::icon(widget::icon::from_name(kind.icon()).size(16).handle())
+            .size(16)
+            .into(),
+        widget::text(kind.name()).into(),
+    ]);
+    if let Some(project_license) = &info.project_license_opt {
+        row = row.push(widget::text(project_license));
+    }
+    let mut column = widget::column::with_capacity(2)
+        .push(row.align_items(Alignment::Center).spacing(spacing))
+        .spacing(spacing);
+    if let Some(metadata_license) = &info.metadata_license_opt {
+        column = column.push(widget::text(fl!(
+            "metadata-license",
+            license = metadata_license.as_str()
+        )));
+    }
+    column.into()
+}
+
+/// Icon, name, and summary of a search result with the labels of its sources
+fn result_row<'a>(
+    result: &'a SearchResult,
+    spacing: u16,
+    message: Message,
+) -> Element<'a, Message> {
+    let source = result.source();
+    let mut sources_column = widget::column::with_capacity(result.sources.len());
+    for other in result.sources.iter() {
+        sources_column = sources_column.push(widget::text(other.label()));
+    }
+    widget::mouse_area(
+        widget::row::with_children(vec![
+            widget::icon::icon(source.icon.clone())
+                .size(ICON_SIZE_LIST)
+                .into(),
+            widget::column::with_children(vec![
+                widget::text(&source.info.name).into(),
+                widget::text(&source.info.summary).into(),
+            ])
+            .into(),
+            widget::horizontal_space(Length::Fill).into(),
+            sources_column.align_items(Alignment::End).into(),
+        ])
+        .align_items(Alignment::Center)
+        .spacing(spacing),
+    )
+    .on_press(message)
+    .into()
+}
+
+/// Buttons with an icon and a name, four per row
+fn button_grid<'a>(
+    buttons: Vec<(&'static str, String, Message)>,
+    spacing: u16,
+) -> Element<'a, Message> {
+    let mut column = widget::column::with_capacity((buttons.len() + 3) / 4).spacing(spacing);
+    let mut buttons = buttons.into_iter().peekable();
+    while buttons.peek().is_some() {
+        let mut row = widget::row::with_capacity(4).spacing(spacing);
+        let mut count = 0;
+        for (icon, name, message) in buttons.by_ref().take(4) {
+            row = row.push(
+                widget::button(
+                    widget::row::with_children(vec![
+                        widget::icon::icon(widget::icon::from_name(icon).size(16).handle())
+                            .size(16)
+                            .into(),
+                        widget::text(name).into(),
+                    ])
+                    .align_items(Alignment::Center)
+                    .spacing(spacing),
+                )
+                .width(Length::Fill)
+                .on_press(message),
+            );
+            count += 1;
+        }
+        // Keep buttons the same width in the last row
+        for _ in count..4 {
+            row = row.push(widget::horizontal_space(Length::Fill));
+        }
+        column = column.push(row);
+    }
+    column.into()
+}
+
+/// Link buttons for project URLs, opened with the default handler
+fn project_urls_column<'a>(urls: &'a [ProjectUrl], spacing: u16) -> Element<'a, Message> {
+    button_grid(
+        urls.iter()
+            .map(|url| {
+                (
+                    url.kind.icon(),
+                    url.kind.name(),
+                    Message::OpenUrl(url.url.clone()),
+                )
+            })
+            .collect(),
+        spacing,
+    )
+}
+
+/// Text of a paragraph or list item, wrapping at the width of the page
+fn spans_element<'a>(spans: &'a [DescriptionSpan]) -> Element<'a, Message> {
+    //TODO: show emphasis in bold and code in a monospace font when rich text can wrap
+    widget::text(spans_text(spans)).width(Length::Fill).into()
+}
+
+/// Paragraphs and lists of an app description
+fn description_column<'a>(description: &'a Description, spacing: u16) -> Element<'a, Message> {
+    let list_item = |marker: String, spans: &'a [DescriptionSpan]| -> Element<'a, Message> {
+        widget::row::with_children(vec![widget::text(marker).into(), spans_element(spans)])
+            .padding([0, 0, 0, spacing])
+            .spacing(spacing)
+            .into()
+    };
+    let mut column = widget::column::with_capacity(description.blocks.len()).spacing(spacing);
+    for block in description.blocks.iter() {
+        match block {
+            DescriptionBlock::Paragraph(spans) => {
+                column = column.push(spans_element(spans));
+            }
+            DescriptionBlock::OrderedList(items) => {
+                for (i, spans) in items.iter().enumerate() {
+                    column = column.push(list_item(format!("{}.", i + 1), spans));
+                }
+            }
+            DescriptionBlock::UnorderedList(items) => {
+                for spans in items.iter() {
+                    column = column.push(list_item("•".to_string(), spans));
+                }
+            }
+        }
+    }
+    column.into()
+}
+
+/// Top-level media types of MIME types, and those used by shared-mime-info
+const MIME_TOP_LEVEL_TYPES: &[&str] = &[
+    "application",
+    "audio",
+    "font",
+    "image",
+    "inode",
+    "message",
+    "model",
+    "multipart",
+    "text",
+    "video",
+    "x-content",
+];
+
+/// MIME type of a search input given as a MIME type, file path, or file URI
+fn input_mimetype(input: &str) -> Option<String> {
+    let input = input.trim();
+    let path = match input.strip_prefix("file://") {
+        // File URIs may name the local host and escape characters like spaces
+        Some(uri_path) => percent_decode(uri_path.strip_prefix("localhost").unwrap_or(uri_path))?,
+        None => input.to_string(),
+    };
+    if path.starts_with('/') {
+        //TODO: install package files with backends that have the local_files capability
+        return if Path::new(&path).is_file() {
+            path_mimetype(&path)
+        } else {
+            None
+        };
+    }
+    // MIME types look like type/subtype, such as image/png
+    let (kind, subtype) = input.split_once('/')?;
+    if MIME_TOP_LEVEL_TYPES
+        .iter()
+        .any(|top_level| kind.eq_ignore_ascii_case(top_level))
+        && !subtype.is_empty()
+        && !subtype.contains(|c: char| c.is_whitespace() || c == '/')
+    {
+        Some(input.to_lowercase())
+    } else {
+        None
+    }
+}
+
+/// Decode %XX escapes in a URI path, or None if they are invalid or not UTF-8
+fn percent_decode(text: &str) -> Option<String> {
+    let mut bytes = Vec::with_capacity(text.len());
+    let mut iter = text.bytes();
+    while let Some(byte) = iter.next() {
+        if byte == b'%' {
+            let high = char::from(iter.next()?).to_digit(16)?;
+            let low = char::from(iter.next()?).to_digit(16)?;
+            bytes.push((high * 16 + low) as u8);
+        } else {
+            bytes.push(byte);
+        }
+    }
+    String::from_utf8(bytes).ok()
+}
+
+/// Guess the MIME type of a file using the desktop tools for it
+fn path_mimetype(path: &str) -> Option<String> {
+    let commands: [(&str, &[&str]); 2] = [
+        ("xdg-mime", &["query", "filetype"]),
+        ("file", &["--brief", "--mime-type"]),
+    ];
+    for (program, args) in commands {
+        match process::Command::new(program).args(args).arg(path).output() {
+            Ok(output) => {
+                let mimetype = String::from_utf8_lossy(&output.stdout).trim().to_string();
+                if output.status.success() && !mimetype.is_empty() {
+                    return Some(mimetype);
+                }
+            }
+            Err(err) => {
+                log::debug!("failed to run {}: {}", program, err);
+            }
+        }
+    }
+    log::warn!("failed to find MIME type of {:?}", path);
+    None
+}
+
+/// Weight of an app handling a MIME type, lower is better
+fn mimetype_weight(mimetype: &str, info: &AppInfo) -> Option<usize> {
+    if info
+        .mimetypes
+        .iter()
+        .any(|x| x.eq_ignore_ascii_case(mimetype))
+    {
+        // Handles this exact type
+        return Some(0);
+    }
+    let family = format!("{}/*", mimetype.split('/').next()?);
+    if info
+        .mimetypes
+        .iter()
+        .any(|x| x.eq_ignore_ascii_case(&family))
+    {
+        // Handles all types in the family, such as image/*
+        return Some(1);
+    }
+    None
+}
+
+/// Find apps that can open a MIME type, remote searches are only done when `remote` is set
+fn search_mimetype(
+    backends: &Backends,
+    mimetype: &str,
+    hide_proprietary: bool,
+    remote: bool,
+) -> Vec<(AppSource, usize)> {
+    let mut matches = Vec::<(AppSource, usize)>::new();
+    for (backend_name, backend) in backends.iter() {
+        let appstream_cache = backend.info_cache();
+        let mut backend_matches = appstream_cache
+            .infos
+            .par_iter()
+            .filter_map(|(id, info)| {
+                if hide_proprietary && info.license_kind == LicenseKind::Proprietary {
+                    return None;
+                }
+                let weight = mimetype_weight(mimetype, info)?;
+                Some((
+                    AppSource {
+                        backend_name: *backend_name,
+                        id: id.clone(),
+                        icon: appstream_cache.icon(info),
+                        info: info.clone(),
+                    },
+                    weight,
+                ))
+            })
+            .collect();
+        matches.append(&mut backend_matches);
+
+        if !remote {
+            continue;
+        }
+
+        // Some backends know handlers that are missing from appstream data
+        match backend.what_provides(mimetype) {
+            Ok(packages) => {
+                for package in packages {
+                    if matches.iter().any(|(source, _)| {
+                        source.backend_name == *backend_name && source.id == package.id
+                    }) {
+                        continue;
+                    }
+                    let info = match backend.info(&package) {
+                        Ok(ok) => ok,
+                        Err(err) => {
+                            log::warn!("failed to get info for {}: {}", package.id, err);
+                            continue;
+                        }
+                    };
+                    if hide_proprietary && info.license_kind == LicenseKind::Proprietary {
+                        continue;
+                    }
+                    matches.push((
+                        AppSource {
+                            backend_name: *backend_name,
+                            id: package.id,
+                            icon: package.icon,
+                            info,
+                        },
+                        2,
+                    ));
+                }
+            }
+            Err(err) => {
+                log::warn!(
+                    "failed to find providers of {} in {} backend: {}",
+                    mimetype,
+                    backend_name,
+                    err
+                );
+            }
+        }
+    }
+    matches
+}
+
+/// Watch backend paths with inotify, sending the names of changed backends
+fn watch(
+    watch_paths: Vec<(&'static str, Vec<PathBuf>)>,
+    msg_tx: &mut futures::channel::mpsc::Sender<Message>,
+) {
+    use notify::Watcher;
+
+    let (event_tx, event_rx) = mpsc::channel();
+    let mut watcher = match notify::recommended_watcher(event_tx) {
+        Ok(ok) => ok,
+        Err(err) => {
+            log::error!("failed to create watcher: {}", err);
+            return;
+        }
+    };
+    for (_backend_name, paths) in watch_paths.iter() {
+        for path in paths.iter() {
+            if let Err(err) = watcher.watch(path, notify::RecursiveMode::NonRecursive) {
+                log::warn!("failed to watch {:?}: {}", path, err);
+            }
+        }
+    }
+
+    let changed_backends = |event: notify::Result<notify::Event>| -> Vec<&'static str> {
+        let event = match event {
+            Ok(ok) => ok,
+            Err(err) => {
+                log::warn!("failed to watch: {}", err);
+                return Vec::new();
+            }
+        };
+        if event.kind.is_access() {
+            return Vec::new();
+        }
+        watch_paths
+            .iter()
+            .filter(|(_, paths)| {
+                paths.iter().any(|path| {
+                    event
+                        .paths
+                        .iter()
+                        .any(|event_path| event_path == path || event_path.parent() == Some(path))
+                })
+            })
+            .map(|(backend_name, _)| *backend_name)
+            .collect()
+    };
+
+    loop {
+        // Stop when the subscription is dropped, like after backends are reloaded
+        let event = match event_rx.recv_timeout(Duration::from_secs(1)) {
+            Ok(ok) => ok,
+            Err(mpsc::RecvTimeoutError::Timeout) => {
+                if msg_tx.is_closed() {
+                    return;
+                }
+                continue;
+            }
+            Err(mpsc::RecvTimeoutError::Disconnected) => return,
+        };
+        let mut backend_names = changed_backends(event);
+        if backend_names.is_empty() {
+            continue;
+        }
+        // Wait for a burst of changes to finish
+        loop {
+            match event_rx.recv_timeout(Duration::from_secs(1)) {
+                Ok(event) => {
+                    for backend_name in changed_backends(event) {
+                        if !backend_names.contains(&backend_name) {
+                            backend_names.push(backend_name);
+                        }
+                    }
+                }
+                Err(mpsc::RecvTimeoutError::Timeout) => break,
+                Err(mpsc::RecvTimeoutError::Disconnected) => return,
+            }
+        }
+        if futures::executor::block_on(msg_tx.send(Message::WatchChanged(backend_names))).is_err() {
+            return;
+        }
+    }
+}
+
+/// Position of a backend in the preferred source order, unlisted backends go last
+fn source_priority(preferred_sources: &[String], backend_name: &str) -> usize {
+    preferred_sources
+        .iter()
+        .position(|x| x == backend_name)
+        .unwrap_or(preferred_sources.len())
+}
+
+/// An app as provided by one backend
 #[derive(Clone, Debug)]
-pub struct SearchResult {
+pub struct AppSource {
     backend_name: &'static str,
     id: String,
     icon: widget::icon::Handle,
     info: Arc<AppInfo>,
+}
+
+impl AppSource {
+    fn keys(&self) -> Vec<String> {
+        let mut keys = Vec::with_capacity(self.info.desktop_ids.len() + 1);
+        keys.push(app_key(&self.id));
+        for desktop_id in self.info.desktop_ids.iter() {
+            let key = app_key(desktop_id);
+            if !keys.contains(&key) {
+                keys.push(key);
+            }
+        }
+        keys
+    }
+
+    fn label(&self) -> String {
+        match &self.info.origin_opt {
+            Some(origin) => format!("{} ({})", backend_label(self.backend_name), origin),
+            None => backend_label(self.backend_name),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct SearchResult {
+    /// The same app from every backend, in preferred order
+    sources: Vec<AppSource>,
     weight: usize,
 }
 
+impl SearchResult {
+    fn source(&self) -> &AppSource {
+        &self.sources[0]
+    }
+
+    /// Group matches by component ID or desktop ID, keeping the best weight of each group
+    fn group(matches: Vec<(AppSource, usize)>, preferred_sources: &[String]) -> Vec<Self> {
+        let mut results = Vec::<Self>::new();
+        let mut result_keys = HashMap::<String, usize>::new();
+        for (source, weight) in matches {
+            let keys = source.keys();
+            let result_i = match keys.iter().find_map(|key| result_keys.get(key)) {
+                Some(result_i) => {
+                    let result = &mut results[*result_i];
+                    result.weight = cmp::min(result.weight, weight);
+                    result.sources.push(source);
+                    *result_i
+                }
+                None => {
+                    results.push(Self {
+                        sources: vec![source],
+                        weight,
+                    });
+                    results.len() - 1
+                }
+            };
+            for key in keys {
+                result_keys.entry(key).or_insert(result_i);
+            }
+        }
+        for result in results.iter_mut() {
+            result
+                .sources
+                .sort_by_key(|source| source_priority(preferred_sources, source.backend_name));
+        }
+        results
+    }
+}
+
+/// Apps in a main category, optionally narrowed to one of its subcategories
+#[derive(Clone, Debug)]
+pub struct CategoryPage {
+    category: Category,
+    subcategory_opt: Option<&'static str>,
+    /// Subcategories that have apps
+    subcategories: Vec<&'static str>,
+    results_opt: Option<Vec<SearchResult>>,
+}
+
+/// Sort category results, newest releases first for recency and by name otherwise
+fn sort_category_results(results: &mut [SearchResult], sort: CategorySort) {
+    let latest_release = |result: &SearchResult| {
+        result
+            .sources
+            .iter()
+            .filter_map(|source| source.info.releases.first()?.timestamp_opt)
+            .max()
+    };
+    results.sort_by(|a, b| {
+        let ordering = match sort {
+            CategorySort::Name => cmp::Ordering::Equal,
+            CategorySort::Recent => latest_release(b).cmp(&latest_release(a)),
+        };
+        ordering.then_with(|| {
+            lexical_sort::natural_lexical_cmp(&a.source().info.name, &b.source().info.name)
+        })
+    });
+}
+
 #[derive(Clone, Debug)]
 pub struct Selected {
-    backend_name: &'static str,
-    id: String,
-    icon: widget::icon::Handle,
-    info: Arc<AppInfo>,
+    sources: Vec<AppSource>,
+    source_labels: Vec<String>,
+    source_i: usize,
+    /// Installed branch to show and operate on, if opened from an installed package
+    branch_opt: Option<Branch>,
+    screenshot_i: usize,
+    /// Show the current screenshot in the full size viewer
+    screenshot_full: bool,
+    /// Show all releases instead of the latest and those newer than installed
+    releases_expanded: bool,
+}
+
+impl Selected {
+    fn new(sources: Vec<AppSource>, source_i: usize) -> Self {
+        let source_labels = sources.iter().map(|source| source.label()).collect();
+        Self {
+            sources,
+            source_labels,
+            source_i,
+            branch_opt: None,
+            screenshot_i: 0,
+            screenshot_full: false,
+            releases_expanded: false,
+        }
+    }
+
+    fn source(&self) -> &AppSource {
+        &self.sources[self.source_i]
+    }
 }
 
 /// The [`App`] stores application-specific state.
@@ -161,6 +1007,9 @@ pub struct App {
     config: Config,
     locale: String,
     app_themes: Vec<String>,
+    category_opt: Option<CategoryPage>,
+    category_sort: CategorySort,
+    category_sorts: Vec<String>,
     backends: Backends,
     context_page: ContextPage,
     key_binds: HashMap<KeyBind, Action>,
@@ -168,11 +1017,75 @@ pub struct App {
     search_id: widget::Id,
     search_input: String,
     installed: Option<Vec<(&'static str, Package)>>,
+    updates: Option<Vec<(&'static str, Package)>>,
+    sources: Vec<(&'static str, Vec<String>)>,
+    pending_operation_id: u64,
+    pending_operations: BTreeMap<u64, (Operation, f32)>,
+    /// App id and error of the last failed change to a hold, shown on its details page
+    hold_error_opt: Option<(String, String)>,
     search_results: Option<(String, Vec<SearchResult>)>,
     selected_opt: Option<Selected>,
+    branches: Option<(&'static str, String, Vec<Branch>)>,
+    history_opt: Option<Vec<HistoryEntry>>,
+    history_filter: String,
+    offline_states: Vec<(&'static str, OfflineState)>,
+    offline_reboot_confirm: Option<&'static str>,
+    watch_paths: Vec<(&'static str, Vec<PathBuf>)>,
+    reloading: HashSet<&'static str>,
+    /// Backends being refreshed, or the time they finished refreshing
+    refreshing: HashMap<&'static str, Option<Instant>>,
+    /// Loaded screenshots by URL, None while loading, failed loads are removed so they are retried
+    screenshots: HashMap<String, Option<widget::image::Handle>>,
 }
 
 impl App {
+    fn load_history(&self) -> Command<Message> {
+        Command::perform(
+            async move {
+                tokio::task::spawn_blocking(move || match history::load() {
+                    Ok(entries) => message::app(Message::HistoryLoaded(entries)),
+                    Err(err) => {
+                        log::error!("failed to load history: {}", err);
+                        message::none()
+                    }
+                })
+                .await
+                .unwrap_or(message::none())
+            },
+            |x| x,
+        )
+    }
+
+    fn hold(
+        &self,
+        backend_name: &'static str,
+        id: String,
+        runtime: bool,
+        hold: bool,
+    ) -> Command<Message> {
+        let backend = match self.backends.get(backend_name) {
+            Some(some) => some.clone(),
+            None => {
+                log::error!("failed to find backend {:?}", backend_name);
+                return Command::none();
+            }
+        };
+        Command::perform(
+            async move {
+                tokio::task::spawn_blocking(move || match backend.hold(&id, runtime, hold) {
+                    Ok(()) => message::app(Message::RefreshInstalled),
+                    Err(err) => {
+                        log::error!("failed to change hold of {}: {}", id, err);
+                        message::app(Message::HoldError(id, err.to_string()))
+                    }
+                })
+                .await
+                .unwrap_or(message::none())
+            },
+            |x| x,
+        )
+    }
+
     fn open_desktop_id(&self, mut desktop_id: String) -> Command<Message> {
         Command::perform(
             async move {
@@ -219,89 +1132,484 @@ impl App {
         )
     }
 
-    fn search(&self) -> Command<Message> {
-        let input = self.search_input.clone();
-        let pattern = regex::escape(&input);
-        let regex = match regex::RegexBuilder::new(&pattern)
-            .case_insensitive(true)
-            .build()
-        {
-            Ok(ok) => ok,
-            Err(err) => {
-                log::warn!("failed to parse regex {:?}: {}", pattern, err);
-                return Command::none();
-            }
+    fn capabilities(&self, backend_name: &str) -> Capabilities {
+        self.backends
+            .get(backend_name)
+            .map_or_else(Capabilities::default, |backend| backend.capabilities())
+    }
+
+    fn operation(&mut self, operation: Operation) {
+        if !self.backends.contains_key(operation.backend_name) {
+            log::warn!(
+                "{:?} of {} failed: {} backend is not enabled",
+                operation.kind,
+                operation.package_id,
+                operation.backend_name
+            );
+            return;
+        }
+        let id = self.pending_operation_id;
+        self.pending_operation_id += 1;
+        self.pending_operations.insert(id, (operation, 0.0));
+    }
+
+    /// List branches of the selected app, if supported by its backend
+    fn update_branches(&mut self) -> Command<Message> {
+        self.branches = None;
+        let source = match &self.selected_opt {
+            Some(selected) => selected.source(),
+            None => return Command::none(),
         };
-        let backends = self.backends.clone();
+        if !self.capabilities(source.backend_name).branches {
+            return Command::none();
+        }
+        let backend = match self.backends.get(source.backend_name) {
+            Some(some) => some.clone(),
+            None => return Command::none(),
+        };
+        let backend_name = source.backend_name;
+        let id = source.id.clone();
+        Command::perform(
+            async move {
+                tokio::task::spawn_blocking(move || match backend.branches(&id) {
+                    Ok(branches) => message::app(Message::Branches(backend_name, id, branches)),
+                    Err(err) => {
+                        log::error!("failed to list branches of {}: {}", id, err);
+                        message::none()
+                    }
+                })
+                .await
+                .unwrap_or(message::none())
+            },
+            |x| x,
+        )
+    }
+
+    /// Load a screenshot from a local catalog or the media cache, if not already loaded or loading
+    fn load_screenshot(&mut self, image: &ScreenshotImage) -> Command<Message> {
+        if self.screenshots.contains_key(&image.url) {
+            return Command::none();
+        }
+        self.screenshots.insert(image.url.clone(), None);
+        let url = image.url.clone();
+        let path_opt = image.path_opt.clone();
+        Command::perform(
+            async move {
+                tokio::task::spawn_blocking(move || {
+                    let path_res = match path_opt.map(PathBuf::from).filter(|x| x.is_file()) {
+                        Some(path) => Ok(path),
+                        None => media_cache::get(&url),
+                    };
+                    match path_res {
+                        Ok(path) => message::app(Message::ScreenshotLoaded(
+                            url,
+                            Some(widget::image::Handle::from_path(path)),
+                        )),
+                        Err(err) => {
+                            log::warn!("failed to load screenshot {}: {}", url, err);
+                            message::app(Message::ScreenshotLoaded(url, None))
+                        }
+                    }
+                })
+                .await
+                .unwrap_or(message::none())
+            },
+            |x| x,
+        )
+    }
+
+    /// Load the screenshots of the selected app, and the full size image if it is shown
+    fn update_screenshots(&mut self) -> Command<Message> {
+        let (info, screenshot_i, screenshot_full) = match &self.selected_opt {
+            Some(selected) => (
+                selected.source().info.clone(),
+                selected.screenshot_i,
+                selected.screenshot_full,
+            ),
+            None => return Command::none(),
+        };
+        let mut commands = Vec::with_capacity(info.screenshots.len() + 1);
+        for screenshot in info.screenshots.iter() {
+            if let Some(image) = screenshot.image(SCREENSHOT_WIDTH) {
+                commands.push(self.load_screenshot(image));
+            }
+        }
+        if screenshot_full {
+            if let Some(image) = info.screenshots.get(screenshot_i).and_then(|x| x.source()) {
+                commands.push(self.load_screenshot(image));
+            }
+        }
+        Command::batch(commands)
+    }
+
+    /// Select an app, finding the same app in other backends for the source picker
+    fn select(&self, source: AppSource) -> Selected {
+        let base_id = source.id.trim_end_matches(".desktop").to_string();
+        let desktop_id = format!("{}.desktop", base_id);
+        // Match apps with other ids by desktop ids, like search results are grouped
+        let keys = source.keys();
+        let matches_keys = |id: &str, info: &AppInfo| {
+            keys.contains(&app_key(id))
+                || info
+                    .desktop_ids
+                    .iter()
+                    .any(|desktop_id| keys.contains(&app_key(desktop_id)))
+        };
+        let mut sources = Vec::with_capacity(self.backends.len());
+        for (backend_name, backend) in self.backends.iter() {
+            if *backend_name == source.backend_name {
+                continue;
+            }
+            let info_cache = backend.info_cache();
+            let found_opt = [&base_id, &desktop_id]
+                .into_iter()
+                .find_map(|id| info_cache.infos.get_key_value(id))
+                .or_else(|| {
+                    info_cache
+                        .infos
+                        .par_iter()
+                        .find_first(|(id, info)| matches_keys(id, info))
+                });
+            if let Some((id, info)) = found_opt {
+                sources.push(AppSource {
+                    backend_name,
+                    id: id.clone(),
+                    icon: info_cache.icon(info),
+                    info: info.clone(),
+                });
+            }
+        }
+        let selected_backend = source.backend_name;
+        sources.push(source);
+        sources.sort_by_key(|source| {
+            source_priority(&self.config.preferred_sources, source.backend_name)
+        });
+        // Each backend provides at most one source
+        let source_i = sources
+            .iter()
+            .position(|source| source.backend_name == selected_backend)
+            .unwrap_or(0);
+        Selected::new(sources, source_i)
+    }
+
+    /// List apps of the open category page across all backends
+    fn update_category(&self) -> Command<Message> {
+        let (category, subcategory_opt) = match &self.category_opt {
+            Some(page) => (page.category, page.subcategory_opt),
+            None => return Command::none(),
+        };
+        let backends = self.backends.clone();
+        let preferred_sources = self.config.preferred_sources.clone();
+        let hide_proprietary = self.config.hide_proprietary;
+        let sort = self.category_sort;
         Command::perform(
             async move {
                 tokio::task::spawn_blocking(move || {
                     let start = Instant::now();
-                    let mut results = Vec::<SearchResult>::new();
-                    //TODO: par_iter?
+                    let mut subcategories = Vec::new();
+                    let mut matches = Vec::<(AppSource, usize)>::new();
                     for (backend_name, backend) in backends.iter() {
                         let appstream_cache = backend.info_cache();
-                        let mut backend_results = appstream_cache
-                            .infos
-                            .par_iter()
-                            .filter_map(|(id, info)| {
-                                //TODO: fuzzy match (nucleus-matcher?)
-                                let weight_opt = match regex.find(&info.name) {
-                                    Some(mat) => {
-                                        if mat.range().start == 0 {
-                                            if mat.range().end == info.name.len() {
-                                                // Name equals search phrase
-                                                Some(0)
-                                            } else {
-                                                // Name starts with search phrase
-                                                Some(1)
-                                            }
-                                        } else {
-                                            // Name contains search phrase
-                                            Some(2)
-                                        }
-                                    }
-                                    None => match regex.find(&info.summary) {
-                                        Some(mat) => {
-                                            if mat.range().start == 0 {
-                                                if mat.range().end == info.summary.len() {
-                                                    // Summary equals search phrase
-                                                    Some(3)
-                                                } else {
-                                                    // Summary starts with search phrase
-                                                    Some(4)
-                                                }
-                                            } else {
-                                                // Summary contains search phrase
-                                                Some(5)
-                                            }
-                                        }
-                                        None => None,
-                                    },
-                                };
-                                if let Some(weight) = weight_opt {
-                                    Some(SearchResult {
-                                        backend_name,
+                        // Additional categories are shared, so only use them with the main category
+                        let category_ids = match appstream_cache.categories.get(category.id()) {
+                            Some(some) => some,
+                            None => continue,
+                        };
+                        for subcategory in category.subcategories() {
+                            if subcategories.contains(subcategory) {
+                                continue;
+                            }
+                            if let Some(ids) = appstream_cache.categories.get(*subcategory) {
+                                if !ids.is_disjoint(category_ids) {
+                                    subcategories.push(*subcategory);
+                                }
+                            }
+                        }
+                        let subcategory_ids_opt = match subcategory_opt {
+                            Some(subcategory) => {
+                                match appstream_cache.categories.get(subcategory) {
+                                    Some(some) => Some(some),
+                                    None => continue,
+                                }
+                            }
+                            None => None,
+                        };
+                        for id in category_ids.iter() {
+                            if let Some(subcategory_ids) = subcategory_ids_opt {
+                                if !subcategory_ids.contains(id) {
+                                    continue;
+                                }
+                            }
+                            if let Some(info) = appstream_cache.infos.get(id) {
+                                if hide_proprietary && info.license_kind == LicenseKind::Proprietary
+                                {
+                                    continue;
+                                }
+                                matches.push((
+                                    AppSource {
+                                        backend_name: *backend_name,
                                         id: id.clone(),
                                         icon: appstream_cache.icon(info),
                                         info: info.clone(),
-                                        weight,
-                                    })
+                                    },
+                                    0,
+                                ));
+                            }
+                        }
+                    }
+                    // Keep the order of the menu specification
+                    subcategories.sort_by_key(|subcategory| {
+                        category
+                            .subcategories()
+                            .iter()
+                            .position(|x| x == subcategory)
+                    });
+                    let mut results = SearchResult::group(matches, &preferred_sources);
+                    sort_category_results(&mut results, sort);
+                    let duration = start.elapsed();
+                    log::info!(
+                        "listed {} apps in category {:?} {:?} in {:?}",
+                        results.len(),
+                        category,
+                        subcategory_opt,
+                        duration
+                    );
+                    message::app(Message::CategoryResults(
+                        category,
+                        subcategory_opt,
+                        subcategories,
+                        results,
+                    ))
+                })
+                .await
+                .unwrap_or(message::none())
+            },
+            |x| x,
+        )
+    }
+
+    /// Search backend catalogs, remote searches are only done when `remote` is set
+    fn search(&self, remote: bool) -> Command<Message> {
+        let input = self.search_input.clone();
+        let query = SearchQuery::parse(&input);
+        // Searches with filters or phrases are never paths or MIME types
+        let mimetype_search = query.filters.is_empty() && query.phrases.is_empty();
+        let regexes = |texts: &[String]| {
+            texts
+                .iter()
+                .map(|text| search_regex(text))
+                .collect::<Result<Vec<_>, _>>()
+        };
+        let (word_regexes, phrase_regexes) = match (regexes(&query.words), regexes(&query.phrases))
+        {
+            (Ok(words), Ok(phrases)) => (words, phrases),
+            (Err(err), _) | (_, Err(err)) => {
+                log::warn!("failed to parse search regex: {}", err);
+                return Command::none();
+            }
+        };
+        // Only words outside of quotes may have typos
+        let fuzzy_opt = FuzzyQuery::new(&query.words.join(" "));
+        // License filters show proprietary apps even if they are hidden
+        let hide_proprietary = self.config.hide_proprietary
+            && !query
+                .filters
+                .iter()
+                .any(|filter| matches!(filter, SearchFilter::License(_)));
+        // Installed packages are only needed by installed filters
+        let mut installed = HashMap::<&'static str, HashSet<String>>::new();
+        if query
+            .filters
+            .iter()
+            .any(|filter| matches!(filter, SearchFilter::Installed(_)))
+        {
+            for (backend_name, package) in self.installed.iter().flatten() {
+                installed
+                    .entry(*backend_name)
+                    .or_default()
+                    .insert(package.id.clone());
+            }
+        }
+        let backends = self.backends.clone();
+        let preferred_sources = self.config.preferred_sources.clone();
+        Command::perform(
+            async move {
+                tokio::task::spawn_blocking(move || {
+                    let start = Instant::now();
+                    // Finding the MIME type of a file runs external tools, so it is done here
+                    let mut mimetype_opt = if mimetype_search {
+                        input_mimetype(&input)
+                    } else {
+                        None
+                    };
+                    let mut matches = Vec::<(AppSource, usize)>::new();
+                    if let Some(mimetype) = &mimetype_opt {
+                        matches = search_mimetype(&backends, mimetype, hide_proprietary, remote);
+                        // Inputs like "AC/DC" look like MIME types, so search them as text too
+                        if matches.is_empty() {
+                            log::info!("no apps for MIME type {:?}, searching for text", mimetype);
+                            mimetype_opt = None;
+                        }
+                    }
+                    if mimetype_opt.is_none() {
+                        //TODO: par_iter?
+                        for (backend_name, backend) in backends.iter() {
+                            let backend_start = Instant::now();
+                            let appstream_cache = backend.info_cache();
+                            let backend_installed = installed.get(backend_name);
+                            let filter = |id: &str, info: &AppInfo| {
+                                if hide_proprietary && info.license_kind == LicenseKind::Proprietary
+                                {
+                                    return false;
+                                }
+                                query.matches(
+                                    backend_name,
+                                    info,
+                                    backend_installed.map_or(false, |ids| ids.contains(id)),
+                                )
+                            };
+                            let source = |id: &str, info: &Arc<AppInfo>, weight| {
+                                (
+                                    AppSource {
+                                        backend_name: *backend_name,
+                                        id: id.to_string(),
+                                        icon: appstream_cache.icon(info),
+                                        info: info.clone(),
+                                    },
+                                    weight,
+                                )
+                            };
+                            let weigh = |id: &str, info: &Arc<AppInfo>| {
+                                if !filter(id, info) {
+                                    return None;
+                                }
+                                let weight = query_weight(
+                                    &word_regexes,
+                                    &phrase_regexes,
+                                    fuzzy_opt.as_ref(),
+                                    id,
+                                    info,
+                                )?;
+                                Some(source(id, info, weight))
+                            };
+                            // Weigh apps with words starting with the search words from the index,
+                            // filters without words check every app
+                            let indexed_opt = appstream_cache.search_index(&query.text);
+                            let mut backend_matches: Vec<_> = match &indexed_opt {
+                                Some(indexed) => indexed
+                                    .par_iter()
+                                    .filter_map(|id| weigh(id, appstream_cache.infos.get(*id)?))
+                                    .collect(),
+                                None => appstream_cache
+                                    .infos
+                                    .par_iter()
+                                    .filter_map(|(id, info)| weigh(id, info))
+                                    .collect(),
+                            };
+                            // Matches inside words, in descriptions, or with typos are not in the
+                            // index, so only scan for them when the index finds few apps
+                            let scanned = match &indexed_opt {
+                                Some(indexed) if backend_matches.len() < SEARCH_SCAN_BELOW => {
+                                    backend_matches.par_extend(
+                                        appstream_cache
+                                            .infos
+                                            .par_iter()
+                                            .filter(|(id, _info)| !indexed.contains(id.as_str()))
+                                            .filter_map(|(id, info)| weigh(id, info)),
+                                    );
+                                    true
+                                }
+                                _ => false,
+                            };
+                            // Addons are only searched when asked for
+                            if query.filters.contains(&SearchFilter::Kind(AppKind::Addon)) {
+                                backend_matches.par_extend(
+                                    appstream_cache
+                                        .addons
+                                        .par_iter()
+                                        .filter_map(|(id, info)| weigh(id, info)),
+                                );
+                            }
+                            log::debug!(
+                                "searched {} backend in {:?}, {} matches{}",
+                                backend_name,
+                                backend_start.elapsed(),
+                                backend_matches.len(),
+                                if scanned {
+                                    " after scanning all apps"
                                 } else {
-                                    None
+                                    ""
                                 }
-                            })
-                            .collect();
-                        results.append(&mut backend_results);
+                            );
+                            matches.append(&mut backend_matches);
+
+                            if !remote || query.text.is_empty() {
+                                continue;
+                            }
+
+                            // Backends without a local catalog search remotely
+                            match backend.search(&query.text) {
+                                Ok(packages) => {
+                                    for package in packages {
+                                        let info = match backend.info(&package) {
+                                            Ok(ok) => ok,
+                                            Err(err) => {
+                                                log::warn!(
+                                                    "failed to get info for {}: {}",
+                                                    package.id,
+                                                    err
+                                                );
+                                                continue;
+                                            }
+                                        };
+                                        if !filter(&package.id, &info) {
+                                            continue;
+                                        }
+                                        // Remote results may match fields that are not available locally
+                                        let weight = query_weight(
+                                            &word_regexes,
+                                            &phrase_regexes,
+                                            None,
+                                            &package.id,
+                                            &info,
+                                        )
+                                        .unwrap_or(SEARCH_WEIGHT_REMOTE);
+                                        matches.push((
+                                            AppSource {
+                                                backend_name: *backend_name,
+                                                id: package.id,
+                                                icon: package.icon,
+                                                info,
+                                            },
+                                            weight,
+                                        ));
+                                    }
+                                }
+                                Err(err) => {
+                                    log::warn!(
+                                        "failed to search {} backend: {}",
+                                        backend_name,
+                                        err
+                                    );
+                                }
+                            }
+                        }
                     }
+                    let mut results = SearchResult::group(matches, &preferred_sources);
                     results.sort_by(|a, b| match a.weight.cmp(&b.weight) {
-                        cmp::Ordering::Equal => {
-                            lexical_sort::natural_lexical_cmp(&a.info.name, &b.info.name)
-                        }
+                        cmp::Ordering::Equal => lexical_sort::natural_lexical_cmp(
+                            &a.source().info.name,
+                            &b.source().info.name,
+                        ),
                         ordering => ordering,
                     });
                     let duration = start.elapsed();
-                    log::info!("searched for {:?} in {:?}", input, duration);
+                    match &mimetype_opt {
+                        Some(mimetype) => {
+                            log::info!("searched for MIME type {:?} in {:?}", mimetype, duration)
+                        }
+                        None => log::info!("searched for {:?} in {:?}", input, duration),
+                    }
                     message::app(Message::SearchResults(input, results))
                 })
                 .await
@@ -310,6 +1618,7 @@ impl App {
             |x| x,
         )
     }
+
     fn select_package(&self, backend_name: &'static str, package: Package) -> Command<Message> {
         let backend = match self.backends.get(backend_name) {
             Some(some) => some.clone(),
@@ -321,12 +1630,18 @@ impl App {
         Command::perform(
             async move {
                 tokio::task::spawn_blocking(move || match backend.info(&package) {
-                    Ok(info) => message::app(Message::Selected(Selected {
-                        backend_name,
-                        id: package.id,
-                        icon: package.icon,
-                        info,
-                    })),
+                    Ok(info) => {
+                        let branch_opt = package.branch_opt();
+                        message::app(Message::Selected(
+                            AppSource {
+                                backend_name,
+                                id: package.id,
+                                icon: package.icon,
+                                info,
+                            },
+                            branch_opt,
+                        ))
+                    }
                     Err(err) => {
                         log::error!("failed to get appstream data for {}: {}", package.id, err);
                         message::none()
@@ -341,11 +1656,12 @@ impl App {
 
     fn update_backends(&self) -> Command<Message> {
         let locale = self.locale.clone();
+        let config = self.config.clone();
         Command::perform(
             async move {
                 tokio::task::spawn_blocking(move || {
                     let start = Instant::now();
-                    let backends = backend::backends(&locale);
+                    let backends = backend::backends(&locale, &config);
                     let duration = start.elapsed();
                     log::info!("loaded backends in {:?}", duration);
                     message::app(Message::Backends(backends))
@@ -357,47 +1673,877 @@ impl App {
         )
     }
 
-    fn update_config(&mut self) -> Command<Message> {
-        cosmic::app::command::set_theme(self.config.app_theme.theme())
+    /// Download missing or old remote metadata in the background, reloading backends it changed
+    fn refresh_backends(&mut self) -> Command<Message> {
+        let mut commands = Vec::with_capacity(self.backends.len());
+        for (backend_name, backend) in self.backends.iter() {
+            let backend_name = *backend_name;
+            self.refreshing.insert(backend_name, None);
+            let backend = backend.clone();
+            commands.push(Command::perform(
+                async move {
+                    tokio::task::spawn_blocking(move || {
+                        let start = Instant::now();
+                        match backend.refresh() {
+                            Ok(true) => {
+                                let duration = start.elapsed();
+                                log::info!("refreshed {} backend in {:?}", backend_name, duration);
+                                message::app(Message::BackendRefreshed(backend_name, true))
+                            }
+                            Ok(false) => {
+                                message::app(Message::BackendRefreshed(backend_name, false))
+                            }
+                            Err(err) => {
+                                log::warn!("failed to refresh {} backend: {}", backend_name, err);
+                                message::app(Message::BackendRefreshed(backend_name, false))
+                            }
+                        }
+                    })
+                    .await
+                    .unwrap_or(message::none())
+                },
+                |x| x,
+            ));
+        }
+        Command::batch(commands)
+    }
+
+    fn reload_backend(&mut self, backend_name: &'static str) -> Command<Message> {
+        // Changes come in bursts, only reload once at a time
+        if !self.reloading.insert(backend_name) {
+            return Command::none();
+        }
+        let locale = self.locale.clone();
+        let config = self.config.clone();
+        Command::perform(
+            async move {
+                tokio::task::spawn_blocking(move || {
+                    let start = Instant::now();
+                    match backend::reload_backend(backend_name, &locale, &config) {
+                        Ok(backend) => {
+                            let duration = start.elapsed();
+                            log::info!("reloaded {} backend in {:?}", backend_name, duration);
+                            message::app(Message::BackendReloaded(backend_name, backend))
+                        }
+                        Err(err) => {
+                            log::error!("failed to reload {} backend: {}", backend_name, err);
+                            message::none()
+                        }
+                    }
+                })
+                .await
+                .unwrap_or(message::none())
+            },
+            |x| x,
+        )
+    }
+
+    /// Reload only the backends that were enabled, disabled, or had their settings changed
+    fn update_changed_backends(&mut self, old_config: &Config) -> Command<Message> {
+        let mut commands = Vec::new();
+        let mut removed = false;
+        for backend_name in backend::backend_names() {
+            let was_enabled = old_config
+                .enabled_backends
+                .iter()
+                .any(|x| x == backend_name);
+            let enabled = self
+                .config
+                .enabled_backends
+                .iter()
+                .any(|x| x == backend_name);
+            let settings_changed =
+                backend_name == "appimage" && old_config.appimage_dir != self.config.appimage_dir;
+            if enabled && (!was_enabled || settings_changed) {
+                commands.push(self.reload_backend(backend_name));
+            } else if !enabled && self.backends.remove(backend_name).is_some() {
+                log::info!("disabled {} backend", backend_name);
+                removed = true;
+            }
+        }
+        if removed {
+            self.forget_disabled_backends();
+            commands.extend([
+                self.update_installed(),
+                self.update_updates(),
+                self.update_sources(),
+                self.update_offline(),
+                self.update_category(),
+            ]);
+            if self.search_results.is_some() {
+                commands.push(self.search(false));
+            }
+        }
+        Command::batch(commands)
+    }
+
+    /// Stop watching disabled backends, forget their selected app, and fail their operations
+    fn forget_disabled_backends(&mut self) {
+        self.watch_paths = self
+            .backends
+            .iter()
+            .map(|(backend_name, backend)| (*backend_name, backend.watch_paths()))
+            .collect();
+        if let Some(selected) = &self.selected_opt {
+            if !self.backends.contains_key(selected.source().backend_name) {
+                self.selected_opt = None;
+            }
+        }
+        // Operations of disabled backends can no longer report progress or finish
+        let backends = &self.backends;
+        self.pending_operations.retain(|_id, (op, _)| {
+            if backends.contains_key(op.backend_name) {
+                true
+            } else {
+                log::warn!(
+                    "{:?} of {} failed: {} backend was disabled",
+                    op.kind,
+                    op.package_id,
+                    op.backend_name
+                );
+                false
+            }
+        });
+    }
+
+    fn update_sources(&self) -> Command<Message> {
+        let backends = self.backends.clone();
+        Command::perform(
+            async move {
+                tokio::task::spawn_blocking(move || {
+                    let mut sources = Vec::new();
+                    for (backend_name, backend) in backends.iter() {
+                        let capabilities = backend.capabilities();
+                        if !capabilities.remotes && !capabilities.repos {
+                            continue;
+                        }
+                        match backend.sources() {
+                            Ok(backend_sources) => {
+                                sources.push((*backend_name, backend_sources));
+                            }
+                            Err(err) => {
+                                log::error!(
+                                    "failed to list sources from {}: {}",
+                                    backend_name,
+                                    err
+                                );
+                            }
+                        }
+                    }
+                    sources.sort_by(|a, b| a.0.cmp(b.0));
+                    message::app(Message::Sources(sources))
+                })
+                .await
+                .unwrap_or(message::none())
+            },
+            |x| x,
+        )
+    }
+
+    fn update_config(&mut self) -> Command<Message> {
+        cosmic::app::command::set_theme(self.config.app_theme.theme())
+    }
+
+    fn update_installed(&self) -> Command<Message> {
+        let backends = self.backends.clone();
+        Command::perform(
+            async move {
+                tokio::task::spawn_blocking(move || {
+                    let mut installed = Vec::new();
+                    //TODO: par_iter?
+                    for (backend_name, backend) in backends.iter() {
+                        let start = Instant::now();
+                        match backend.installed() {
+                            Ok(packages) => {
+                                for package in packages {
+                                    installed.push((*backend_name, package));
+                                }
+                            }
+                            Err(err) => {
+                                log::error!("failed to list installed: {}", err);
+                            }
+                        }
+                        let duration = start.elapsed();
+                        log::info!("loaded installed from {} in {:?}", backend_name, duration);
+                    }
+                    installed
+                        .sort_by(|a, b| lexical_sort::natural_lexical_cmp(&a.1.name, &b.1.name));
+                    message::app(Message::Installed(installed))
+                })
+                .await
+                .unwrap_or(message::none())
+            },
+            |x| x,
+        )
+    }
+
+    fn update_updates(&self) -> Command<Message> {
+        let backends = self.backends.clone();
+        Command::perform(
+            async move {
+                tokio::task::spawn_blocking(move || {
+                    let mut updates = Vec::new();
+                    //TODO: par_iter?
+                    for (backend_name, backend) in backends.iter() {
+                        if !backend.capabilities().updates {
+                            continue;
+                        }
+                        let start = Instant::now();
+                        match backend.updates() {
+                            Ok(packages) => {
+                                for package in packages {
+                                    updates.push((*backend_name, package));
+                                }
+                            }
+                            Err(err) => {
+                                log::error!("failed to list updates: {}", err);
+                            }
+                        }
+                        let duration = start.elapsed();
+                        log::info!("loaded updates from {} in {:?}", backend_name, duration);
+                    }
+                    updates.sort_by(|a, b| lexical_sort::natural_lexical_cmp(&a.1.name, &b.1.name));
+                    message::app(Message::Updates(updates))
+                })
+                .await
+                .unwrap_or(message::none())
+            },
+            |x| x,
+        )
+    }
+
+    fn update_offline(&self) -> Command<Message> {
+        let backends = self.backends.clone();
+        Command::perform(
+            async move {
+                tokio::task::spawn_blocking(move || {
+                    message::app(Message::OfflineStates(backend::offline_states(&backends)))
+                })
+                .await
+                .unwrap_or(message::none())
+            },
+            |x| x,
+        )
+    }
+
+    /// Run a blocking backend call, then refresh offline update state
+    fn offline_command(
+        &self,
+        backend_name: &'static str,
+        f: fn(&dyn backend::Backend) -> Result<(), Box<dyn std::error::Error>>,
+    ) -> Command<Message> {
+        let backends = self.backends.clone();
+        Command::perform(
+            async move {
+                tokio::task::spawn_blocking(move || {
+                    let offline_states = backend::offline_request(&backends, backend_name, f);
+                    message::app(Message::OfflineStates(offline_states))
+                })
+                .await
+                .unwrap_or(message::none())
+            },
+            |x| x,
+        )
+    }
+
+    fn update_title(&mut self) -> Command<Message> {
+        let title = "COSMIC App Store";
+        self.set_header_title(title.to_string());
+        self.set_window_title(title.to_string())
+    }
+
+    /// Installed package with an ID, and the branch if given as side by side branches share IDs
+    fn installed_package(
+        &self,
+        backend_name: &str,
+        id: &str,
+        branch_opt: Option<&Branch>,
+    ) -> Option<&Package> {
+        self.installed.as_ref().and_then(|installed| {
+            installed
+                .iter()
+                .find(|(installed_backend, package)| {
+                    *installed_backend == backend_name
+                        && package.id == id
+                        && package_on_branch(package, branch_opt)
+                })
+                .map(|(_, package)| package)
+        })
+    }
+
+    fn operation_buttons(
+        &self,
+        backend_name: &'static str,
+        id: &str,
+        branch_opt: Option<&Branch>,
+        info: &Arc<AppInfo>,
+    ) -> Vec<Element<Message>> {
+        for (_op_id, (op, progress)) in self.pending_operations.iter() {
+            if op.backend_name == backend_name && op.package_id == id {
+                return vec![widget::text(op.kind.pending_text(*progress as i32)).into()];
+            }
+        }
+
+        // Only offer what the backend can do
+        let capabilities = self.capabilities(backend_name);
+        let installed_opt = self.installed_package(backend_name, id, branch_opt);
+        let has_update = self.updates.as_ref().map_or(false, |updates| {
+            updates.iter().any(|(update_backend, package)| {
+                *update_backend == backend_name
+                    && package.id == id
+                    && package_on_branch(package, branch_opt)
+            })
+        });
+        let button = |text: String, kind: OperationKind| -> Element<Message> {
+            widget::button(widget::text(text))
+                .on_press(Message::Operation(
+                    kind,
+                    backend_name,
+                    id.to_string(),
+                    info.clone(),
+                    branch_opt.cloned(),
+                ))
+                .into()
+        };
+        let mut buttons = Vec::with_capacity(4);
+        // End-of-life apps with a replacement can switch to it
+        if let Some(eol_rebase) = installed_opt
+            .and_then(|package| package.extra.get("eol_rebase"))
+            .filter(|_| capabilities.rebase)
+        {
+            buttons.push(button(
+                fl!("rebase", id = eol_rebase.as_str()),
+                OperationKind::Rebase,
+            ));
+        }
+        // Updates may be for things that are not listed as installed, like firmware
+        if has_update && capabilities.updates {
+            buttons.push(button(fl!("update"), OperationKind::Update));
+        }
+        if let Some(package) = installed_opt {
+            if capabilities.integrate
+                && package
+                    .extra
+                    .get("integrated")
+                    .map_or(false, |x| x != "true")
+            {
+                buttons.push(button(fl!("integrate"), OperationKind::Integrate));
+            }
+            if capabilities.uninstall {
+                buttons.push(button(fl!("uninstall"), OperationKind::Uninstall));
+            }
+        } else if capabilities.install {
+            buttons.push(button(fl!("install"), OperationKind::Install));
+        }
+        buttons
+    }
+
+    /// Offline updates are enabled in config and supported by the backend
+    fn offline_mode(&self, backend_name: &str) -> bool {
+        self.config.offline_updates && self.capabilities(backend_name).offline_updates
+    }
+
+    /// Results of the last offline updates and actions to prepare or install offline updates
+    fn offline_rows(&self, updates: &[(&'static str, Package)]) -> Vec<Element<Message>> {
+        let mut rows = Vec::new();
+        for (backend_name, offline_state) in self.offline_states.iter() {
+            let backend_name = *backend_name;
+            if let Some(last_result) = &offline_state.last_result_opt {
+                let text = match &last_result.error_opt {
+                    Some(err) if !last_result.success => {
+                        fl!("offline-update-failed", error = err.as_str())
+                    }
+                    _ => fl!(
+                        "offline-update-succeeded",
+                        count = last_result.packages.len()
+                    ),
+                };
+                rows.push(
+                    widget::row::with_children(vec![
+                        widget::text(text).into(),
+                        widget::horizontal_space(Length::Fill).into(),
+                        widget::button(widget::text(fl!("dismiss")))
+                            .on_press(Message::OfflineClearResult(backend_name))
+                            .into(),
+                    ])
+                    .align_items(Alignment::Center)
+                    .into(),
+                );
+            }
+
+            if !self.offline_mode(backend_name) {
+                continue;
+            }
+            let pending_opt = self.pending_operations.values().find(|(op, _)| {
+                op.backend_name == backend_name && op.kind == OperationKind::OfflineUpdate
+            });
+            let mut children = vec![
+                widget::text(backend_label(backend_name)).into(),
+                widget::horizontal_space(Length::Fill).into(),
+            ];
+            if let Some((op, progress)) = pending_opt {
+                children.push(widget::text(op.kind.pending_text(*progress as i32)).into());
+            } else if offline_state.triggered {
+                children.push(widget::text(fl!("restart-to-install")).into());
+                if self.offline_reboot_confirm == Some(backend_name) {
+                    children.push(
+                        widget::button(widget::text(fl!("cancel")))
+                            .on_press(Message::OfflineRebootConfirm(None))
+                            .into(),
+                    );
+                    children.push(
+                        widget::button(widget::text(fl!("restart-now")))
+                            .on_press(Message::OfflineReboot(backend_name))
+                            .into(),
+                    );
+                } else {
+                    children.push(
+                        widget::button(widget::text(fl!("restart")))
+                            .on_press(Message::OfflineRebootConfirm(Some(backend_name)))
+                            .into(),
+                    );
+                }
+            } else if updates.iter().any(|(x, _)| *x == backend_name) {
+                children.push(
+                    widget::button(widget::text(fl!("offline-update")))
+                        .on_press(Message::OfflineUpdate(backend_name))
+                        .into(),
+                );
+            } else {
+                continue;
+            }
+            rows.push(
+                widget::row::with_children(children)
+                    .align_items(Alignment::Center)
+                    .into(),
+            );
+        }
+        rows
+    }
+
+    fn branches_section(&self, source: &AppSource, branches: &[Branch]) -> Element<Message> {
+        let pending = self
+            .pending_operations
+            .values()
+            .any(|(op, _)| op.backend_name == source.backend_name && op.package_id == source.id);
+        let mut section = widget::settings::view_section(fl!("branches"));
+        for branch in branches.iter() {
+            let (text, kind) = if branch.installed {
+                (fl!("uninstall"), OperationKind::Uninstall)
+            } else {
+                (fl!("install"), OperationKind::Install)
+            };
+            let mut button = widget::button(widget::text(text));
+            // Wait for pending operations before changing branches
+            if !pending {
+                button = button.on_press(Message::Operation(
+                    kind,
+                    source.backend_name,
+                    source.id.clone(),
+                    source.info.clone(),
+                    Some(branch.clone()),
+                ));
+            }
+            section = section.add(
+                widget::settings::item::builder(fl!(
+                    "branch",
+                    branch = branch.branch.as_str(),
+                    arch = branch.arch.as_str(),
+                    remote = branch.remote.as_str()
+                ))
+                .control(button),
+            );
+        }
+        section.into()
+    }
+
+    /// A loaded screenshot image, or a placeholder while it loads or if it failed
+    fn screenshot_image(
+        &self,
+        image_opt: Option<&ScreenshotImage>,
+        height: Length,
+    ) -> Element<Message> {
+        match image_opt.and_then(|image| self.screenshots.get(&image.url)) {
+            Some(Some(handle)) => widget::image(handle.clone())
+                .width(Length::Fill)
+                .height(height)
+                .into(),
+            _ => widget::container(
+                widget::icon::icon(
+                    widget::icon::from_name("image-x-generic-symbolic")
+                        .size(64)
+                        .handle(),
+                )
+                .size(64),
+            )
+            .width(Length::Fill)
+            .height(height)
+            .center_x()
+            .center_y()
+            .into(),
+        }
+    }
+
+    /// Previous and next buttons with the position of the current screenshot
+    fn screenshot_nav(&self, screenshots: &[Screenshot], screenshot_i: usize) -> Element<Message> {
+        let cosmic_theme::Spacing { space_xxs, .. } = self.core().system_theme().cosmic().spacing;
+
+        let mut previous = widget::button::icon(widget::icon::from_name("go-previous-symbolic"));
+        if screenshot_i > 0 {
+            previous = previous.on_press(Message::Screenshot(screenshot_i - 1));
+        }
+        let mut next = widget::button::icon(widget::icon::from_name("go-next-symbolic"));
+        if screenshot_i + 1 < screenshots.len() {
+            next = next.on_press(Message::Screenshot(screenshot_i + 1));
+        }
+        let mut row = widget::row::with_capacity(4)
+            .align_items(Alignment::Center)
+            .spacing(space_xxs);
+        row = row.push(previous);
+        row = row.push(widget::text(fl!(
+            "screenshot-position",
+            position = screenshot_i + 1,
+            count = screenshots.len()
+        )));
+        row = row.push(next);
+        if let Some(screenshot) = screenshots.get(screenshot_i) {
+            if let Some(caption) = &screenshot.caption_opt {
+                row = row.push(widget::text(caption));
+            }
+        }
+        row.into()
+    }
+
+    fn screenshots_section(&self, selected: &Selected) -> Element<Message> {
+        let cosmic_theme::Spacing { space_xxs, .. } = self.core().system_theme().cosmic().spacing;
+
+        let screenshots = &selected.source().info.screenshots;
+        let screenshot = &screenshots[selected.screenshot_i];
+        let mut column = widget::column::with_capacity(3).spacing(space_xxs);
+        column = column.push(
+            widget::button(self.screenshot_image(
+                screenshot.image(SCREENSHOT_WIDTH),
+                Length::Fixed(SCREENSHOT_HEIGHT.into()),
+            ))
+            .on_press(Message::ScreenshotFull(true)),
+        );
+        column = column.push(self.screenshot_nav(screenshots, selected.screenshot_i));
+        for video in screenshot.videos.iter() {
+            column = column.push(
+                widget::button(widget::text(fl!("play-video")))
+                    .on_press(Message::OpenUrl(video.url.clone())),
+            );
+        }
+        column.into()
+    }
+
+    /// Latest release notes, releases newer than the installed version, and optionally all others
+    fn releases_section(&self, selected: &Selected) -> Element<Message> {
+        let cosmic_theme::Spacing {
+            space_xs,
+            space_xxs,
+            ..
+        } = self.core().system_theme().cosmic().spacing;
+
+        let source = selected.source();
+        let releases = &source.info.releases;
+        let installed_version_opt = self
+            .installed_package(
+                source.backend_name,
+                &source.id,
+                selected.branch_opt.as_ref(),
+            )
+            .map(|package| package.version.as_str())
+            .filter(|x| !x.is_empty());
+        let newer = |release: &Release| {
+            installed_version_opt.map_or(false, |installed_version| {
+                compare_versions(&release.version, installed_version) == cmp::Ordering::Greater
+            })
+        };
+
+        let mut column = widget::column::with_capacity(releases.len() + 2).spacing(space_xxs);
+        column = column.push(widget::text(fl!("releases")));
+        for (release_i, release) in releases.iter().enumerate() {
+            let release_newer = newer(release);
+            if release_i > 0 && !release_newer && !selected.releases_expanded {
+                continue;
+            }
+            let version = match release.date() {
+                Some(date) => fl!(
+                    "release-version-date",
+                    version = release.version.as_str(),
+                    date = date
+                ),
+                None => fl!("release-version", version = release.version.as_str()),
+            };
+            let mut labels = Vec::new();
+            if release_newer {
+                labels.push(fl!("release-newer"));
+            }
+            match release.urgency {
+                ReleaseUrgency::High => labels.push(fl!("release-urgency-high")),
+                ReleaseUrgency::Critical => labels.push(fl!("release-urgency-critical")),
+                _ => {}
+            }
+            match release.kind {
+                ReleaseKind::Development | ReleaseKind::Snapshot => {
+                    labels.push(fl!("release-development"))
+                }
+                ReleaseKind::Stable => {}
+            }
+            let mut row = widget::row::with_capacity(3)
+                .align_items(Alignment::Center)
+                .spacing(space_xxs);
+            if release_newer {
+                row = row.push(
+                    widget::icon::icon(
+                        widget::icon::from_name("software-update-available-symbolic")
+                            .size(16)
+                            .handle(),
+                    )
+                    .size(16),
+                );
+            }
+            row = row.push(widget::text(version));
+            if !labels.is_empty() {
+                row = row.push(widget::text(labels.join(" · ")));
+            }
+            column = column.push(row);
+            if !release.description.is_empty() {
+                column = column.push(
+                    widget::container(description_column(&release.description, space_xxs))
+                        .padding([0, 0, space_xs, 0]),
+                );
+            }
+        }
+        if releases.len() > 1 {
+            let text = if selected.releases_expanded {
+                fl!("release-history-hide")
+            } else {
+                fl!("release-history-show")
+            };
+            column = column.push(
+                widget::button(widget::text(text))
+                    .on_press(Message::ReleasesExpand(!selected.releases_expanded)),
+            );
+        }
+        column.into()
+    }
+
+    /// Buttons for the main categories, four per row
+    fn categories_grid(&self) -> Element<Message> {
+        let cosmic_theme::Spacing { space_xxs, .. } = self.core().system_theme().cosmic().spacing;
+
+        widget::column::with_children(vec![
+            widget::text(fl!("categories")).into(),
+            button_grid(
+                Category::all()
+                    .iter()
+                    .map(|category| {
+                        (
+                            category.icon(),
+                            category.name(),
+                            Message::Category(Some(*category)),
+                        )
+                    })
+                    .collect(),
+                space_xxs,
+            ),
+        ])
+        .spacing(space_xxs)
+        .into()
+    }
+
+    fn category_page(&self) -> Element<Message> {
+        let cosmic_theme::Spacing {
+            space_xs,
+            space_xxs,
+            ..
+        } = self.core().system_theme().cosmic().spacing;
+
+        let page = match &self.category_opt {
+            Some(some) => some,
+            None => return widget::column().into(),
+        };
+        let results = page.results_opt.as_deref().unwrap_or(&[]);
+        //TODO: paging or dynamic load
+        let results_len = cmp::min(results.len(), 256);
+        let mut column = widget::column::with_capacity(results_len + 3)
+            // Hack to make room for scroll bar
+            .padding([0, space_xs, 0, 0])
+            .spacing(space_xxs)
+            .width(Length::Fill);
+        column = column.push(
+            widget::row::with_children(vec![
+                widget::button(widget::text(fl!("back")))
+                    .on_press(Message::Category(None))
+                    .into(),
+                widget::icon::icon(
+                    widget::icon::from_name(page.category.icon())
+                        .size(16)
+                        .handle(),
+                )
+                .size(16)
+                .into(),
+                widget::text(page.category.name()).into(),
+                widget::horizontal_space(Length::Fill).into(),
+                widget::dropdown(
+                    &self.category_sorts,
+                    CategorySort::all()
+                        .iter()
+                        .position(|x| *x == self.category_sort),
+                    Message::CategorySort,
+                )
+                .into(),
+            ])
+            .align_items(Alignment::Center)
+            .spacing(space_xxs),
+        );
+        if !page.subcategories.is_empty() {
+            let mut buttons = Vec::with_capacity(page.subcategories.len() + 1);
+            let mut all = widget::button(widget::text(fl!("category-all")));
+            if page.subcategory_opt.is_some() {
+                all = all.on_press(Message::CategorySubcategory(None));
+            }
+            buttons.push(all);
+            for subcategory in page.subcategories.iter() {
+                let mut button = widget::button(widget::text(subcategory_name(subcategory)));
+                if page.subcategory_opt != Some(*subcategory) {
+                    button = button.on_press(Message::CategorySubcategory(Some(*subcategory)));
+                }
+                buttons.push(button);
+            }
+            //TODO: wrap buttons to the window width
+            let mut buttons_iter = buttons.into_iter().peekable();
+            while buttons_iter.peek().is_some() {
+                let mut row = widget::row::with_capacity(6).spacing(space_xxs);
+                for button in buttons_iter.by_ref().take(6) {
+                    row = row.push(button);
+                }
+                column = column.push(row);
+            }
+        }
+        match &page.results_opt {
+            Some(_) => {
+                for (result_i, result) in results.iter().take(results_len).enumerate() {
+                    column = column.push(result_row(
+                        result,
+                        space_xxs,
+                        Message::SelectCategoryResult(result_i),
+                    ));
+                }
+            }
+            None => {
+                column = column.push(widget::text(fl!("loading")));
+            }
+        }
+        widget::scrollable(column).into()
     }
 
-    fn update_installed(&self) -> Command<Message> {
-        let backends = self.backends.clone();
-        Command::perform(
-            async move {
-                tokio::task::spawn_blocking(move || {
-                    let mut installed = Vec::new();
-                    //TODO: par_iter?
-                    for (backend_name, backend) in backends.iter() {
-                        let start = Instant::now();
-                        match backend.installed() {
-                            Ok(packages) => {
-                                for package in packages {
-                                    installed.push((*backend_name, package));
-                                }
-                            }
-                            Err(err) => {
-                                log::error!("failed to list installed: {}", err);
-                            }
-                        }
-                        let duration = start.elapsed();
-                        log::info!("loaded installed from {} in {:?}", backend_name, duration);
-                    }
-                    installed
-                        .sort_by(|a, b| lexical_sort::natural_lexical_cmp(&a.1.name, &b.1.name));
-                    message::app(Message::Installed(installed))
-                })
-                .await
-                .unwrap_or(message::none())
-            },
-            |x| x,
-        )
+    /// Full size viewer of the current screenshot
+    fn screenshot_viewer(&self, selected: &Selected) -> Element<Message> {
+        let cosmic_theme::Spacing {
+            space_xs,
+            space_xxs,
+            ..
+        } = self.core().system_theme().cosmic().spacing;
+
+        let screenshots = &selected.source().info.screenshots;
+        let screenshot_opt = screenshots.get(selected.screenshot_i);
+        widget::column::with_children(vec![
+            widget::row::with_children(vec![
+                widget::button(widget::text(fl!("back")))
+                    .on_press(Message::ScreenshotFull(false))
+                    .into(),
+                widget::text(&selected.source().info.name).into(),
+            ])
+            .align_items(Alignment::Center)
+            .spacing(space_xxs)
+            .into(),
+            self.screenshot_image(screenshot_opt.and_then(|x| x.source()), Length::Fill),
+            self.screenshot_nav(screenshots, selected.screenshot_i),
+        ])
+        .padding([0, space_xs, space_xxs, 0])
+        .spacing(space_xxs)
+        .width(Length::Fill)
+        .height(Length::Fill)
+        .into()
     }
 
-    fn update_title(&mut self) -> Command<Message> {
-        let title = "COSMIC App Store";
-        self.set_header_title(title.to_string());
-        self.set_window_title(title.to_string())
+    fn history(&self) -> Element<Message> {
+        let cosmic_theme::Spacing {
+            space_xs,
+            space_xxs,
+            ..
+        } = self.core().system_theme().cosmic().spacing;
+
+        let entries = self.history_opt.as_deref().unwrap_or(&[]);
+        let mut column = widget::column::with_capacity(entries.len() + 2)
+            // Hack to make room for scroll bar
+            .padding([0, space_xs, 0, 0])
+            .spacing(space_xxs)
+            .width(Length::Fill);
+        column = column.push(
+            widget::row::with_children(vec![
+                widget::button(widget::text(fl!("back")))
+                    .on_press(Message::HistoryClose)
+                    .into(),
+                widget::text(fl!("history")).into(),
+                widget::horizontal_space(Length::Fill).into(),
+                widget::text_input::search_input(fl!("filter-history"), &self.history_filter)
+                    .width(Length::Fixed(240.0))
+                    .on_clear(Message::HistoryFilter(String::new()))
+                    .on_input(Message::HistoryFilter)
+                    .into(),
+            ])
+            .align_items(Alignment::Center)
+            .spacing(space_xxs),
+        );
+        // Newest entries first
+        for entry in entries
+            .iter()
+            .rev()
+            .filter(|entry| entry.matches(&self.history_filter))
+        {
+            let versions = match (
+                entry.version_before.is_empty(),
+                entry.version_after.is_empty(),
+            ) {
+                (true, true) => String::new(),
+                (false, true) => entry.version_before.clone(),
+                (true, false) => entry.version_after.clone(),
+                (false, false) => format!("{} → {}", entry.version_before, entry.version_after),
+            };
+            let result = match &entry.error_opt {
+                Some(err) => fl!("history-failed", error = err.as_str()),
+                None => fl!("history-succeeded"),
+            };
+            column = column.push(
+                widget::row::with_children(vec![
+                    widget::column::with_children(vec![
+                        widget::text(&entry.package_id).into(),
+                        widget::text(format!(
+                            "{} · {} · {}",
+                            entry.date_time(),
+                            backend_label(&entry.backend_name),
+                            entry.kind.label()
+                        ))
+                        .into(),
+                    ])
+                    .into(),
+                    widget::horizontal_space(Length::Fill).into(),
+                    widget::column::with_children(vec![
+                        widget::text(versions).into(),
+                        widget::text(result).into(),
+                    ])
+                    .align_items(Alignment::End)
+                    .into(),
+                ])
+                .align_items(Alignment::Center)
+                .spacing(space_xxs),
+            );
+        }
+        widget::scrollable(column).into()
     }
 
     fn settings(&self) -> Element<Message> {
@@ -406,7 +2552,7 @@ impl App {
             AppTheme::Light => 2,
             AppTheme::System => 0,
         };
-        widget::settings::view_column(vec![widget::settings::view_section(fl!("appearance"))
+        let mut sections = vec![widget::settings::view_section(fl!("appearance"))
             .add(
                 widget::settings::item::builder(fl!("theme")).control(widget::dropdown(
                     &self.app_themes,
@@ -420,8 +2566,63 @@ impl App {
                     },
                 )),
             )
-            .into()])
-        .into()
+            .into()];
+        let mut preferred_section = widget::settings::view_section(fl!("preferred-sources"));
+        for (source_i, backend_name) in self.config.preferred_sources.iter().enumerate() {
+            let mut button = widget::button::icon(widget::icon::from_name("go-up-symbolic"));
+            if source_i > 0 {
+                button = button.on_press(Message::PreferSource(source_i));
+            }
+            preferred_section = preferred_section
+                .add(widget::settings::item::builder(backend_label(backend_name)).control(button));
+        }
+        sections.push(preferred_section.into());
+        let mut backends_section = widget::settings::view_section(fl!("backends"));
+        for backend_name in backend::backend_names() {
+            let enabled = self
+                .config
+                .enabled_backends
+                .iter()
+                .any(|x| x == backend_name);
+            backends_section = backends_section.add(
+                widget::settings::item::builder(backend_label(backend_name))
+                    .toggler(enabled, move |enable| {
+                        Message::EnableBackend(backend_name, enable)
+                    }),
+            );
+        }
+        sections.push(backends_section.into());
+        sections.push(
+            widget::settings::view_section(fl!("updates"))
+                .add(
+                    widget::settings::item::builder(fl!("offline-updates"))
+                        .toggler(self.config.offline_updates, Message::OfflineUpdates),
+                )
+                .into(),
+        );
+        sections.push(
+            widget::settings::view_section(fl!("browsing"))
+                .add(
+                    widget::settings::item::builder(fl!("hide-proprietary"))
+                        .toggler(self.config.hide_proprietary, Message::HideProprietary),
+                )
+                .into(),
+        );
+        // Only backends with remotes or repositories have sources to show
+        for (backend_name, sources) in self.sources.iter() {
+            let mut section = widget::settings::view_section(fl!(
+                "sources",
+                backend = backend_label(backend_name)
+            ));
+            for source in sources.iter() {
+                section = section.add(widget::settings::item_row(vec![widget::text(
+                    source.as_str(),
+                )
+                .into()]));
+            }
+            sections.push(section.into());
+        }
+        widget::settings::view_column(sections).into()
     }
 }
 
@@ -460,15 +2661,32 @@ impl Application for App {
             config: flags.config,
             locale,
             app_themes,
+            category_opt: None,
+            category_sort: CategorySort::Name,
+            category_sorts: CategorySort::all().iter().map(|x| x.label()).collect(),
             backends: Backends::new(),
             context_page: ContextPage::Settings,
             key_binds: key_binds(),
-            search_active: false,
+            search_active: flags.search_opt.is_some(),
             search_id: widget::Id::unique(),
-            search_input: String::new(),
+            search_input: flags.search_opt.unwrap_or_default(),
             installed: None,
+            updates: None,
+            sources: Vec::new(),
+            pending_operation_id: 0,
+            pending_operations: BTreeMap::new(),
+            hold_error_opt: None,
             search_results: None,
             selected_opt: None,
+            branches: None,
+            history_opt: None,
+            history_filter: String::new(),
+            offline_states: Vec::new(),
+            offline_reboot_confirm: None,
+            watch_paths: Vec::new(),
+            reloading: HashSet::new(),
+            refreshing: HashMap::new(),
+            screenshots: HashMap::new(),
         };
 
         let command = Command::batch([app.update_title(), app.update_backends()]);
@@ -521,18 +2739,133 @@ impl Application for App {
                 config_set!(app_theme, app_theme);
                 return self.update_config();
             }
+            Message::BackendRefreshed(backend_name, changed) => {
+                self.refreshing.insert(backend_name, Some(Instant::now()));
+                // Skip if the backend was disabled while refreshing
+                if changed && self.backends.contains_key(backend_name) {
+                    return self.reload_backend(backend_name);
+                }
+            }
+            Message::BackendReloaded(backend_name, backend) => {
+                self.reloading.remove(backend_name);
+                // Skip if the backend was disabled while reloading
+                if !self
+                    .config
+                    .enabled_backends
+                    .iter()
+                    .any(|x| x == backend_name)
+                {
+                    return Command::none();
+                }
+                let added = self.backends.insert(backend_name, backend).is_none();
+                self.watch_paths = self
+                    .backends
+                    .iter()
+                    .map(|(backend_name, backend)| (*backend_name, backend.watch_paths()))
+                    .collect();
+                let mut commands = vec![
+                    self.update_installed(),
+                    self.update_updates(),
+                    self.update_branches(),
+                ];
+                // A newly enabled backend has its own sources and offline updates
+                if added {
+                    commands.push(self.update_sources());
+                    commands.push(self.update_offline());
+                }
+                if self.search_results.is_some() {
+                    commands.push(self.search(false));
+                }
+                commands.push(self.update_category());
+                return Command::batch(commands);
+            }
             Message::Backends(backends) => {
                 self.backends = backends;
-                return self.update_installed();
+                self.forget_disabled_backends();
+                let mut commands = vec![
+                    self.update_installed(),
+                    self.update_updates(),
+                    self.update_sources(),
+                    self.update_offline(),
+                    self.refresh_backends(),
+                    self.update_category(),
+                ];
+                // Also performs the search given on the command line, later reloads only update
+                // the results locally
+                if !self.search_input.is_empty() {
+                    commands.push(self.search(self.search_results.is_none()));
+                }
+                return Command::batch(commands);
+            }
+            Message::Branches(backend_name, id, branches) => {
+                self.branches = Some((backend_name, id, branches));
+            }
+            Message::Category(category_opt) => {
+                self.category_opt = category_opt.map(|category| CategoryPage {
+                    category,
+                    subcategory_opt: None,
+                    subcategories: Vec::new(),
+                    results_opt: None,
+                });
+                return self.update_category();
+            }
+            Message::CategoryResults(category, subcategory_opt, subcategories, results) => {
+                if let Some(page) = &mut self.category_opt {
+                    // Ignore results for a page that is no longer open
+                    if page.category == category && page.subcategory_opt == subcategory_opt {
+                        page.subcategories = subcategories;
+                        page.results_opt = Some(results);
+                    }
+                }
+            }
+            Message::CategorySort(sort_i) => {
+                if let Some(sort) = CategorySort::all().get(sort_i) {
+                    self.category_sort = *sort;
+                    if let Some(page) = &mut self.category_opt {
+                        if let Some(results) = &mut page.results_opt {
+                            sort_category_results(results, *sort);
+                        }
+                    }
+                }
+            }
+            Message::CategorySubcategory(subcategory_opt) => {
+                if let Some(page) = &mut self.category_opt {
+                    page.subcategory_opt = subcategory_opt;
+                    page.results_opt = None;
+                }
+                return self.update_category();
             }
             Message::Config(config) => {
                 if config != self.config {
                     log::info!("update config");
                     //TODO: update syntax theme by clearing tabs, only if needed
-                    self.config = config;
-                    return self.update_config();
+                    let old_config = mem::replace(&mut self.config, config);
+                    return Command::batch([
+                        self.update_config(),
+                        self.update_changed_backends(&old_config),
+                    ]);
+                }
+            }
+            Message::EnableBackend(backend_name, enable) => {
+                let mut enabled_backends = self.config.enabled_backends.clone();
+                enabled_backends.retain(|x| x != backend_name);
+                if enable {
+                    enabled_backends.push(backend_name.to_string());
+                }
+                if enabled_backends != self.config.enabled_backends {
+                    let old_config = self.config.clone();
+                    config_set!(enabled_backends, enabled_backends);
+                    return self.update_changed_backends(&old_config);
                 }
             }
+            Message::HideProprietary(hide_proprietary) => {
+                config_set!(hide_proprietary, hide_proprietary);
+                let mut commands = vec![self.update_category()];
+                if self.search_results.is_some() {
+                    commands.push(self.search(false));
+                }
+                return Command::batch(commands);
+            }
             Message::Installed(installed) => {
                 self.installed = Some(installed);
             }
@@ -543,9 +2876,145 @@ impl Application for App {
                     }
                 }
             }
+            Message::History => {
+                return self.load_history();
+            }
+            Message::HistoryClose => {
+                self.history_opt = None;
+            }
+            Message::HistoryFilter(filter) => {
+                self.history_filter = filter;
+            }
+            Message::HistoryLoaded(entries) => {
+                self.history_opt = Some(entries);
+            }
+            Message::Hold(backend_name, id, runtime, hold) => {
+                self.hold_error_opt = None;
+                return self.hold(backend_name, id, runtime, hold);
+            }
+            Message::HoldError(id, err) => {
+                self.hold_error_opt = Some((id, err));
+                // The hold may have changed partially
+                return self.update(Message::RefreshInstalled);
+            }
+            Message::OfflineClearResult(backend_name) => {
+                return self
+                    .offline_command(backend_name, |backend| backend.offline_clear_result());
+            }
+            Message::OfflineReboot(backend_name) => {
+                self.offline_reboot_confirm = None;
+                return self.offline_command(backend_name, |backend| backend.offline_reboot());
+            }
+            Message::OfflineRebootConfirm(backend_name_opt) => {
+                self.offline_reboot_confirm = backend_name_opt;
+            }
+            Message::OfflineStates(offline_states) => {
+                self.offline_states = offline_states;
+            }
+            Message::OfflineUpdate(backend_name) => {
+                self.operation(Operation {
+                    kind: OperationKind::OfflineUpdate,
+                    backend_name,
+                    package_id: String::new(),
+                    info: Arc::new(AppInfo::default()),
+                    branch_opt: None,
+                });
+            }
+            Message::OfflineUpdates(offline_updates) => {
+                config_set!(offline_updates, offline_updates);
+            }
             Message::OpenDesktopId(desktop_id) => {
                 return self.open_desktop_id(desktop_id);
             }
+            Message::OpenUrl(url) => match process::Command::new("xdg-open").arg(&url).spawn() {
+                Ok(_child) => {}
+                Err(err) => {
+                    log::error!("failed to open {:?}: {}", url, err);
+                }
+            },
+            Message::Operation(kind, backend_name, package_id, info, branch_opt) => {
+                self.operation(Operation {
+                    kind,
+                    backend_name,
+                    package_id,
+                    info,
+                    branch_opt,
+                });
+            }
+            Message::PendingComplete(id) => {
+                if let Some((op, _)) = self.pending_operations.remove(&id) {
+                    log::info!("{:?} of {} complete", op.kind, op.package_id);
+                }
+                let mut commands = vec![
+                    self.update_installed(),
+                    self.update_updates(),
+                    self.update_branches(),
+                    self.update_offline(),
+                ];
+                if self.history_opt.is_some() {
+                    commands.push(self.load_history());
+                }
+                return Command::batch(commands);
+            }
+            Message::PendingError(id, err) => {
+                //TODO: show error to user
+                if let Some((op, _)) = self.pending_operations.remove(&id) {
+                    log::warn!("{:?} of {} failed: {}", op.kind, op.package_id, err);
+                }
+                let mut commands = vec![
+                    self.update_installed(),
+                    self.update_updates(),
+                    self.update_branches(),
+                    self.update_offline(),
+                ];
+                if self.history_opt.is_some() {
+                    commands.push(self.load_history());
+                }
+                return Command::batch(commands);
+            }
+            Message::PendingProgress(id, new_progress) => {
+                if let Some((_, progress)) = self.pending_operations.get_mut(&id) {
+                    *progress = new_progress;
+                }
+            }
+            Message::PreferSource(source_i) => {
+                if source_i > 0 && source_i < self.config.preferred_sources.len() {
+                    let mut preferred_sources = self.config.preferred_sources.clone();
+                    preferred_sources.swap(source_i - 1, source_i);
+                    config_set!(preferred_sources, preferred_sources);
+                }
+            }
+            Message::RefreshInstalled => {
+                return Command::batch([self.update_installed(), self.update_updates()]);
+            }
+            Message::ReleasesExpand(releases_expanded) => {
+                if let Some(selected) = &mut self.selected_opt {
+                    selected.releases_expanded = releases_expanded;
+                }
+            }
+            Message::Screenshot(screenshot_i) => {
+                if let Some(selected) = &mut self.selected_opt {
+                    if screenshot_i < selected.source().info.screenshots.len() {
+                        selected.screenshot_i = screenshot_i;
+                    }
+                }
+                return self.update_screenshots();
+            }
+            Message::ScreenshotFull(screenshot_full) => {
+                if let Some(selected) = &mut self.selected_opt {
+                    selected.screenshot_full = screenshot_full;
+                }
+                return self.update_screenshots();
+            }
+            Message::ScreenshotLoaded(url, handle_opt) => match handle_opt {
+                Some(handle) => {
+                    self.screenshots.insert(url, Some(handle));
+                }
+                None => {
+                    // Load again the next time the screenshot is shown
+                    self.screenshots.remove(&url);
+                }
+            },
             Message::SearchActivate => {
                 self.search_active = true;
                 return widget::text_input::focus(self.search_id.clone());
@@ -555,12 +3024,20 @@ impl Application for App {
                 self.search_input.clear();
                 self.search_results = None;
             }
+            Message::SearchFilterRemove(filter_i) => {
+                self.search_input = SearchQuery::remove_filter(&self.search_input, filter_i);
+                if self.search_input.is_empty() {
+                    self.search_results = None;
+                } else {
+                    return self.search(false);
+                }
+            }
             Message::SearchInput(input) => {
                 if input != self.search_input {
                     self.search_input = input;
                     // This performs live search
                     if !self.search_input.is_empty() {
-                        return self.search();
+                        return self.search(false);
                     }
                 }
             }
@@ -577,7 +3054,27 @@ impl Application for App {
             }
             Message::SearchSubmit => {
                 if !self.search_input.is_empty() {
-                    return self.search();
+                    return self.search(true);
+                }
+            }
+            Message::SelectCategoryResult(result_i) => {
+                if let Some(results) = self
+                    .category_opt
+                    .as_ref()
+                    .and_then(|page| page.results_opt.as_ref())
+                {
+                    match results.get(result_i) {
+                        Some(result) => {
+                            self.selected_opt = Some(Selected::new(result.sources.clone(), 0));
+                            return Command::batch([
+                                self.update_branches(),
+                                self.update_screenshots(),
+                            ]);
+                        }
+                        None => {
+                            log::error!("failed to find category result with index {}", result_i);
+                        }
+                    }
                 }
             }
             Message::SelectInstalled(installed_i) => {
@@ -600,17 +3097,17 @@ impl Application for App {
             }
             Message::SelectNone => {
                 self.selected_opt = None;
+                self.branches = None;
             }
             Message::SelectSearchResult(result_i) => {
                 if let Some((_input, results)) = &self.search_results {
                     match results.get(result_i) {
                         Some(result) => {
-                            self.selected_opt = Some(Selected {
-                                backend_name: result.backend_name,
-                                id: result.id.clone(),
-                                icon: result.icon.clone(),
-                                info: result.info.clone(),
-                            })
+                            self.selected_opt = Some(Selected::new(result.sources.clone(), 0));
+                            return Command::batch([
+                                self.update_branches(),
+                                self.update_screenshots(),
+                            ]);
                         }
                         None => {
                             log::error!("failed to find search result with index {}", result_i);
@@ -618,8 +3115,26 @@ impl Application for App {
                     }
                 }
             }
-            Message::Selected(selected) => {
+            Message::SelectSource(source_i) => {
+                if let Some(selected) = &mut self.selected_opt {
+                    if source_i < selected.sources.len() {
+                        selected.source_i = source_i;
+                        // The branch belongs to the previous source
+                        selected.branch_opt = None;
+                        selected.screenshot_i = 0;
+                        selected.screenshot_full = false;
+                    }
+                }
+                return Command::batch([self.update_branches(), self.update_screenshots()]);
+            }
+            Message::Selected(source, branch_opt) => {
+                let mut selected = self.select(source);
+                selected.branch_opt = branch_opt;
                 self.selected_opt = Some(selected);
+                return Command::batch([self.update_branches(), self.update_screenshots()]);
+            }
+            Message::Sources(sources) => {
+                self.sources = sources;
             }
             Message::SystemThemeModeChange(_theme_mode) => {
                 return self.update_config();
@@ -634,6 +3149,26 @@ impl Application for App {
                 }
                 self.set_context_title(context_page.title());
             }
+            Message::Updates(updates) => {
+                self.updates = Some(updates);
+            }
+            Message::WatchChanged(backend_names) => {
+                let mut commands = Vec::with_capacity(backend_names.len());
+                for backend_name in backend_names {
+                    // Refreshing writes to watched paths and reloads the backend itself
+                    let refreshing = match self.refreshing.get(backend_name) {
+                        Some(None) => true,
+                        Some(Some(refreshed)) => refreshed.elapsed() < REFRESH_WATCH_IGNORE,
+                        None => false,
+                    };
+                    if refreshing {
+                        continue;
+                    }
+                    log::info!("{} backend data changed", backend_name);
+                    commands.push(self.reload_backend(backend_name));
+                }
+                return Command::batch(commands);
+            }
             Message::WindowClose => {
                 return window::close(window::Id::MAIN);
             }
@@ -679,6 +3214,17 @@ impl Application for App {
         }]
     }
 
+    fn header_end(&self) -> Vec<Element<Message>> {
+        vec![
+            widget::button::icon(widget::icon::from_name("document-open-recent-symbolic"))
+                .on_press(Message::History)
+                .into(),
+            widget::button::icon(widget::icon::from_name("preferences-system-symbolic"))
+                .on_press(Message::ToggleContextPage(ContextPage::Settings))
+                .into(),
+        ]
+    }
+
     /// Creates a view after each update.
     fn view(&self) -> Element<Self::Message> {
         let cosmic_theme::Spacing {
@@ -688,7 +3234,20 @@ impl Application for App {
         } = self.core().system_theme().cosmic().spacing;
 
         let content: Element<_> = match &self.selected_opt {
+            _ if self.history_opt.is_some() => self.history(),
+            Some(selected) if selected.screenshot_full => self.screenshot_viewer(selected),
             Some(selected) => {
+                let source = selected.source();
+                let source_element: Element<_> = if selected.sources.len() > 1 {
+                    widget::dropdown(
+                        &selected.source_labels,
+                        Some(selected.source_i),
+                        Message::SelectSource,
+                    )
+                    .into()
+                } else {
+                    widget::text(source.info.origin_opt.as_deref().unwrap_or("")).into()
+                };
                 let mut column = widget::column::with_capacity(2)
                     // Hack to make room for scroll bar
                     .padding([0, space_xs, 0, 0])
@@ -697,29 +3256,99 @@ impl Application for App {
                 column = column.push(widget::button("Back").on_press(Message::SelectNone));
                 column = column.push(
                     widget::row::with_children(vec![
-                        widget::icon::icon(selected.icon.clone())
+                        widget::icon::icon(source.icon.clone())
                             .size(ICON_SIZE_DETAILS)
                             .into(),
                         widget::column::with_children(vec![
-                            widget::text(&selected.info.name).into(),
-                            widget::text(&selected.info.summary).into(),
-                            widget::text(&selected.id).into(),
+                            widget::text(&source.info.name).into(),
+                            widget::text(&source.info.summary).into(),
+                            widget::text(&source.id).into(),
                         ])
                         .into(),
                         widget::horizontal_space(Length::Fill).into(),
-                        widget::text(selected.info.origin_opt.as_deref().unwrap_or("")).into(),
-                        //TODO: buttons for status
+                        source_element,
+                        widget::row::with_children(self.operation_buttons(
+                            source.backend_name,
+                            &source.id,
+                            selected.branch_opt.as_ref(),
+                            &source.info,
+                        ))
+                        .spacing(space_xxs)
+                        .into(),
                     ])
                     .align_items(Alignment::Center)
                     .spacing(space_xxs),
                 );
-                for desktop_id in &selected.info.desktop_ids {
+                let installed_opt = self.installed_package(
+                    source.backend_name,
+                    &source.id,
+                    selected.branch_opt.as_ref(),
+                );
+                if let Some(warning) = installed_opt.and_then(eol_warning) {
+                    column = column.push(eol_badge(warning));
+                }
+                column = column.push(license_badge(&source.info, space_xxs));
+                if !source.info.screenshots.is_empty() {
+                    column = column.push(self.screenshots_section(selected));
+                }
+                if !source.info.description.is_empty() {
+                    column = column.push(description_column(&source.info.description, space_xxs));
+                }
+                if !source.info.urls.is_empty() {
+                    column = column.push(project_urls_column(&source.info.urls, space_xxs));
+                }
+                if let Some(package) = installed_opt {
+                    if self.capabilities(source.backend_name).holds {
+                        let backend_name = source.backend_name;
+                        let id = source.id.clone();
+                        let held = package.extra.get("held").map_or(false, |x| x == "true");
+                        let mut section = widget::settings::view_section(fl!("hold-updates")).add(
+                            widget::settings::item::builder(fl!("hold-app"))
+                                .toggler(held, move |hold| {
+                                    Message::Hold(backend_name, id.clone(), false, hold)
+                                }),
+                        );
+                        if let Some(runtime) = package.extra.get("runtime") {
+                            let id = source.id.clone();
+                            let runtime_held = package
+                                .extra
+                                .get("runtime_held")
+                                .map_or(false, |x| x == "true");
+                            section = section.add(
+                                widget::settings::item::builder(fl!(
+                                    "hold-runtime",
+                                    runtime = runtime.as_str()
+                                ))
+                                .toggler(runtime_held, move |hold| {
+                                    Message::Hold(backend_name, id.clone(), true, hold)
+                                }),
+                            );
+                        }
+                        column = column.push(section);
+                        if let Some((_, err)) = self
+                            .hold_error_opt
+                            .as_ref()
+                            .filter(|(id, _)| id == &source.id)
+                        {
+                            column =
+                                column.push(eol_badge(fl!("hold-failed", error = err.as_str())));
+                        }
+                    }
+                }
+                if let Some((branches_backend, branches_id, branches)) = &self.branches {
+                    if *branches_backend == source.backend_name && branches_id == &source.id {
+                        column = column.push(self.branches_section(source, branches));
+                    }
+                }
+                for desktop_id in &source.info.desktop_ids {
                     column = column.push(
                         widget::button(desktop_id.as_str())
                             .on_press(Message::OpenDesktopId(desktop_id.clone())),
                     );
                 }
-                //TODO: screenshots, description, releases, etc.
+                if !source.info.releases.is_empty() {
+                    column = column.push(self.releases_section(selected));
+                }
                 widget::scrollable(column).into()
             }
             None => match &self.search_results {
@@ -727,11 +3356,36 @@ impl Application for App {
                     //TODO: paging or dynamic load
                     let results_len = cmp::min(results.len(), 256);
 
-                    let mut column = widget::column::with_capacity(results_len + 1)
+                    let mut column = widget::column::with_capacity(results_len + 2)
                         // Hack to make room for scroll bar
                         .padding([0, space_xs, 0, 0])
                         .spacing(space_xxs)
                         .width(Length::Fill);
+                    let query = SearchQuery::parse(&self.search_input);
+                    if !query.filters.is_empty() {
+                        let mut filters_row =
+                            widget::row::with_capacity(query.filters.len()).spacing(space_xxs);
+                        for (filter_i, filter) in query.filters.iter().enumerate() {
+                            filters_row = filters_row.push(
+                                widget::button(
+                                    widget::row::with_children(vec![
+                                        widget::text(filter.label()).into(),
+                                        widget::icon::icon(
+                                            widget::icon::from_name("window-close-symbolic")
+                                                .size(16)
+                                                .handle(),
+                                        )
+                                        .size(16)
+                                        .into(),
+                                    ])
+                                    .align_items(Alignment::Center)
+                                    .spacing(space_xxs),
+                                )
+                                .on_press(Message::SearchFilterRemove(filter_i)),
+                            );
+                        }
+                        column = column.push(filters_row);
+                    }
                     //TODO: back button?
                     //TODO: translate
                     column = column.push(widget::text(format!(
@@ -740,52 +3394,140 @@ impl Application for App {
                         input
                     )));
                     for (result_i, result) in results.iter().take(results_len).enumerate() {
-                        column = column.push(
-                            widget::mouse_area(
-                                widget::row::with_children(vec![
-                                    widget::icon::icon(result.icon.clone())
-                                        .size(ICON_SIZE_LIST)
-                                        .into(),
-                                    widget::column::with_children(vec![
-                                        widget::text(&result.info.name).into(),
-                                        widget::text(&result.info.summary).into(),
-                                    ])
-                                    .into(),
-                                    widget::horizontal_space(Length::Fill).into(),
-                                    widget::text(result.info.origin_opt.as_deref().unwrap_or(""))
-                                        .into(),
-                                ])
-                                .align_items(Alignment::Center)
-                                .spacing(space_xxs),
-                            )
-                            .on_press(Message::SelectSearchResult(result_i)),
-                        );
+                        column = column.push(result_row(
+                            result,
+                            space_xxs,
+                            Message::SelectSearchResult(result_i),
+                        ));
                     }
                     widget::scrollable(column).into()
                 }
+                None if self.category_opt.is_some() => self.category_page(),
                 None => match &self.installed {
                     Some(installed) => {
-                        let mut column = widget::column::with_capacity(installed.len() + 1)
-                            .padding([0, space_xs, 0, 0])
-                            .spacing(space_xxs)
-                            .width(Length::Fill);
+                        let updates = self.updates.as_deref().unwrap_or(&[]);
+                        let mut column =
+                            widget::column::with_capacity(updates.len() + installed.len() + 2)
+                                .padding([0, space_xs, 0, 0])
+                                .spacing(space_xxs)
+                                .width(Length::Fill);
+                        column = column.push(self.categories_grid());
+                        for element in self.offline_rows(updates) {
+                            column = column.push(element);
+                        }
+                        if !updates.is_empty() {
+                            column = column.push(widget::text(fl!(
+                                "updates-available",
+                                count = updates.len()
+                            )));
+                            for (backend_name, package) in updates.iter() {
+                                let info_opt = self
+                                    .backends
+                                    .get(backend_name)
+                                    .and_then(|backend| backend.info(package).ok());
+                                let mut package_column = widget::column::with_children(vec![
+                                    widget::text(&package.name).into(),
+                                    widget::text(&package.summary).into(),
+                                ]);
+                                // Firmware updates include release notes
+                                if let Some(release_notes) = package.extra.get("release_notes") {
+                                    package_column =
+                                        package_column.push(widget::text(release_notes.as_str()));
+                                }
+                                let mut row = widget::row::with_children(vec![
+                                    widget::icon::icon(package.icon.clone())
+                                        .size(ICON_SIZE_LIST)
+                                        .into(),
+                                    package_column.into(),
+                                    widget::horizontal_space(Length::Fill).into(),
+                                    widget::text(&package.version).into(),
+                                ])
+                                .align_items(Alignment::Center)
+                                .spacing(space_xxs);
+                                // Offline updates are applied all at once, so they have no button
+                                if let Some(info) = info_opt
+                                    .as_ref()
+                                    .filter(|_| !self.offline_mode(backend_name))
+                                {
+                                    // The first button of an updatable package is update or progress
+                                    if let Some(button) = self
+                                        .operation_buttons(
+                                            *backend_name,
+                                            &package.id,
+                                            package.branch_opt().as_ref(),
+                                            info,
+                                        )
+                                        .into_iter()
+                                        .next()
+                                    {
+                                        row = row.push(button);
+                                    }
+                                }
+                                column = column.push(row);
+                            }
+                        }
+                        // Held packages are skipped by updates, list them so they are not forgotten
+                        let held: Vec<&Package> = installed
+                            .iter()
+                            .map(|(_, package)| package)
+                            .filter(|package| {
+                                package.extra.get("held").map_or(false, |x| x == "true")
+                                    || package
+                                        .extra
+                                        .get("runtime_held")
+                                        .map_or(false, |x| x == "true")
+                            })
+                            .collect();
+                        if !held.is_empty() {
+                            column =
+                                column.push(widget::text(fl!("updates-held", count = held.len())));
+                            for package in held {
+                                let held_text =
+                                    if package.extra.get("held").map_or(false, |x| x == "true") {
+                                        fl!("held-at", version = package.version.as_str())
+                                    } else {
+                                        fl!(
+                                            "runtime-held",
+                                            runtime = package
+                                                .extra
+                                                .get("runtime")
+                                                .map_or("", |x| x.as_str())
+                                        )
+                                    };
+                                column = column.push(
+                                    widget::row::with_children(vec![
+                                        widget::icon::icon(package.icon.clone())
+                                            .size(ICON_SIZE_LIST)
+                                            .into(),
+                                        widget::text(&package.name).into(),
+                                        widget::horizontal_space(Length::Fill).into(),
+                                        widget::text(held_text).into(),
+                                    ])
+                                    .align_items(Alignment::Center)
+                                    .spacing(space_xxs),
+                                );
+                            }
+                        }
                         //TODO: translate
                         column = column.push(widget::text(format!(
                             "{} installed applications",
                             installed.len(),
                         )));
                         for (installed_i, (_backend_i, package)) in installed.iter().enumerate() {
+                            let mut package_column = widget::column::with_children(vec![
+                                widget::text(&package.name).into(),
+                                widget::text(&package.summary).into(),
+                            ]);
+                            if let Some(warning) = eol_warning(package) {
+                                package_column = package_column.push(eol_badge(warning));
+                            }
                             column = column.push(
                                 widget::mouse_area(
                                     widget::row::with_children(vec![
                                         widget::icon::icon(package.icon.clone())
                                             .size(ICON_SIZE_LIST)
                                             .into(),
-                                        widget::column::with_children(vec![
-                                            widget::text(&package.name).into(),
-                                            widget::text(&package.summary).into(),
-                                        ])
-                                        .into(),
+                                        package_column.into(),
                                         widget::horizontal_space(Length::Fill).into(),
                                         widget::column::with_children(vec![
                                             widget::text(
@@ -793,6 +3535,13 @@ impl Application for App {
                                             )
                                             .into(),
                                             widget::text(&package.version).into(),
+                                            widget::text(
+                                                package
+                                                    .extra
+                                                    .get("branch")
+                                                    .map_or("", |x| x.as_str()),
+                                            )
+                                            .into(),
                                         ])
                                         .align_items(Alignment::End)
                                         .into(),
@@ -826,8 +3575,9 @@ impl Application for App {
     fn subscription(&self) -> Subscription<Self::Message> {
         struct ConfigSubscription;
         struct ThemeSubscription;
+        struct WatchSubscription;
 
-        Subscription::batch([
+        let mut subscriptions = vec![
             event::listen_with(|event, _status| match event {
                 Event::Keyboard(KeyEvent::KeyPressed {
                     key_code,
@@ -857,6 +3607,171 @@ impl Application for App {
                 }
                 Message::SystemThemeModeChange(update.config)
             }),
-        ])
+        ];
+
+        if !self.watch_paths.is_empty() {
+            // Restarted whenever the watched paths change
+            let watch_paths = self.watch_paths.clone();
+            subscriptions.push(subscription::channel(
+                (TypeId::of::<WatchSubscription>(), watch_paths.clone()),
+                16,
+                move |mut msg_tx| async move {
+                    let _ =
+                        tokio::task::spawn_blocking(move || watch(watch_paths, &mut msg_tx)).await;
+                    // Subscription futures must never complete
+                    futures::future::pending().await
+                },
+            ));
+        }
+
+        for (id, (op, _)) in self.pending_operations.iter() {
+            //TODO: use recipe?
+            let id = *id;
+            let backend = match self.backends.get(op.backend_name) {
+                Some(some) => some.clone(),
+                None => {
+                    log::warn!("failed to find backend {:?}", op.backend_name);
+                    continue;
+                }
+            };
+            let op = op.clone();
+            // Backends that do not list installed packages, like fwupd, have the current
+            // version and the version being installed in their updates
+            let update_opt = self.updates.as_ref().and_then(|updates| {
+                updates
+                    .iter()
+                    .find(|(backend_name, package)| {
+                        *backend_name == op.backend_name && package.id == op.package_id
+                    })
+                    .map(|(_, package)| package)
+            });
+            let update_version = update_opt
+                .filter(|_| op.kind == OperationKind::Update)
+                .map(|package| package.version.clone())
+                .unwrap_or_default();
+            // The installed version before the operation is already known
+            let version_before = self
+                .installed_package(op.backend_name, &op.package_id, op.branch_opt.as_ref())
+                .map(|package| package.version.clone())
+                .or_else(|| update_opt?.extra.get("current_version").cloned())
+                .unwrap_or_default();
+            subscriptions.push(subscription::channel(
+                id,
+                16,
+                move |mut msg_tx| async move {
+                    let mut progress_tx = msg_tx.clone();
+                    let msg = match tokio::task::spawn_blocking(move || {
+                        let result = backend
+                            .operation(
+                                &op,
+                                Box::new(move |progress| {
+                                    let _ = futures::executor::block_on(
+                                        progress_tx.send(Message::PendingProgress(id, progress)),
+                                    );
+                                }),
+                            )
+                            .map_err(|err| err.to_string());
+                        // Failed operations change nothing, offline updates are installed on
+                        // the next boot, and uninstalled packages have no version
+                        let version_after = match (op.kind, &result) {
+                            (_, Err(_)) => version_before.clone(),
+                            (OperationKind::OfflineUpdate | OperationKind::Uninstall, Ok(())) => {
+                                String::new()
+                            }
+                            _ => {
+                                let version =
+                                    history::installed_version(backend.as_ref(), &op.package_id);
+                                if version.is_empty() {
+                                    update_version
+                                } else {
+                                    version
+                                }
+                            }
+                        };
+                        let entry = HistoryEntry::new(
+                            op.backend_name,
+                            &op.package_id,
+                            op.kind,
+                            version_before,
+                            version_after,
+                            result.as_ref().err().cloned(),
+                        );
+                        if let Err(err) = history::append(&entry) {
+                            log::error!("failed to append to history: {}", err);
+                        }
+                        result
+                    })
+                    .await
+                    {
+                        Ok(Ok(())) => Message::PendingComplete(id),
+                        Ok(Err(err)) => Message::PendingError(id, err),
+                        Err(err) => Message::PendingError(id, err.to_string()),
+                    };
+                    let _ = msg_tx.send(msg).await;
+                    // Subscription futures must never complete
+                    futures::future::pending().await
+                },
+            ));
+        }
+
+        Subscription::batch(subscriptions)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn percent_decode_escapes() {
+        assert_eq!(
+            percent_decode("/home/user/My%20File.png").as_deref(),
+            Some("/home/user/My File.png")
+        );
+        assert_eq!(
+            percent_decode("/tmp/caf%C3%A9").as_deref(),
+            Some("/tmp/café")
+        );
+        assert_eq!(percent_decode("/tmp/100%25").as_deref(), Some("/tmp/100%"));
+        assert_eq!(
+            percent_decode("/plain/path").as_deref(),
+            Some("/plain/path")
+        );
+        // Truncated and non-hex escapes and invalid UTF-8 are rejected
+        assert_eq!(percent_decode("/tmp/%2"), None);
+        assert_eq!(percent_decode("/tmp/%zz"), None);
+        assert_eq!(percent_decode("/tmp/%ff"), None);
+    }
+
+    #[test]
+    fn input_mimetypes() {
+        assert_eq!(input_mimetype("image/png").as_deref(), Some("image/png"));
+        assert_eq!(input_mimetype(" Text/HTML ").as_deref(), Some("text/html"));
+        assert_eq!(
+            input_mimetype("application/vnd.oasis.opendocument.text").as_deref(),
+            Some("application/vnd.oasis.opendocument.text")
+        );
+        assert_eq!(
+            input_mimetype("x-content/audio-cdda").as_deref(),
+            Some("x-content/audio-cdda")
+        );
+        assert_eq!(
+            input_mimetype("inode/directory").as_deref(),
+            Some("inode/directory")
+        );
+        // Text with slashes that is not a MIME type is searched as text
+        assert_eq!(input_mimetype("AC/DC"), None);
+        assert_eq!(input_mimetype("input/output"), None);
+        assert_eq!(input_mimetype("image/"), None);
+        assert_eq!(input_mimetype("image/png editor"), None);
+        assert_eq!(input_mimetype("image/png/extra"), None);
+        assert_eq!(input_mimetype("photo editor"), None);
+        // Paths and file URIs that are not files have no MIME type
+        assert_eq!(input_mimetype("/nonexistent/cosmic-store/file.png"), None);
+        assert_eq!(
+            input_mimetype("file:///nonexistent/cosmic-store/My%20File.png"),
+            None
+        );
+        assert_eq!(input_mimetype("file:///nonexistent/%zz"), None);
     }
 }
@@ -0,0 +1,215 @@
+use std::{
+    error::Error,
+    ffi::CString,
+    fs,
+    io::{Read, Write},
+    os::unix::ffi::OsStrExt,
+    path::{Path, PathBuf},
+    time::{Instant, SystemTime},
+};
+
+/// Total size of cached media, least recently used files are removed above this
+const MEDIA_CACHE_SIZE: u64 = 256 * 1024 * 1024;
+/// Size of a single media file, larger downloads are refused
+const MEDIA_FILE_SIZE: u64 = 32 * 1024 * 1024;
+
+fn cache_dir() -> Result<PathBuf, Box<dyn Error>> {
+    Ok(dirs::cache_dir()
+        .ok_or("failed to find cache directory")?
+        .join("cosmic-store")
+        .join("media"))
+}
+
+/// File name for a URL, a FNV-1a hash keeping the extension so the image format is known
+fn file_name(url: &str) -> String {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in url.bytes() {
+        hash ^= u64::from(byte);
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    let extension = url
+        .rsplit('/')
+        .next()
+        .and_then(|x| x.split(['?', '#']).next())
+        .and_then(|x| x.rsplit_once('.'))
+        .map(|(_, extension)| extension)
+        .filter(|x| !x.is_empty() && x.len() <= 4 && x.chars().all(|c| c.is_ascii_alphanumeric()))
+        .unwrap_or("bin");
+    format!("{:016x}.{}", hash, extension)
+}
+
+/// Mark a cached file as recently used by setting its modified time to now
+fn touch(path: &Path) {
+    let c_path = match CString::new(path.as_os_str().as_bytes()) {
+        Ok(ok) => ok,
+        Err(_) => return,
+    };
+    // Passing null times sets access and modified time to the current time
+    if unsafe { libc::utime(c_path.as_ptr(), std::ptr::null()) } != 0 {
+        log::warn!("failed to update modified time of {:?}", path);
+    }
+}
+
+/// Remove least recently used files until the cache fits in its size
+fn evict(cache_dir: &Path) -> Result<(), Box<dyn Error>> {
+    let start = Instant::now();
+    let mut files = Vec::new();
+    let mut total = 0;
+    for entry_res in fs::read_dir(cache_dir)? {
+        let entry = entry_res?;
+        let metadata = entry.metadata()?;
+        if !metadata.is_file() {
+            continue;
+        }
+        total += metadata.len();
+        files.push((
+            metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH),
+            metadata.len(),
+            entry.path(),
+        ));
+    }
+    if total <= MEDIA_CACHE_SIZE {
+        return Ok(());
+    }
+    files.sort();
+    let mut removed = 0;
+    for (_modified, size, path) in files {
+        if total <= MEDIA_CACHE_SIZE {
+            break;
+        }
+        match fs::remove_file(&path) {
+            Ok(()) => {
+                total -= size;
+                removed += 1;
+            }
+            // Another download may have evicted it already
+            Err(err) => log::warn!("failed to remove cached media {:?}: {}", path, err),
+        }
+    }
+    let duration = start.elapsed();
+    log::info!("evicted {} cached media files in {:?}", removed, duration);
+    Ok(())
+}
+
+/// Read downloaded media, refusing files that are too large
+fn read_media<R: Read>(url: &str, reader: R) -> Result<Vec<u8>, Box<dyn Error>> {
+    let mut data = Vec::new();
+    reader.take(MEDIA_FILE_SIZE + 1).read_to_end(&mut data)?;
+    if data.len() as u64 > MEDIA_FILE_SIZE {
+        return Err(format!("media at {} is larger than {} bytes", url, MEDIA_FILE_SIZE).into());
+    }
+    Ok(data)
+}
+
+/// Path of media at a URL, downloading it if it is not already cached
+pub fn get(url: &str) -> Result<PathBuf, Box<dyn Error>> {
+    let cache_dir = cache_dir()?;
+    let path = cache_dir.join(file_name(url));
+    if path.is_file() {
+        touch(&path);
+        return Ok(path);
+    }
+
+    if !(url.starts_with("https://") || url.starts_with("http://")) {
+        return Err(format!("unsupported media URL {:?}", url).into());
+    }
+    fs::create_dir_all(&cache_dir)?;
+    log::info!("downloading {} to {:?}", url, path);
+    let data = read_media(url, ureq::get(url).call()?.into_reader())?;
+    atomicwrites::AtomicFile::new(&path, atomicwrites::OverwriteBehavior::AllowOverwrite)
+        .write(|file| file.write_all(&data))?;
+
+    if let Err(err) = evict(&cache_dir) {
+        log::warn!("failed to evict cached media: {}", err);
+    }
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use super::*;
+
+    const MB: u64 = 1024 * 1024;
+
+    /// Create a sparse file of a size, last used at a time in seconds from the unix epoch
+    fn cached_file(dir: &Path, name: &str, size: u64, modified: i64) -> PathBuf {
+        let path = dir.join(name);
+        fs::File::create(&path).unwrap().set_len(size).unwrap();
+        let c_path = CString::new(path.as_os_str().as_bytes()).unwrap();
+        let times = libc::utimbuf {
+            actime: modified as libc::time_t,
+            modtime: modified as libc::time_t,
+        };
+        assert_eq!(unsafe { libc::utime(c_path.as_ptr(), &times) }, 0);
+        path
+    }
+
+    fn temp_cache_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "cosmic-store-media-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn evict_least_recently_used() {
+        let dir = temp_cache_dir("evict");
+        let oldest = cached_file(&dir, "oldest.png", 100 * MB, 1000);
+        let newest = cached_file(&dir, "newest.png", 100 * MB, 4000);
+        let old = cached_file(&dir, "old.png", 100 * MB, 2000);
+        let new = cached_file(&dir, "new.png", 100 * MB, 3000);
+
+        // 400 MB is above the limit, removing the two oldest files brings it to 200 MB
+        evict(&dir).unwrap();
+        assert!(!oldest.exists());
+        assert!(!old.exists());
+        assert!(new.exists());
+        assert!(newest.exists());
+
+        // Touching a file makes it the most recently used
+        touch(&new);
+        let newer = cached_file(&dir, "newer.png", 120 * MB, 5000);
+        evict(&dir).unwrap();
+        assert!(!newest.exists());
+        assert!(new.exists());
+        assert!(newer.exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn evict_below_limit() {
+        let dir = temp_cache_dir("limit");
+        let a = cached_file(&dir, "a.png", 128 * MB, 1000);
+        let b = cached_file(&dir, "b.png", MEDIA_CACHE_SIZE - 128 * MB, 2000);
+        evict(&dir).unwrap();
+        assert!(a.exists());
+        assert!(b.exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn file_size_limit() {
+        let url = "https://example.com/video.webm";
+        let data = read_media(url, io::repeat(0).take(MEDIA_FILE_SIZE)).unwrap();
+        assert_eq!(data.len() as u64, MEDIA_FILE_SIZE);
+        assert!(read_media(url, io::repeat(0).take(MEDIA_FILE_SIZE + 1)).is_err());
+    }
+
+    #[test]
+    fn file_names() {
+        let name = file_name("https://example.com/screenshots/1.png?size=large#top");
+        assert!(name.ends_with(".png"));
+        assert_eq!(name.len(), 16 + 4);
+        assert_ne!(name, file_name("https://example.com/screenshots/2.png"));
+        assert!(file_name("https://example.com/media").ends_with(".bin"));
+        assert!(file_name("https://example.com/archive.tar.gz/").ends_with(".bin"));
+    }
+}
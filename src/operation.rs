@@ -0,0 +1,47 @@
+use std::sync::Arc;
+
+use crate::{backend::Branch, fl, AppInfo};
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum OperationKind {
+    Install,
+    Integrate,
+    OfflineUpdate,
+    Rebase,
+    Uninstall,
+    Update,
+}
+
+impl OperationKind {
+    pub fn label(&self) -> String {
+        match self {
+            Self::Install => fl!("install"),
+            Self::Integrate => fl!("integrate"),
+            Self::OfflineUpdate => fl!("offline-update"),
+            Self::Rebase => fl!("rebase-label"),
+            Self::Uninstall => fl!("uninstall"),
+            Self::Update => fl!("update"),
+        }
+    }
+
+    pub fn pending_text(&self, progress: i32) -> String {
+        match self {
+            Self::Install => fl!("installing", progress = progress),
+            Self::Integrate => fl!("integrating", progress = progress),
+            Self::OfflineUpdate => fl!("downloading-updates", progress = progress),
+            Self::Rebase => fl!("rebasing", progress = progress),
+            Self::Uninstall => fl!("uninstalling", progress = progress),
+            Self::Update => fl!("updating", progress = progress),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Operation {
+    pub kind: OperationKind,
+    pub backend_name: &'static str,
+    pub package_id: String,
+    pub info: Arc<AppInfo>,
+    /// Branch to operate on instead of the default one
+    pub branch_opt: Option<Branch>,
+}
@@ -0,0 +1,177 @@
+use appstream::xmltree;
+
+use crate::{
+    description::{child_elements, element_text},
+    fl,
+};
+
+/// Types of project URLs, in the order they are shown
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd, bitcode::Decode, bitcode::Encode)]
+pub enum ProjectUrlKind {
+    Homepage,
+    Help,
+    Faq,
+    BugTracker,
+    Contact,
+    VcsBrowser,
+    Contribute,
+    Translate,
+    Donation,
+}
+
+impl ProjectUrlKind {
+    /// Parse the type as written in appstream data
+    fn parse(kind: &str) -> Option<Self> {
+        match kind {
+            "homepage" => Some(Self::Homepage),
+            "help" => Some(Self::Help),
+            "faq" => Some(Self::Faq),
+            "bugtracker" => Some(Self::BugTracker),
+            "contact" => Some(Self::Contact),
+            "vcs-browser" => Some(Self::VcsBrowser),
+            "contribute" => Some(Self::Contribute),
+            "translate" => Some(Self::Translate),
+            "donation" => Some(Self::Donation),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> String {
+        match self {
+            Self::Homepage => fl!("url-homepage"),
+            Self::Help => fl!("url-help"),
+            Self::Faq => fl!("url-faq"),
+            Self::BugTracker => fl!("url-bugtracker"),
+            Self::Contact => fl!("url-contact"),
+            Self::VcsBrowser => fl!("url-vcs-browser"),
+            Self::Contribute => fl!("url-contribute"),
+            Self::Translate => fl!("url-translate"),
+            Self::Donation => fl!("url-donation"),
+        }
+    }
+
+    pub fn icon(&self) -> &'static str {
+        match self {
+            Self::Homepage => "web-browser-symbolic",
+            Self::Help | Self::Faq => "help-browser-symbolic",
+            Self::BugTracker => "dialog-warning-symbolic",
+            Self::Contact => "mail-send-symbolic",
+            Self::VcsBrowser => "text-x-generic-symbolic",
+            Self::Contribute => "system-users-symbolic",
+            Self::Translate => "preferences-desktop-locale-symbolic",
+            Self::Donation => "emblem-favorite-symbolic",
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, bitcode::Decode, bitcode::Encode)]
+pub struct ProjectUrl {
+    pub kind: ProjectUrlKind,
+    pub url: String,
+}
+
+fn push(urls: &mut Vec<ProjectUrl>, kind: &str, url: &str) {
+    let kind = match ProjectUrlKind::parse(kind) {
+        Some(some) => some,
+        None => return,
+    };
+    // Only web and mail links can be opened safely
+    let url = url.trim();
+    if !(url.starts_with("https://") || url.starts_with("http://") || url.starts_with("mailto:")) {
+        return;
+    }
+    // Keep only the first URL of each type
+    if !urls.iter().any(|x| x.kind == kind) {
+        urls.push(ProjectUrl {
+            kind,
+            url: url.to_string(),
+        });
+    }
+}
+
+/// Parse the project URLs of a component element
+pub fn from_component(component: &xmltree::Element) -> Vec<ProjectUrl> {
+    let mut urls = Vec::new();
+    for e in child_elements(component, "url") {
+        if let Some(kind) = e.attributes.get("type") {
+            let mut url = String::new();
+            element_text(e, &mut url);
+            push(&mut urls, kind, &url);
+        }
+    }
+    urls.sort_by_key(|url| url.kind);
+    urls
+}
+
+/// Parse the Url value of a YAML component, a mapping of type to URL
+pub fn from_yaml(value: &serde_yaml::Value) -> Vec<ProjectUrl> {
+    let mut urls = Vec::new();
+    for (kind, url) in value.as_mapping().into_iter().flatten() {
+        if let (Some(kind), Some(url)) = (kind.as_str(), url.as_str()) {
+            push(&mut urls, kind, url);
+        }
+    }
+    urls.sort_by_key(|url| url.kind);
+    urls
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn url(kind: ProjectUrlKind, url: &str) -> ProjectUrl {
+        ProjectUrl {
+            kind,
+            url: url.to_string(),
+        }
+    }
+
+    #[test]
+    fn component_urls() {
+        let component = xmltree::Element::parse(
+            r#"<component>
+                <url type="donation">https://example.com/donate</url>
+                <url type="bugtracker"> https://example.com/issues </url>
+                <url type="homepage">https://example.com</url>
+                <url type="homepage">https://example.org</url>
+                <url type="contact">mailto:dev@example.com</url>
+                <url type="help">file:///usr/share/help/index.html</url>
+                <url type="faq">javascript:alert(1)</url>
+                <url type="vcs-browser">http://git.example.com</url>
+                <url type="unknown">https://example.com/unknown</url>
+                <url>https://example.com/untyped</url>
+            </component>"#
+                .as_bytes(),
+        )
+        .unwrap();
+        assert_eq!(
+            from_component(&component),
+            [
+                url(ProjectUrlKind::Homepage, "https://example.com"),
+                url(ProjectUrlKind::BugTracker, "https://example.com/issues"),
+                url(ProjectUrlKind::Contact, "mailto:dev@example.com"),
+                url(ProjectUrlKind::VcsBrowser, "http://git.example.com"),
+                url(ProjectUrlKind::Donation, "https://example.com/donate"),
+            ]
+        );
+    }
+
+    #[test]
+    fn yaml_urls() {
+        let value: serde_yaml::Value = serde_yaml::from_str(
+            "translate: https://example.com/translate\n\
+             help: ftp://example.com/help\n\
+             homepage: https://example.com\n\
+             contribute: https://example.com/contribute\n",
+        )
+        .unwrap();
+        assert_eq!(
+            from_yaml(&value),
+            [
+                url(ProjectUrlKind::Homepage, "https://example.com"),
+                url(ProjectUrlKind::Contribute, "https://example.com/contribute"),
+                url(ProjectUrlKind::Translate, "https://example.com/translate"),
+            ]
+        );
+    }
+}
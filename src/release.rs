@@ -0,0 +1,261 @@
+use appstream::xmltree;
+use std::cmp::{self, Ordering};
+
+use crate::{
+    date::{civil_from_days, days_from_civil},
+    description::{child_elements, lang_score, normalize_locale},
+    Description,
+};
+
+#[derive(
+    Clone, Copy, Debug, Default, Eq, Ord, PartialEq, PartialOrd, bitcode::Decode, bitcode::Encode,
+)]
+pub enum ReleaseUrgency {
+    #[default]
+    Unknown,
+    Low,
+    Medium,
+    High,
+    Critical,
+}
+
+impl ReleaseUrgency {
+    fn parse(urgency: &str) -> Self {
+        match urgency {
+            "low" => Self::Low,
+            "medium" => Self::Medium,
+            "high" => Self::High,
+            "critical" => Self::Critical,
+            _ => Self::Unknown,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, bitcode::Decode, bitcode::Encode)]
+pub enum ReleaseKind {
+    #[default]
+    Stable,
+    Development,
+    Snapshot,
+}
+
+impl ReleaseKind {
+    fn parse(kind: &str) -> Self {
+        match kind {
+            "development" => Self::Development,
+            "snapshot" => Self::Snapshot,
+            _ => Self::Stable,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, bitcode::Decode, bitcode::Encode)]
+pub struct Release {
+    pub version: String,
+    /// Release time in seconds from the unix epoch
+    pub timestamp_opt: Option<u64>,
+    pub urgency: ReleaseUrgency,
+    pub kind: ReleaseKind,
+    pub description: Description,
+}
+
+impl Release {
+    /// Release date formatted as year-month-day
+    pub fn date(&self) -> Option<String> {
+        let (year, month, day) = civil_from_days((self.timestamp_opt? / 86400) as i64);
+        Some(format!("{:04}-{:02}-{:02}", year, month, day))
+    }
+}
+
+/// Parse a date like 2024-01-31 or 2024-01-31T12:00:00Z, ignoring the time
+fn parse_date(date: &str) -> Option<u64> {
+    let mut parts = date.get(..10)?.split('-');
+    let year = parts.next()?.parse().ok()?;
+    let month = parts.next()?.parse().ok()?;
+    let day = parts.next()?.parse().ok()?;
+    let days = days_from_civil(year, month, day);
+    u64::try_from(days).ok().map(|x| x * 86400)
+}
+
+/// Compare versions by numeric and alphabetic segments, like 1.10 > 1.9 and 1.0~rc1 < 1.0
+pub fn compare_versions(a: &str, b: &str) -> Ordering {
+    // Ignore epochs like 1:2.0 used by system packages
+    let strip_epoch = |x: &str| match x.split_once(':') {
+        Some((epoch, rest)) if epoch.chars().all(|c| c.is_ascii_digit()) => rest.to_string(),
+        _ => x.to_string(),
+    };
+    let (a, b) = (strip_epoch(a), strip_epoch(b));
+    let (mut a, mut b) = (a.as_str(), b.as_str());
+    loop {
+        a = a.trim_start_matches(|c: char| !c.is_ascii_alphanumeric() && c != '~');
+        b = b.trim_start_matches(|c: char| !c.is_ascii_alphanumeric() && c != '~');
+        // A tilde sorts before anything, even the end of the version
+        match (a.strip_prefix('~'), b.strip_prefix('~')) {
+            (Some(a_rest), Some(b_rest)) => {
+                a = a_rest;
+                b = b_rest;
+                continue;
+            }
+            (Some(_), None) => return Ordering::Less,
+            (None, Some(_)) => return Ordering::Greater,
+            (None, None) => {}
+        }
+        if a.is_empty() || b.is_empty() {
+            return a.len().cmp(&b.len());
+        }
+        let numeric = a.starts_with(|c: char| c.is_ascii_digit());
+        let segment_len = |x: &str| {
+            x.find(|c: char| {
+                if numeric {
+                    !c.is_ascii_digit()
+                } else {
+                    !c.is_ascii_alphabetic()
+                }
+            })
+            .unwrap_or(x.len())
+        };
+        let (a_segment, a_rest) = a.split_at(segment_len(a));
+        let (b_segment, b_rest) = b.split_at(segment_len(b));
+        if b_segment.is_empty() {
+            // Numeric segments are newer than alphabetic ones
+            return if numeric {
+                Ordering::Greater
+            } else {
+                Ordering::Less
+            };
+        }
+        let ordering = if numeric {
+            let a_segment = a_segment.trim_start_matches('0');
+            let b_segment = b_segment.trim_start_matches('0');
+            a_segment
+                .len()
+                .cmp(&b_segment.len())
+                .then_with(|| a_segment.cmp(b_segment))
+        } else {
+            a_segment.cmp(b_segment)
+        };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+        a = a_rest;
+        b = b_rest;
+    }
+}
+
+/// Parse the releases of a component element, newest first
+pub fn from_component(component: &xmltree::Element, locale: &str) -> Vec<Release> {
+    let mut releases = Vec::new();
+    for releases_e in child_elements(component, "releases") {
+        for e in child_elements(releases_e, "release") {
+            let version = match e.attributes.get("version") {
+                Some(some) => some.clone(),
+                None => continue,
+            };
+            let timestamp_opt = e
+                .attributes
+                .get("timestamp")
+                .and_then(|x| x.parse().ok())
+                .or_else(|| e.attributes.get("date").and_then(|x| parse_date(x)));
+            releases.push(Release {
+                version,
+                timestamp_opt,
+                urgency: e
+                    .attributes
+                    .get("urgency")
+                    .map_or(ReleaseUrgency::Unknown, |x| ReleaseUrgency::parse(x)),
+                kind: e
+                    .attributes
+                    .get("type")
+                    .map_or(ReleaseKind::Stable, |x| ReleaseKind::parse(x)),
+                description: Description::from_component(e, locale),
+            });
+        }
+    }
+    sort(&mut releases);
+    releases
+}
+
+/// Parse the Releases value of a YAML component, newest first
+pub fn from_yaml(value: &serde_yaml::Value, locale: &str) -> Vec<Release> {
+    let locale = normalize_locale(locale);
+    let mut releases = Vec::new();
+    for release_value in value.as_sequence().into_iter().flatten() {
+        let version = match release_value["version"].as_str() {
+            Some(some) => some.to_string(),
+            None => continue,
+        };
+        // Descriptions are a mapping of language to markup
+        let mut markup_opt: Option<(u8, &str)> = None;
+        for (lang, markup) in release_value["description"]
+            .as_mapping()
+            .into_iter()
+            .flatten()
+        {
+            let (lang, markup) = match (lang.as_str(), markup.as_str()) {
+                (Some(lang), Some(markup)) => (lang, markup),
+                _ => continue,
+            };
+            if let Some(score) = lang_score(Some(lang), &locale) {
+                if markup_opt.map_or(true, |(best, _)| score > best) {
+                    markup_opt = Some((score, markup));
+                }
+            }
+        }
+        releases.push(Release {
+            version,
+            timestamp_opt: release_value["unix-timestamp"]
+                .as_u64()
+                .or_else(|| release_value["date"].as_str().and_then(parse_date)),
+            urgency: release_value["urgency"]
+                .as_str()
+                .map_or(ReleaseUrgency::Unknown, ReleaseUrgency::parse),
+            kind: release_value["type"]
+                .as_str()
+                .map_or(ReleaseKind::Stable, ReleaseKind::parse),
+            description: markup_opt.map_or_else(Description::default, |(_, markup)| {
+                Description::from_markup(markup)
+            }),
+        });
+    }
+    sort(&mut releases);
+    releases
+}
+
+fn sort(releases: &mut [Release]) {
+    // Releases should already be newest first, but not all catalogs follow that
+    releases.sort_by_key(|release| cmp::Reverse(release.timestamp_opt));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn versions() {
+        assert_eq!(compare_versions("1.10", "1.9"), Ordering::Greater);
+        assert_eq!(compare_versions("1.9", "1.10"), Ordering::Less);
+        assert_eq!(compare_versions("1.0~rc1", "1.0"), Ordering::Less);
+        assert_eq!(compare_versions("1.0~rc1", "1.0~rc2"), Ordering::Less);
+        assert_eq!(compare_versions("1:2.0", "2.0"), Ordering::Equal);
+        assert_eq!(compare_versions("1.0", "1.0.1"), Ordering::Less);
+        assert_eq!(compare_versions("1.01", "1.1"), Ordering::Equal);
+        assert_eq!(compare_versions("1.0a", "1.0"), Ordering::Greater);
+        assert_eq!(compare_versions("2.0", "2.0b"), Ordering::Less);
+        assert_eq!(compare_versions("1.2.3", "1.2.3"), Ordering::Equal);
+    }
+
+    #[test]
+    fn dates() {
+        assert_eq!(parse_date("2024-01-31"), Some(19753 * 86400));
+        assert_eq!(parse_date("2024-01-31T12:00:00Z"), Some(19753 * 86400));
+        assert_eq!(parse_date("2024-01"), None);
+        let release = Release {
+            version: "1.0".to_string(),
+            timestamp_opt: Some(19753 * 86400 + 3600),
+            urgency: ReleaseUrgency::Unknown,
+            kind: ReleaseKind::Stable,
+            description: Description::default(),
+        };
+        assert_eq!(release.date().as_deref(), Some("2024-01-31"));
+    }
+}
@@ -0,0 +1,430 @@
+use appstream::xmltree;
+use std::path::{Path, PathBuf};
+
+use crate::description::{
+    child_elements, element_lang, element_text, lang_score, normalize_locale, translated_child,
+};
+
+#[derive(Clone, Debug, Eq, PartialEq, bitcode::Decode, bitcode::Encode)]
+pub struct ScreenshotImage {
+    pub url: String,
+    /// Copy shipped in a local catalog media directory, preferred over downloading the URL
+    pub path_opt: Option<String>,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, bitcode::Decode, bitcode::Encode)]
+pub struct ScreenshotVideo {
+    pub url: String,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, bitcode::Decode, bitcode::Encode)]
+pub struct Screenshot {
+    pub default: bool,
+    pub caption_opt: Option<String>,
+    pub source_opt: Option<ScreenshotImage>,
+    pub thumbnails: Vec<ScreenshotImage>,
+    pub videos: Vec<ScreenshotVideo>,
+}
+
+impl Screenshot {
+    /// Smallest image at least as wide as requested, or the largest one
+    pub fn image(&self, width: u32) -> Option<&ScreenshotImage> {
+        let mut images: Vec<_> = self.thumbnails.iter().chain(&self.source_opt).collect();
+        // Images without a size are usually the source, so sort them last
+        images.sort_by_key(|image| image.width.unwrap_or(u32::MAX));
+        images
+            .iter()
+            .find(|image| image.width.map_or(true, |x| x >= width))
+            .or(images.last())
+            .copied()
+    }
+
+    /// Full size image for the viewer
+    pub fn source(&self) -> Option<&ScreenshotImage> {
+        self.source_opt
+            .as_ref()
+            .or_else(|| self.thumbnails.iter().max_by_key(|image| image.width))
+    }
+}
+
+/// Where relative media URLs of a catalog are found
+#[derive(Debug, Default)]
+pub struct MediaLocation {
+    /// Prefix of relative URLs, such as MediaBaseUrl in YAML catalogs
+    pub base_url_opt: Option<String>,
+    /// Local media directories shipped with the catalog
+    pub dirs: Vec<PathBuf>,
+}
+
+impl MediaLocation {
+    /// Media location for a catalog file in a swcatalog or app-info directory
+    pub fn new(catalog_path: &Path, origin_opt: Option<&str>, base_url_opt: Option<&str>) -> Self {
+        let mut dirs = Vec::new();
+        if let Some(media_dir) = catalog_path
+            .parent()
+            .and_then(|x| x.parent())
+            .map(|x| x.join("media"))
+        {
+            if let Some(origin) = origin_opt {
+                dirs.push(media_dir.join(origin));
+            }
+            dirs.push(media_dir);
+        }
+        dirs.retain(|dir| dir.is_dir());
+        Self {
+            base_url_opt: base_url_opt.map(|x| x.trim_end_matches('/').to_string()),
+            dirs,
+        }
+    }
+
+    fn image(&self, url: &str, width: Option<u32>, height: Option<u32>) -> ScreenshotImage {
+        let url = url.trim();
+        if url.contains("://") {
+            return ScreenshotImage {
+                url: url.to_string(),
+                path_opt: url.strip_prefix("file://").map(|x| x.to_string()),
+                width,
+                height,
+            };
+        }
+        let relative = url.trim_start_matches('/');
+        ScreenshotImage {
+            url: match &self.base_url_opt {
+                Some(base_url) => format!("{}/{}", base_url, relative),
+                None => url.to_string(),
+            },
+            path_opt: self
+                .dirs
+                .iter()
+                .map(|dir| dir.join(relative))
+                .find(|path| path.is_file())
+                .and_then(|path| Some(path.to_str()?.to_string())),
+            width,
+            height,
+        }
+    }
+}
+
+fn attribute_u32(e: &xmltree::Element, name: &str) -> Option<u32> {
+    e.attributes.get(name).and_then(|x| x.parse().ok())
+}
+
+fn text(e: &xmltree::Element) -> String {
+    let mut text = String::new();
+    element_text(e, &mut text);
+    text.trim().to_string()
+}
+
+/// Parse the screenshots of a component element, with the default screenshot first
+pub fn from_component(
+    component: &xmltree::Element,
+    locale: &str,
+    media: &MediaLocation,
+) -> Vec<Screenshot> {
+    let locale = normalize_locale(locale);
+    let mut screenshots = Vec::new();
+    for screenshots_e in child_elements(component, "screenshots") {
+        for e in child_elements(screenshots_e, "screenshot") {
+            let mut screenshot = Screenshot {
+                default: e.attributes.get("type").map_or(false, |x| x == "default"),
+                caption_opt: translated_child(e, "caption", &locale)
+                    .map(text)
+                    .filter(|x| !x.is_empty()),
+                source_opt: None,
+                thumbnails: Vec::new(),
+                videos: Vec::new(),
+            };
+            for image_e in child_elements(e, "image") {
+                // Images may also be translated, skip other languages
+                if lang_score(element_lang(image_e), &locale).is_none() {
+                    continue;
+                }
+                let image = media.image(
+                    &text(image_e),
+                    attribute_u32(image_e, "width"),
+                    attribute_u32(image_e, "height"),
+                );
+                match image_e.attributes.get("type").map(|x| x.as_str()) {
+                    Some("thumbnail") => screenshot.thumbnails.push(image),
+                    _ => screenshot.source_opt = Some(image),
+                }
+            }
+            for video_e in child_elements(e, "video") {
+                let image = media.image(&text(video_e), None, None);
+                screenshot.videos.push(ScreenshotVideo {
+                    url: image.url,
+                    width: attribute_u32(video_e, "width"),
+                    height: attribute_u32(video_e, "height"),
+                });
+            }
+            if screenshot.source_opt.is_some() || !screenshot.thumbnails.is_empty() {
+                screenshots.push(screenshot);
+            }
+        }
+    }
+    screenshots.sort_by_key(|screenshot| !screenshot.default);
+    screenshots
+}
+
+fn yaml_u32(value: &serde_yaml::Value, key: &str) -> Option<u32> {
+    value[key].as_u64().and_then(|x| x.try_into().ok())
+}
+
+fn yaml_image(value: &serde_yaml::Value, media: &MediaLocation) -> Option<ScreenshotImage> {
+    Some(media.image(
+        value["url"].as_str()?,
+        yaml_u32(value, "width"),
+        yaml_u32(value, "height"),
+    ))
+}
+
+/// Parse the Screenshots value of a YAML component, with the default screenshot first
+pub fn from_yaml(
+    value: &serde_yaml::Value,
+    locale: &str,
+    media: &MediaLocation,
+) -> Vec<Screenshot> {
+    let locale = normalize_locale(locale);
+    let mut screenshots = Vec::new();
+    for screenshot_value in value.as_sequence().into_iter().flatten() {
+        // Captions are a mapping of language to text
+        let mut caption_opt: Option<(u8, String)> = None;
+        for (lang, caption) in screenshot_value["caption"]
+            .as_mapping()
+            .into_iter()
+            .flatten()
+        {
+            let (lang, caption) = match (lang.as_str(), caption.as_str()) {
+                (Some(lang), Some(caption)) => (lang, caption),
+                _ => continue,
+            };
+            if let Some(score) = lang_score(Some(lang), &locale) {
+                if caption_opt.as_ref().map_or(true, |(best, _)| score > *best) {
+                    caption_opt = Some((score, caption.trim().to_string()));
+                }
+            }
+        }
+        let screenshot = Screenshot {
+            default: screenshot_value["default"].as_bool().unwrap_or(false),
+            caption_opt: caption_opt
+                .map(|(_, caption)| caption)
+                .filter(|x| !x.is_empty()),
+            source_opt: yaml_image(&screenshot_value["source-image"], media),
+            thumbnails: screenshot_value["thumbnails"]
+                .as_sequence()
+                .into_iter()
+                .flatten()
+                .filter_map(|x| yaml_image(x, media))
+                .collect(),
+            videos: screenshot_value["videos"]
+                .as_sequence()
+                .into_iter()
+                .flatten()
+                .filter_map(|x| {
+                    Some(ScreenshotVideo {
+                        url: yaml_image(x, media)?.url,
+                        width: yaml_u32(x, "width"),
+                        height: yaml_u32(x, "height"),
+                    })
+                })
+                .collect(),
+        };
+        if screenshot.source_opt.is_some() || !screenshot.thumbnails.is_empty() {
+            screenshots.push(screenshot);
+        }
+    }
+    screenshots.sort_by_key(|screenshot| !screenshot.default);
+    screenshots
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    fn screenshots(xml: &str, locale: &str, media: &MediaLocation) -> Vec<Screenshot> {
+        let component = xmltree::Element::parse(xml.as_bytes()).unwrap();
+        from_component(&component, locale, media)
+    }
+
+    fn image(url: &str, width: Option<u32>) -> ScreenshotImage {
+        ScreenshotImage {
+            url: url.to_string(),
+            path_opt: None,
+            width,
+            height: width.map(|x| x * 9 / 16),
+        }
+    }
+
+    #[test]
+    fn default_first() {
+        let screenshots = screenshots(
+            r#"<component>
+              <screenshots>
+                <screenshot><image>https://example.com/second.png</image></screenshot>
+                <screenshot type="default"><image>https://example.com/first.png</image></screenshot>
+                <screenshot><caption>No images</caption></screenshot>
+              </screenshots>
+            </component>"#,
+            "en",
+            &MediaLocation::default(),
+        );
+        assert_eq!(screenshots.len(), 2);
+        assert!(screenshots[0].default);
+        assert_eq!(
+            screenshots[0].source().map(|x| x.url.as_str()),
+            Some("https://example.com/first.png")
+        );
+        assert!(!screenshots[1].default);
+    }
+
+    #[test]
+    fn thumbnails_and_source() {
+        let screenshots = screenshots(
+            r#"<component>
+              <screenshots>
+                <screenshot type="default">
+                  <caption>Main window</caption>
+                  <caption xml:lang="de">Hauptfenster</caption>
+                  <image type="source">https://example.com/source.png</image>
+                  <image type="thumbnail" width="624" height="351">https://example.com/624.png</image>
+                  <image type="thumbnail" width="224" height="126">https://example.com/224.png</image>
+                  <image type="thumbnail" width="1248" height="702">https://example.com/1248.png</image>
+                </screenshot>
+              </screenshots>
+            </component>"#,
+            "de_DE.UTF-8",
+            &MediaLocation::default(),
+        );
+        let screenshot = &screenshots[0];
+        assert_eq!(screenshot.caption_opt.as_deref(), Some("Hauptfenster"));
+        assert_eq!(
+            screenshot.source_opt,
+            Some(image("https://example.com/source.png", None))
+        );
+        assert_eq!(screenshot.thumbnails.len(), 3);
+        // The smallest image that is wide enough is shown, the source is the largest
+        assert_eq!(
+            screenshot.image(600),
+            Some(&image("https://example.com/624.png", Some(624)))
+        );
+        assert_eq!(
+            screenshot.image(200),
+            Some(&image("https://example.com/224.png", Some(224)))
+        );
+        assert_eq!(
+            screenshot.image(2000),
+            Some(&image("https://example.com/source.png", None))
+        );
+        assert_eq!(
+            screenshot.source(),
+            Some(&image("https://example.com/source.png", None))
+        );
+    }
+
+    #[test]
+    fn thumbnails_only() {
+        let screenshots = screenshots(
+            r#"<component>
+              <screenshots>
+                <screenshot>
+                  <caption xml:lang="de">Hauptfenster</caption>
+                  <image type="thumbnail" width="224" height="126">https://example.com/224.png</image>
+                  <image type="thumbnail" width="624" height="351">https://example.com/624.png</image>
+                </screenshot>
+              </screenshots>
+            </component>"#,
+            "en_US",
+            &MediaLocation::default(),
+        );
+        let screenshot = &screenshots[0];
+        // Captions in other languages are not shown
+        assert_eq!(screenshot.caption_opt, None);
+        assert_eq!(screenshot.source_opt, None);
+        assert_eq!(
+            screenshot.source(),
+            Some(&image("https://example.com/624.png", Some(624)))
+        );
+        assert_eq!(
+            screenshot.image(1000),
+            Some(&image("https://example.com/624.png", Some(624)))
+        );
+    }
+
+    #[test]
+    fn videos() {
+        let screenshots = screenshots(
+            r#"<component>
+              <screenshots>
+                <screenshot>
+                  <image>https://example.com/poster.png</image>
+                  <video container="webm" codec="av1" width="1600" height="900">https://example.com/demo.webm</video>
+                </screenshot>
+              </screenshots>
+            </component>"#,
+            "en",
+            &MediaLocation::default(),
+        );
+        assert_eq!(
+            screenshots[0].videos,
+            [ScreenshotVideo {
+                url: "https://example.com/demo.webm".to_string(),
+                width: Some(1600),
+                height: Some(900),
+            }]
+        );
+    }
+
+    #[test]
+    fn local_media() {
+        let root =
+            std::env::temp_dir().join(format!("cosmic-store-screenshot-{}", std::process::id()));
+        let catalog_path = root.join("swcatalog").join("xml").join("flathub.xml.gz");
+        let media_dir = root.join("swcatalog").join("media").join("flathub");
+        fs::create_dir_all(media_dir.join("org.gimp.GIMP")).unwrap();
+        fs::create_dir_all(catalog_path.parent().unwrap()).unwrap();
+        fs::write(media_dir.join("org.gimp.GIMP").join("1.png"), b"").unwrap();
+
+        let media = MediaLocation::new(
+            &catalog_path,
+            Some("flathub"),
+            Some("https://dl.flathub.org/media/"),
+        );
+        let screenshots = screenshots(
+            r#"<component>
+              <screenshots>
+                <screenshot>
+                  <image>org.gimp.GIMP/1.png</image>
+                  <image type="thumbnail" width="224">/org.gimp.GIMP/2.png</image>
+                </screenshot>
+              </screenshots>
+            </component>"#,
+            "en",
+            &media,
+        );
+        let screenshot = &screenshots[0];
+        // Relative URLs use the base URL, and a local copy if the catalog has one
+        let source = screenshot.source_opt.as_ref().unwrap();
+        assert_eq!(
+            source.url,
+            "https://dl.flathub.org/media/org.gimp.GIMP/1.png"
+        );
+        assert_eq!(
+            source.path_opt.as_deref().map(Path::new),
+            Some(media_dir.join("org.gimp.GIMP").join("1.png").as_path())
+        );
+        let thumbnail = &screenshot.thumbnails[0];
+        assert_eq!(
+            thumbnail.url,
+            "https://dl.flathub.org/media/org.gimp.GIMP/2.png"
+        );
+        assert_eq!(thumbnail.path_opt, None);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,271 @@
+use crate::{AppInfo, AppKind, LicenseKind};
+
+/// A filter written as key:value in the search input
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum SearchFilter {
+    Backend(String),
+    Category(String),
+    Installed(bool),
+    Kind(AppKind),
+    License(LicenseKind),
+    Origin(String),
+}
+
+impl SearchFilter {
+    fn parse(key: &str, value: &str) -> Option<Self> {
+        if value.is_empty() {
+            return None;
+        }
+        match key {
+            "backend" => Some(Self::Backend(value.to_string())),
+            "category" => Some(Self::Category(value.to_string())),
+            "installed" => match value {
+                "yes" | "true" => Some(Self::Installed(true)),
+                "no" | "false" => Some(Self::Installed(false)),
+                _ => None,
+            },
+            "kind" => match value {
+                "app" | "desktop-application" => Some(Self::Kind(AppKind::DesktopApplication)),
+                "addon" => Some(Self::Kind(AppKind::Addon)),
+                _ => None,
+            },
+            "license" => LicenseKind::parse(value).map(Self::License),
+            "origin" => Some(Self::Origin(value.to_string())),
+            _ => None,
+        }
+    }
+
+    /// Filter as written in the search input
+    pub fn label(&self) -> String {
+        match self {
+            Self::Backend(backend) => format!("backend:{}", backend),
+            Self::Category(category) => format!("category:{}", category),
+            Self::Installed(true) => "installed:yes".to_string(),
+            Self::Installed(false) => "installed:no".to_string(),
+            Self::Kind(AppKind::DesktopApplication) => "kind:app".to_string(),
+            Self::Kind(AppKind::Addon) => "kind:addon".to_string(),
+            Self::License(kind) => format!("license:{}", kind.id()),
+            Self::Origin(origin) => format!("origin:{}", origin),
+        }
+    }
+
+    /// Check if an app from a backend matches, `installed` is only used by installed filters
+    pub fn matches(&self, backend_name: &str, info: &AppInfo, installed: bool) -> bool {
+        match self {
+            // A backend family like flatpak matches flatpak-user and flatpak-system
+            Self::Backend(backend) => {
+                let backend_name = backend_name.to_lowercase();
+                let backend = backend.to_lowercase();
+                backend_name == backend || backend_name.starts_with(&format!("{}-", backend))
+            }
+            Self::Category(category) => info
+                .categories
+                .iter()
+                .any(|x| x.eq_ignore_ascii_case(category)),
+            Self::Installed(value) => installed == *value,
+            Self::Kind(kind) => info.kind == *kind,
+            Self::License(kind) => info.license_kind == *kind,
+            Self::Origin(origin) => info
+                .origin_opt
+                .as_deref()
+                .map_or(false, |x| x.eq_ignore_ascii_case(origin)),
+        }
+    }
+}
+
+/// A part of the search input, keeping its original text so filters can be removed
+#[derive(Clone, Debug)]
+enum SearchToken {
+    Filter(SearchFilter),
+    Phrase(String),
+    Word(String),
+}
+
+/// Split the search input into words, quoted phrases, and filters
+fn tokens(input: &str) -> Vec<(SearchToken, String)> {
+    let mut tokens = Vec::new();
+    let mut chars = input.chars().peekable();
+    loop {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        let c = match chars.next() {
+            Some(some) => some,
+            None => break,
+        };
+        if c == '"' {
+            let mut phrase = String::new();
+            // An unterminated quote continues to the end of the input
+            for c in chars.by_ref() {
+                if c == '"' {
+                    break;
+                }
+                phrase.push(c);
+            }
+            let raw = format!("\"{}\"", phrase);
+            let phrase = phrase.trim().to_string();
+            if !phrase.is_empty() {
+                tokens.push((SearchToken::Phrase(phrase), raw));
+            }
+            continue;
+        }
+        let mut word = String::from(c);
+        while let Some(c) = chars.next_if(|c| !c.is_whitespace()) {
+            word.push(c);
+        }
+        let filter_opt = word
+            .split_once(':')
+            .and_then(|(key, value)| SearchFilter::parse(&key.to_lowercase(), value));
+        match filter_opt {
+            Some(filter) => tokens.push((SearchToken::Filter(filter), word)),
+            None => tokens.push((SearchToken::Word(word.clone()), word)),
+        }
+    }
+    tokens
+}
+
+/// Search input with filters like `origin:flathub` and quoted phrases
+#[derive(Clone, Debug, Default)]
+pub struct SearchQuery {
+    /// Words and phrases that are matched against apps, without filters or quotes
+    pub text: String,
+    /// Words outside of quotes, which may match with typos
+    pub words: Vec<String>,
+    /// Lowercase quoted phrases, which must be found exactly
+    pub phrases: Vec<String>,
+    pub filters: Vec<SearchFilter>,
+}
+
+impl SearchQuery {
+    pub fn parse(input: &str) -> Self {
+        let mut query = Self::default();
+        let mut text_parts = Vec::new();
+        for (token, _raw) in tokens(input) {
+            match token {
+                SearchToken::Filter(filter) => query.filters.push(filter),
+                SearchToken::Phrase(phrase) => {
+                    query.phrases.push(phrase.to_lowercase());
+                    text_parts.push(phrase);
+                }
+                SearchToken::Word(word) => {
+                    query.words.push(word.clone());
+                    text_parts.push(word);
+                }
+            }
+        }
+        query.text = text_parts.join(" ");
+        query
+    }
+
+    /// Search input with the filter at an index removed
+    pub fn remove_filter(input: &str, filter_i: usize) -> String {
+        let mut filter_count = 0;
+        let mut parts = Vec::new();
+        for (token, raw) in tokens(input) {
+            if let SearchToken::Filter(_) = token {
+                filter_count += 1;
+                if filter_count - 1 == filter_i {
+                    continue;
+                }
+            }
+            parts.push(raw);
+        }
+        parts.join(" ")
+    }
+
+    /// Check if an app from a backend matches all filters, phrases are matched when weighing
+    pub fn matches(&self, backend_name: &str, info: &AppInfo, installed: bool) -> bool {
+        self.filters
+            .iter()
+            .all(|filter| filter.matches(backend_name, info, installed))
+            // Addons are only shown when searching for them
+            && (info.kind == AppKind::DesktopApplication
+                || self.filters.contains(&SearchFilter::Kind(AppKind::Addon)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let query = SearchQuery::parse(r#"  photo "Image Editor"  origin:flathub License:free"#);
+        assert_eq!(query.text, "photo Image Editor");
+        assert_eq!(query.words, ["photo"]);
+        assert_eq!(query.phrases, ["image editor"]);
+        assert_eq!(
+            query.filters,
+            [
+                SearchFilter::Origin("flathub".to_string()),
+                SearchFilter::License(LicenseKind::Free),
+            ]
+        );
+    }
+
+    #[test]
+    fn invalid_filters_are_words() {
+        let query = SearchQuery::parse("installed:maybe color: foo:bar");
+        assert_eq!(query.words, ["installed:maybe", "color:", "foo:bar"]);
+        assert!(query.filters.is_empty());
+    }
+
+    #[test]
+    fn unterminated_phrase() {
+        let query = SearchQuery::parse(r#"kind:addon "text editor"#);
+        assert_eq!(query.phrases, ["text editor"]);
+        assert_eq!(query.filters, [SearchFilter::Kind(AppKind::Addon)]);
+        assert!(SearchQuery::parse(r#""  ""#).phrases.is_empty());
+    }
+
+    #[test]
+    fn remove_filter() {
+        let input = r#"origin:flathub "web  browser" installed:yes fast"#;
+        assert_eq!(
+            SearchQuery::remove_filter(input, 0),
+            r#""web  browser" installed:yes fast"#
+        );
+        assert_eq!(
+            SearchQuery::remove_filter(input, 1),
+            r#"origin:flathub "web  browser" fast"#
+        );
+        assert_eq!(
+            SearchQuery::remove_filter(input, 2),
+            r#"origin:flathub "web  browser" installed:yes fast"#
+        );
+    }
+
+    #[test]
+    fn matches() {
+        let info = AppInfo {
+            origin_opt: Some("flathub".to_string()),
+            name: "GIMP".to_string(),
+            summary: "Create images and edit photographs".to_string(),
+            categories: vec!["Graphics".to_string()],
+            ..Default::default()
+        };
+        let matches = |input: &str, installed: bool| {
+            SearchQuery::parse(input).matches("flatpak-user", &info, installed)
+        };
+        assert!(matches("origin:Flathub", false));
+        assert!(matches("category:graphics", false));
+        assert!(!matches("category:video", false));
+        assert!(matches("backend:flatpak-user installed:yes", true));
+        assert!(!matches("installed:yes", false));
+        assert!(!matches("backend:packagekit", false));
+        // Backend families match every backend in them, but not other backends
+        assert!(matches("backend:Flatpak", false));
+        assert!(!matches("backend:flat", false));
+        assert!(!matches("backend:flatpak-system", false));
+        // Phrases are matched when weighing results, not by filters
+        assert!(matches("\"video editor\"", false));
+        assert!(!matches("license:free", false));
+        assert!(matches("license:unknown", false));
+
+        let addon = AppInfo {
+            kind: AppKind::Addon,
+            name: "GIMP Plugin".to_string(),
+            ..Default::default()
+        };
+        assert!(!SearchQuery::parse("gimp").matches("flatpak-user", &addon, false));
+        assert!(SearchQuery::parse("gimp kind:addon").matches("flatpak-user", &addon, false));
+    }
+}